@@ -0,0 +1,124 @@
+//! A flint glass prism casting a rainbow onto the floor (see `Dispersive`),
+//! rendered in spectral mode.
+//!
+//! The light is a thin, bright bar rather than a point light: the rainbow is a
+//! caustic, which paths only find by hitting the light by chance after going
+//! through the prism.
+//!
+//! Usage:
+//!
+//!    cargo run --release --example prism -- [--samples 1000] [--rgb]
+//!        [--output prism.ppm]
+
+use rrt_core::{
+    camera::Camera,
+    color::Color,
+    environment::Constant,
+    material::{Dispersive, Lambertian, Principled},
+    object::Object,
+    progressive::ProgressiveRenderer,
+    scene::Scene,
+    shape::{Cuboid, Triangle},
+    vec3::Vec3,
+};
+
+/// Adds a prism with the given triangular cross-section (counterclockwise
+/// seen from +z), extruded from z = `back` to z = `front`.
+fn add_prism(scene: &mut Scene, corners: [Vec3; 3], back: f64, front: f64) {
+    let at = |i: usize, z: f64| Vec3::new(corners[i % 3].x, corners[i % 3].y, z);
+    let mut triangles = vec![
+        Triangle::new(at(0, front), at(1, front), at(2, front)),
+        Triangle::new(at(0, back), at(2, back), at(1, back)),
+    ];
+    for i in 0..3 {
+        triangles.push(Triangle::new(
+            at(i, back),
+            at(i + 1, back),
+            at(i + 1, front),
+        ));
+        triangles.push(Triangle::new(at(i, back), at(i + 1, front), at(i, front)));
+    }
+    for triangle in triangles {
+        scene.add_object(Object::new(
+            Box::new(triangle),
+            Box::new(Dispersive::flint(0.0)),
+        ));
+    }
+}
+
+fn main() {
+    let mut samples = 1000;
+    let mut spectral = true;
+    let mut output = "./prism.ppm".to_string();
+    let mut args = std::env::args().skip(1);
+    while let Some(flag) = args.next() {
+        match flag.as_str() {
+            "--samples" => samples = args.next().unwrap().parse().unwrap(),
+            "--rgb" => spectral = false,
+            "--output" => output = args.next().unwrap(),
+            _ => panic!("unknown argument {flag}"),
+        }
+    }
+
+    let mut scene = Scene::new();
+    scene.set_environment(Box::new(Constant::new(Color::BLACK)));
+    scene.add_object(Object::new(
+        Box::new(Cuboid::new(
+            Vec3::new(-4.0, -0.1, -10.0),
+            Vec3::new(4.0, 0.0, 10.0),
+        )),
+        Box::new(Lambertian::new(Color::WHITE * 0.8)),
+    ));
+    // A small equilateral prism floating above the floor, so that the rainbow
+    // has room to spread out before it hits the floor. The light of each
+    // wavelength leaves the prism as a beam about as wide as the prism, so a
+    // small prism keeps the beams from overlapping too much.
+    let (bottom, side) = (1.2, 0.15);
+    let height = side * 3f64.sqrt() / 2.0;
+    add_prism(
+        &mut scene,
+        [
+            Vec3::new(-side / 2.0, bottom, 0.0),
+            Vec3::new(side / 2.0, bottom, 0.0),
+            Vec3::new(0.0, bottom + height, 0.0),
+        ],
+        -10.0,
+        10.0,
+    );
+    // A thin glowing bar along the prism, below it so that its light goes
+    // through the prism rather than being totally reflected inside of it. It
+    // is narrow in the direction the light is spread out by the prism, which
+    // keeps the colors apart, and long in the other, which gives paths a fair
+    // chance of finding it. The prism is just as long, so that the rainbow
+    // isn't cut off at its ends.
+    let mut light = Principled::new(Color::BLACK);
+    light.set_emission(Color::WHITE);
+    light.set_emission_strength(100.0);
+    scene.add_object(Object::new(
+        Box::new(Cuboid::new(
+            Vec3::new(-2.05, 0.2, -10.0),
+            Vec3::new(-2.0, 0.25, 10.0),
+        )),
+        Box::new(light),
+    ));
+    // A low wall keeps the light from reaching the floor directly, so that
+    // the rainbow stands out.
+    scene.add_object(Object::new(
+        Box::new(Cuboid::new(
+            Vec3::new(-1.55, 0.0, -10.0),
+            Vec3::new(-1.5, 0.35, 10.0),
+        )),
+        Box::new(Lambertian::new(Color::BLACK)),
+    ));
+
+    let camera = Camera::new(
+        Vec3::new(1.5, 2.0, 1.0),
+        Vec3::new(1.5, 0.0, -0.5),
+        Vec3::Y,
+        50.0,
+    );
+    let mut renderer = ProgressiveRenderer::new(400, 300, 10);
+    renderer.set_spectral(spectral);
+    renderer.add_samples(&scene, &camera, samples);
+    std::fs::write(output, renderer.image().to_ppm()).unwrap();
+}
@@ -0,0 +1,489 @@
+//! A small command line interface for rendering a scene natively. The image is
+//! rendered progressively and written to the output file after every pass, so
+//! long renders can be inspected before they finish.
+//!
+//! Alternatively, `--time` renders for the given number of seconds (or until
+//! the noise estimate of the image drops below `--noise`) instead of drawing a
+//! fixed number of samples.
+//!
+//! With `--checkpoint`, the state of the render is saved after every pass, and
+//! if the checkpoint file already exists, the render resumes from it, with the
+//! `--filter`, clamping and `--spectral` mode it was started with. Renders of
+//! the same scene done on different machines (with different `--seed`s) can be
+//! combined by passing each of their checkpoints with `--merge`.
+//!
+//! `--aovs` additionally writes the AOVs (depth, normals, albedo, etc ...)
+//! along with the image to a multi-layer OpenEXR file, and `--denoise` removes
+//! the remaining noise from the image with a filter guided by those AOVs.
+//!
+//! `--filter` picks the pixel reconstruction filter (box, tent, gaussian,
+//! mitchell or blackman-harris) and `--filter-radius` its radius in pixels.
+//!
+//! `--clamp-direct` and `--clamp-indirect` clamp the brightness of the direct
+//! and indirect light of each sample, and `--fireflies` replaces pixels that
+//! are more than the given ratio brighter than all of their neighbours.
+//!
+//! `--environment` lights the scene with an equirectangular `.hdr` or `.exr`
+//! image, which can be rotated around the vertical axis (in degrees) with
+//! `--environment-rotation` and scaled with `--environment-intensity`.
+//! Alternatively, `--sun-elevation` (and `--sun-azimuth`, in degrees) lights
+//! the scene with a physical sky model, whose haziness is set by `--turbidity`.
+//! `--point-light x,y,z,intensity` adds a white point light (repeatable).
+//!
+//! `--material` changes the material of the center sphere to a metal (gold,
+//! copper or aluminum), glass, dispersive flint glass or diamond (best seen
+//! with `--spectral`), a red plastic (a principled material), a translucent
+//! skin or milk (subsurface scattering), an iridescent soap bubble or oil
+//! slick (thin-film interference), a red paint under a clear coat (layered),
+//! or a checkerboard of rusty gold (mixed), with the given `--roughness` (from
+//! 0 to 1).
+//!
+//! `--mesh` replaces the center sphere with a triangle mesh loaded from an OBJ
+//! file, used as is (it should be about 1 unit wide, around (0, 0, -1)).
+//! `--displacement` displaces it by a height map image scaled by
+//! `--displacement-scale`, after subdividing it `--subdivisions` times.
+//! `--bump` (with `--bump-scale`) or `--normal-map` add detail to the shading
+//! of the center object with a height map or a tangent space normal map.
+//! `--opacity` cuts it out with an opacity image (e.g. for a lattice).
+//!
+//! `--floor` replaces the ground with a wide plane covered once by an image,
+//! filtered with `--texture-filter` (`bilinear`, `trilinear` or `ewa`, see
+//! `TextureFilter`), e.g. to compare the aliasing of a checkerboard far away.
+//!
+//! `--fog` fills the scene with white fog of the given density (scattering
+//! coefficient), and `--volume` turns the center sphere into a cloud of smoke
+//! of the given density. `--grid` instead replaces it with a box of smoke
+//! loaded from a raw voxel grid file (see `VoxelGrid::from_raw`), whose
+//! densities are scaled by `--volume`, and `--grid-emission` makes the smoke
+//! glow with the colors of a second grid (e.g. for fire).
+//!
+//! `--spectral` renders in spectral mode (see `spectrum`) instead of RGB.
+//!
+//! Usage:
+//!
+//!    cargo run --release --example render -- [--width 800] [--height 450]
+//!        [--samples 100] [--passes 10] [--bounces 50] [--output output.ppm]
+//!        [--time 10] [--noise 0.01] [--checkpoint render.ckpt] [--seed 42]
+//!        [--merge a.ckpt --merge b.ckpt] [--aovs output.exr] [--denoise]
+//!        [--filter gaussian] [--filter-radius 1.5] [--clamp-direct 100]
+//!        [--clamp-indirect 10] [--fireflies 4] [--environment sky.hdr]
+//!        [--environment-rotation 90] [--environment-intensity 1.5]
+//!        [--sun-elevation 30] [--sun-azimuth 45] [--turbidity 3]
+//!        [--point-light 1,1,0,5] [--material gold] [--roughness 0.3]
+//!        [--fog 0.05] [--volume 4] [--grid smoke.raw]
+//!        [--grid-emission fire.raw] [--spectral] [--mesh bunny.obj]
+//!        [--displacement height.exr] [--displacement-scale 0.05]
+//!        [--subdivisions 2] [--bump height.exr] [--bump-scale 0.01]
+//!        [--normal-map normals.exr] [--opacity mask.exr]
+//!        [--floor tiles.exr] [--texture-filter ewa]
+
+use std::{path::Path, time::Duration};
+
+use rrt_core::{
+    camera::Camera,
+    checkpoint::Checkpoint,
+    color::Color,
+    denoise::{remove_fireflies, DenoiseSettings},
+    engine::{self, Budget, Clamp},
+    environment::{EnvironmentMap, PhysicalSky},
+    filter::{Filter, FilterKind},
+    light::PointLight,
+    material::{
+        Conductor, Dielectric, Dispersive, Lambertian, Layered, Material, Mix, Principled,
+        Subsurface, ThinFilm,
+    },
+    medium::{Heterogeneous, Homogeneous, VoxelGrid},
+    object::Object,
+    progressive::ProgressiveRenderer,
+    scene::Scene,
+    shape::{Cuboid, Mesh, Quad, Shape, Sphere},
+    texture::{BumpMap, Checker, ImageTexture, NormalMap, TextureFilter},
+    vec3::Vec3,
+};
+
+struct Args {
+    width: usize,
+    height: usize,
+    samples: usize,
+    passes: usize,
+    bounces: usize,
+    output: String,
+    time: Option<f64>,
+    noise: Option<f64>,
+    checkpoint: Option<String>,
+    seed: Option<u64>,
+    merge: Vec<String>,
+    aovs: Option<String>,
+    denoise: bool,
+    filter: FilterKind,
+    filter_radius: Option<f64>,
+    clamp: Clamp,
+    fireflies: Option<f64>,
+    environment: Option<String>,
+    environment_rotation: f64,
+    environment_intensity: f64,
+    sun_elevation: Option<f64>,
+    sun_azimuth: f64,
+    turbidity: f64,
+    point_lights: Vec<[f64; 4]>,
+    material: Option<String>,
+    roughness: f64,
+    fog: Option<f64>,
+    volume: Option<f64>,
+    grid: Option<String>,
+    grid_emission: Option<String>,
+    spectral: bool,
+    mesh: Option<String>,
+    displacement: Option<String>,
+    displacement_scale: f64,
+    subdivisions: u32,
+    bump: Option<String>,
+    bump_scale: f64,
+    normal_map: Option<String>,
+    opacity: Option<String>,
+    floor: Option<String>,
+    texture_filter: TextureFilter,
+}
+
+impl Args {
+    fn parse() -> Self {
+        let mut args = Args {
+            width: 800,
+            height: 450,
+            samples: 100,
+            passes: 10,
+            bounces: 50,
+            output: "./output.ppm".to_string(),
+            time: None,
+            noise: None,
+            checkpoint: None,
+            seed: None,
+            merge: Vec::new(),
+            aovs: None,
+            denoise: false,
+            filter: FilterKind::Box,
+            filter_radius: None,
+            clamp: Clamp::default(),
+            fireflies: None,
+            environment: None,
+            environment_rotation: 0.0,
+            environment_intensity: 1.0,
+            sun_elevation: None,
+            sun_azimuth: 0.0,
+            turbidity: 3.0,
+            point_lights: Vec::new(),
+            material: None,
+            roughness: 0.0,
+            fog: None,
+            volume: None,
+            grid: None,
+            grid_emission: None,
+            spectral: false,
+            mesh: None,
+            displacement: None,
+            displacement_scale: 0.05,
+            subdivisions: 2,
+            bump: None,
+            bump_scale: 0.01,
+            normal_map: None,
+            opacity: None,
+            floor: None,
+            texture_filter: TextureFilter::Trilinear,
+        };
+        let mut it = std::env::args().skip(1);
+        while let Some(flag) = it.next() {
+            let mut value = || {
+                it.next()
+                    .unwrap_or_else(|| panic!("missing value for {flag}"))
+            };
+            match flag.as_str() {
+                "--width" => args.width = value().parse().unwrap(),
+                "--height" => args.height = value().parse().unwrap(),
+                "--samples" => args.samples = value().parse().unwrap(),
+                "--passes" => args.passes = value().parse().unwrap(),
+                "--bounces" => args.bounces = value().parse().unwrap(),
+                "--output" => args.output = value(),
+                "--time" => args.time = Some(value().parse().unwrap()),
+                "--noise" => args.noise = Some(value().parse().unwrap()),
+                "--checkpoint" => args.checkpoint = Some(value()),
+                "--seed" => args.seed = Some(value().parse().unwrap()),
+                "--merge" => args.merge.push(value()),
+                "--aovs" => args.aovs = Some(value()),
+                "--denoise" => args.denoise = true,
+                "--filter" => {
+                    let name = value();
+                    args.filter = *FilterKind::ALL
+                        .iter()
+                        .find(|kind| kind.name() == name)
+                        .unwrap_or_else(|| panic!("unknown filter {name}"));
+                }
+                "--filter-radius" => args.filter_radius = Some(value().parse().unwrap()),
+                "--clamp-direct" => args.clamp.direct = Some(value().parse().unwrap()),
+                "--clamp-indirect" => args.clamp.indirect = Some(value().parse().unwrap()),
+                "--fireflies" => args.fireflies = Some(value().parse().unwrap()),
+                "--environment" => args.environment = Some(value()),
+                "--environment-rotation" => args.environment_rotation = value().parse().unwrap(),
+                "--environment-intensity" => args.environment_intensity = value().parse().unwrap(),
+                "--sun-elevation" => args.sun_elevation = Some(value().parse().unwrap()),
+                "--sun-azimuth" => args.sun_azimuth = value().parse().unwrap(),
+                "--turbidity" => args.turbidity = value().parse().unwrap(),
+                "--point-light" => {
+                    let values: Vec<f64> = value().split(',').map(|v| v.parse().unwrap()).collect();
+                    args.point_lights
+                        .push(values.try_into().expect("expected x,y,z,intensity"));
+                }
+                "--material" => args.material = Some(value()),
+                "--roughness" => args.roughness = value().parse().unwrap(),
+                "--fog" => args.fog = Some(value().parse().unwrap()),
+                "--volume" => args.volume = Some(value().parse().unwrap()),
+                "--grid" => args.grid = Some(value()),
+                "--grid-emission" => args.grid_emission = Some(value()),
+                "--spectral" => args.spectral = true,
+                "--mesh" => args.mesh = Some(value()),
+                "--displacement" => args.displacement = Some(value()),
+                "--displacement-scale" => args.displacement_scale = value().parse().unwrap(),
+                "--subdivisions" => args.subdivisions = value().parse().unwrap(),
+                "--bump" => args.bump = Some(value()),
+                "--bump-scale" => args.bump_scale = value().parse().unwrap(),
+                "--normal-map" => args.normal_map = Some(value()),
+                "--opacity" => args.opacity = Some(value()),
+                "--floor" => args.floor = Some(value()),
+                "--texture-filter" => {
+                    args.texture_filter = match value().as_str() {
+                        "bilinear" => TextureFilter::Bilinear,
+                        "trilinear" => TextureFilter::Trilinear,
+                        "ewa" => TextureFilter::Ewa,
+                        name => panic!("unknown texture filter {name}"),
+                    }
+                }
+                _ => panic!("unknown argument {flag}"),
+            }
+        }
+        args
+    }
+}
+
+fn main() {
+    let args = Args::parse();
+
+    let material: Box<dyn Material> = match args.material.as_deref() {
+        None => Box::new(Lambertian::new(Color::RED)),
+        Some("gold") => Box::new(Conductor::gold(args.roughness)),
+        Some("copper") => Box::new(Conductor::copper(args.roughness)),
+        Some("aluminum") => Box::new(Conductor::aluminum(args.roughness)),
+        Some("glass") => Box::new(Dielectric::new(1.5, args.roughness)),
+        Some("flint") => Box::new(Dispersive::flint(args.roughness)),
+        Some("diamond") => Box::new(Dispersive::diamond(args.roughness)),
+        Some("plastic") => {
+            let mut plastic = Principled::new(Color::RED);
+            plastic.set_roughness(args.roughness);
+            Box::new(plastic)
+        }
+        Some("skin") => {
+            let mean_free_path = Color::new(0.05, 0.02, 0.01);
+            let mut skin = Subsurface::new(Color::new(0.8, 0.5, 0.4), mean_free_path);
+            skin.set_roughness(args.roughness);
+            Box::new(skin)
+        }
+        Some("milk") => {
+            let mean_free_path = Color::WHITE * 0.02;
+            let mut milk = Subsurface::new(Color::new(0.95, 0.93, 0.88), mean_free_path);
+            milk.set_roughness(args.roughness);
+            Box::new(milk)
+        }
+        Some("bubble") => Box::new(ThinFilm::soap_bubble(500.0)),
+        Some("oil") => {
+            // A film of oil on a puddle of dark water.
+            let water = Lambertian::new(Color::WHITE * 0.02);
+            let mut oil = ThinFilm::new(water, 400.0, 1.5);
+            oil.set_base_ior(1.33);
+            Box::new(oil)
+        }
+        Some("clearcoat") => {
+            let paint = Lambertian::new(Color::new(0.6, 0.05, 0.05));
+            Box::new(Layered::new(Dielectric::new(1.5, args.roughness), paint))
+        }
+        Some("rust") => {
+            let rust = Lambertian::new(Color::new(0.4, 0.15, 0.05));
+            let mask = Checker::new(0.0, 0.8, 8.0);
+            Box::new(Mix::new(Conductor::gold(args.roughness), rust, mask))
+        }
+        Some(name) => panic!("unknown material {name}"),
+    };
+    let mut scene = Scene::new();
+    let sphere: Box<dyn Shape> = match &args.mesh {
+        Some(path) => {
+            let mut mesh = Mesh::load_obj(path).unwrap();
+            if let Some(path) = &args.displacement {
+                let height = ImageTexture::load(path).unwrap();
+                mesh.displace(&height, args.displacement_scale, args.subdivisions);
+            }
+            Box::new(mesh)
+        }
+        None => Box::new(Sphere::new(0.5, Vec3::new(0., 0., -1.))),
+    };
+    match (&args.grid, args.volume) {
+        (Some(path), scale) => {
+            let (corner, opposite) = (Vec3::new(-0.5, -0.5, -1.5), Vec3::new(0.5, 0.5, -0.5));
+            let mut smoke =
+                Heterogeneous::new(VoxelGrid::<f64>::load(path).unwrap(), corner, opposite);
+            smoke.set_density_scale(scale.unwrap_or(1.0));
+            if let Some(path) = &args.grid_emission {
+                smoke.set_emission(VoxelGrid::<Color>::load(path).unwrap());
+            }
+            let bounds = Box::new(Cuboid::new(corner, opposite));
+            scene.add_object(Object::volume(bounds, Box::new(smoke)));
+        }
+        (None, Some(density)) => {
+            let smoke = Homogeneous::new(Color::WHITE * (0.1 * density), Color::WHITE * density);
+            scene.add_object(Object::volume(sphere, Box::new(smoke)));
+        }
+        (None, None) => {
+            let mut object = Object::new(sphere, material);
+            if let Some(path) = &args.normal_map {
+                let map = NormalMap::new(ImageTexture::load(path).unwrap());
+                object.set_normal_mapping(Box::new(map));
+            } else if let Some(path) = &args.bump {
+                let height = ImageTexture::load(path).unwrap();
+                object.set_normal_mapping(Box::new(BumpMap::new(height, args.bump_scale)));
+            }
+            if let Some(path) = &args.opacity {
+                object.set_opacity(Box::new(ImageTexture::load(path).unwrap()));
+            }
+            scene.add_object(object);
+        }
+    }
+    match &args.floor {
+        Some(path) => {
+            let mut image = ImageTexture::load(path).unwrap();
+            image.set_filter(args.texture_filter);
+            let (size, corner) = (40.0, Vec3::new(-20.0, -0.5, 19.0));
+            scene.add_object(Object::new(
+                Box::new(Quad::new(corner, Vec3::X * size, -Vec3::Z * size)),
+                Box::new(Principled::new(image)),
+            ));
+        }
+        None => scene.add_object(Object::new(
+            Box::new(Sphere::new(100.0, Vec3::new(0.0, -100.5, -1.0))),
+            Box::new(Lambertian::new(Color::WHITE * 0.5)),
+        )),
+    }
+    if let Some(path) = &args.environment {
+        let mut map = EnvironmentMap::load(path).unwrap();
+        map.set_rotation(args.environment_rotation);
+        map.set_intensity(args.environment_intensity);
+        scene.set_environment(Box::new(map));
+    } else if let Some(elevation) = args.sun_elevation {
+        // Azimuth 0 puts the sun in front of the camera (towards -z).
+        let sun = Vec3::new(0.0, 0.0, -1.0)
+            .rotate_about_x_axis(elevation)
+            .rotate_about_y_axis(args.sun_azimuth);
+        let mut sky = PhysicalSky::new(sun, args.turbidity);
+        sky.set_intensity(args.environment_intensity);
+        scene.set_environment(Box::new(sky));
+    }
+    if let Some(density) = args.fog {
+        let fog = Homogeneous::new(Color::BLACK, Color::WHITE * density);
+        scene.set_fog(Box::new(fog));
+    }
+    for [x, y, z, intensity] in args.point_lights {
+        scene.add_light(Box::new(PointLight::new(
+            Vec3::new(x, y, z),
+            Color::WHITE * intensity,
+        )));
+    }
+    let camera = Camera::default();
+
+    if !args.merge.is_empty() {
+        let mut merged = Checkpoint::load(&args.merge[0]).unwrap();
+        for path in &args.merge[1..] {
+            merged.merge(&Checkpoint::load(path).unwrap()).unwrap();
+        }
+        if let Some(path) = &args.checkpoint {
+            merged.save(path).unwrap();
+        }
+        std::fs::write(&args.output, merged.film.to_buffer().to_ppm()).unwrap();
+        return;
+    }
+
+    if let Some(seconds) = args.time {
+        let budget = Budget {
+            time: Duration::from_secs_f64(seconds),
+            target_noise: args.noise,
+            samples_per_pass: 4,
+        };
+        let result = engine::render_budgeted(
+            &scene,
+            &camera,
+            args.width,
+            args.height,
+            &budget,
+            args.bounces,
+        );
+        let total: usize = result.sample_counts.iter().sum();
+        println!(
+            "{} passes, {:.1} samples per pixel on average, noise estimate {:.4}",
+            result.passes,
+            total as f64 / result.sample_counts.len() as f64,
+            result.noise
+        );
+        std::fs::write(&args.output, result.image.to_ppm()).unwrap();
+        return;
+    }
+
+    let mut renderer = match &args.checkpoint {
+        Some(path) if Path::new(path).exists() => {
+            let checkpoint = Checkpoint::load(path).unwrap();
+            // The render settings come from the checkpoint: changing them
+            // would reset the render, and the next pass would overwrite the
+            // checkpoint with it.
+            ProgressiveRenderer::resume(checkpoint, &scene, &camera, args.bounces).unwrap()
+        }
+        _ => {
+            let mut renderer = match args.seed {
+                Some(seed) => {
+                    ProgressiveRenderer::with_seed(args.width, args.height, args.bounces, seed)
+                }
+                None => ProgressiveRenderer::new(args.width, args.height, args.bounces),
+            };
+            // Only the box filter is meant to stay within the pixel by default.
+            let default_radius = match args.filter {
+                FilterKind::Box => 0.5,
+                _ => 1.5,
+            };
+            renderer.set_filter(Filter::new(
+                args.filter,
+                args.filter_radius.unwrap_or(default_radius),
+            ));
+            renderer.set_clamp(args.clamp);
+            renderer.set_spectral(args.spectral);
+            renderer
+        }
+    };
+
+    if args.aovs.is_some() {
+        renderer.set_aovs(true);
+    }
+    if args.denoise {
+        renderer.set_denoise(Some(DenoiseSettings::default()));
+    }
+
+    // Spread the samples as evenly as possible over the passes.
+    for pass in renderer.passes()..args.passes {
+        let num_samples =
+            (args.samples * (pass + 1)) / args.passes - (args.samples * pass) / args.passes;
+        renderer.add_samples(&scene, &camera, num_samples);
+        if let Some(path) = &args.checkpoint {
+            renderer.checkpoint(&scene, &camera).save(path).unwrap();
+        }
+        let mut image = renderer.image();
+        if let Some(ratio) = args.fireflies {
+            image = remove_fireflies(&image, ratio);
+        }
+        std::fs::write(&args.output, image.to_ppm()).unwrap();
+        if let Some(path) = &args.aovs {
+            std::fs::write(path, renderer.film().to_frame_buffer().to_exr()).unwrap();
+        }
+        println!("pass {}/{} done", pass + 1, args.passes);
+    }
+}
@@ -0,0 +1,229 @@
+use crate::{color::Color, io::Layer, material::Lobe, vec3::Vec3};
+
+/// Arbitrary output variables (AOVs) are extra images rendered alongside the
+/// final (beauty) image, which are useful for compositing and denoising. This
+/// stores the AOVs of a single camera sample.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AovSample {
+    /// Information about the first surface hit by the camera ray, if any.
+    pub first_hit: Option<FirstHit>,
+
+    /// The kind of scattering at the first hit.
+    pub lobe: Option<Lobe>,
+
+    /// Light that reached the camera after bouncing off a single surface.
+    pub direct: Color,
+
+    /// Light that reached the camera after bouncing off two or more surfaces.
+    pub indirect: Color,
+}
+
+/// Information about the first surface hit by a camera ray.
+#[derive(Clone, Copy, Debug)]
+pub struct FirstHit {
+    /// The distance from the camera to the hit.
+    pub depth: f64,
+
+    /// The world-space normal at the hit.
+    pub normal: Vec3,
+
+    /// The world-space position of the hit.
+    pub position: Vec3,
+
+    /// The attenuation of the first bounce. Averaged over many samples, this
+    /// is the fraction of light reflected by the surface.
+    pub albedo: Color,
+
+    /// The ID of the object that was hit (see `Scene::intersect`).
+    pub object_id: usize,
+
+    /// The ID of the material of the object that was hit (see
+    /// `Scene::material_id`).
+    pub material_id: usize,
+}
+
+impl AovSample {
+    /// Records light that reached the camera after `bounces` bounces.
+    pub fn add_light(&mut self, bounces: usize, light: Color) {
+        match bounces {
+            // Light that was seen directly (e.g. the background) is neither
+            // direct nor indirect lighting of a surface.
+            0 => {}
+            1 => self.direct = self.direct + light,
+            _ => self.indirect = self.indirect + light,
+        }
+    }
+}
+
+/// The accumulated AOVs of a single pixel.
+#[derive(Clone, Copy, Debug)]
+pub struct AovPixel {
+    /// The number of samples.
+    count: usize,
+
+    /// The number of samples that hit a surface.
+    hits: usize,
+
+    depth_sum: f64,
+    normal_sum: Vec3,
+    position_sum: Vec3,
+    albedo_sum: Color,
+
+    /// The direct and indirect lighting for each lobe (indexed in the same
+    /// order as `Lobe::ALL`).
+    direct_sums: [Color; 3],
+    indirect_sums: [Color; 3],
+
+    /// IDs can't be averaged, so we use the IDs of the first sample of the
+    /// pixel.
+    object_id: Option<usize>,
+    material_id: Option<usize>,
+}
+
+impl AovPixel {
+    pub const EMPTY: AovPixel = AovPixel {
+        count: 0,
+        hits: 0,
+        depth_sum: 0.0,
+        normal_sum: Vec3::ZERO,
+        position_sum: Vec3::ZERO,
+        albedo_sum: Color::BLACK,
+        direct_sums: [Color::BLACK; 3],
+        indirect_sums: [Color::BLACK; 3],
+        object_id: None,
+        material_id: None,
+    };
+
+    pub fn add(&mut self, sample: &AovSample) {
+        if self.count == 0 {
+            self.object_id = sample.first_hit.map(|hit| hit.object_id);
+            self.material_id = sample.first_hit.map(|hit| hit.material_id);
+        }
+        self.count += 1;
+
+        if let Some(hit) = sample.first_hit {
+            self.hits += 1;
+            self.depth_sum += hit.depth;
+            self.normal_sum = self.normal_sum + hit.normal;
+            self.position_sum = self.position_sum + hit.position;
+            self.albedo_sum = self.albedo_sum + hit.albedo;
+        }
+        if let Some(lobe) = sample.lobe {
+            let k = lobe as usize;
+            self.direct_sums[k] = self.direct_sums[k] + sample.direct;
+            self.indirect_sums[k] = self.indirect_sums[k] + sample.indirect;
+        }
+    }
+
+    pub fn merge(&mut self, other: &AovPixel) {
+        if self.count == 0 {
+            self.object_id = other.object_id;
+            self.material_id = other.material_id;
+        }
+        self.count += other.count;
+        self.hits += other.hits;
+        self.depth_sum += other.depth_sum;
+        self.normal_sum = self.normal_sum + other.normal_sum;
+        self.position_sum = self.position_sum + other.position_sum;
+        self.albedo_sum = self.albedo_sum + other.albedo_sum;
+        for k in 0..Lobe::ALL.len() {
+            self.direct_sums[k] = self.direct_sums[k] + other.direct_sums[k];
+            self.indirect_sums[k] = self.indirect_sums[k] + other.indirect_sums[k];
+        }
+    }
+
+    /// The average distance to the first hit, over the samples that hit
+    /// something (infinity if none did).
+    pub fn depth(&self) -> f64 {
+        match self.hits {
+            0 => f64::INFINITY,
+            n => self.depth_sum / n as f64,
+        }
+    }
+
+    /// The average normal of the samples that hit something (zero if none
+    /// did). This is not normalized, so at the edges of objects it gets
+    /// shorter.
+    pub fn normal(&self) -> Vec3 {
+        match self.hits {
+            0 => Vec3::ZERO,
+            n => self.normal_sum / n as f64,
+        }
+    }
+
+    /// The average position of the samples that hit something.
+    pub fn position(&self) -> Vec3 {
+        match self.hits {
+            0 => Vec3::ZERO,
+            n => self.position_sum / n as f64,
+        }
+    }
+
+    /// The average albedo over all samples (samples that didn't hit anything
+    /// count as black).
+    pub fn albedo(&self) -> Color {
+        self.average(self.albedo_sum)
+    }
+
+    pub fn direct(&self, lobe: Lobe) -> Color {
+        self.average(self.direct_sums[lobe as usize])
+    }
+
+    pub fn indirect(&self, lobe: Lobe) -> Color {
+        self.average(self.indirect_sums[lobe as usize])
+    }
+
+    fn average(&self, sum: Color) -> Color {
+        match self.count {
+            0 => Color::BLACK,
+            n => sum / n as f64,
+        }
+    }
+}
+
+/// Converts the AOVs of an image into layers: depth, normal, position, albedo,
+/// object and material IDs (-1 for the background), and the direct and
+/// indirect lighting of each lobe.
+pub fn aov_layers(pixels: &[AovPixel]) -> Vec<Layer> {
+    let vec3 = |v: Vec3| [v.x, v.y, v.z];
+    let rgb = |c: Color| [c.r(), c.g(), c.b()];
+    let id = |id: Option<usize>| [id.map_or(-1.0, |id| id as f64)];
+
+    let mut layers = vec![
+        Layer::new("depth", &["Z"], pixels.iter().map(|p| [p.depth()])),
+        Layer::new(
+            "normal",
+            &["X", "Y", "Z"],
+            pixels.iter().map(|p| vec3(p.normal())),
+        ),
+        Layer::new(
+            "position",
+            &["X", "Y", "Z"],
+            pixels.iter().map(|p| vec3(p.position())),
+        ),
+        Layer::new(
+            "albedo",
+            &["R", "G", "B"],
+            pixels.iter().map(|p| rgb(p.albedo())),
+        ),
+        Layer::new("object_id", &["id"], pixels.iter().map(|p| id(p.object_id))),
+        Layer::new(
+            "material_id",
+            &["id"],
+            pixels.iter().map(|p| id(p.material_id)),
+        ),
+    ];
+    for lobe in Lobe::ALL {
+        layers.push(Layer::new(
+            &format!("{}_direct", lobe.name()),
+            &["R", "G", "B"],
+            pixels.iter().map(|p| rgb(p.direct(lobe))),
+        ));
+        layers.push(Layer::new(
+            &format!("{}_indirect", lobe.name()),
+            &["R", "G", "B"],
+            pixels.iter().map(|p| rgb(p.indirect(lobe))),
+        ));
+    }
+    layers
+}
@@ -1,7 +1,11 @@
-use crate::{ray::Ray, vec3::Vec3};
+use crate::{
+    ray::{Differentials, Ray},
+    vec3::Vec3,
+};
 
 /// The camera determines how and where we look at the rendered scene.
 /// Reference: https://www.scratchapixel.com/lessons/3d-basic-rendering/ray-tracing-generating-camera-rays/generating-camera-rays.html
+#[derive(Debug)]
 pub struct Camera {
     /// The location of the camera center (i.e. the "eye")
     eye: Vec3,
@@ -48,11 +52,20 @@ impl Camera {
     /// left corner of the viewport, (-1, 1) would be the top right corner,
     /// (1, -1) would be the bottom-left corner, (1, 1) would be the bottom
     /// right corner, and (0, 0) would be the center.
-    pub fn cast_ray(&self, a: f64, b: f64) -> Ray {
+    ///
+    /// `pixel` is the size of a pixel in these coordinates, which gives the
+    /// ray differentials of the ray (see `Differentials`).
+    pub fn cast_ray(&self, a: f64, b: f64, pixel: f64) -> Ray {
         let scale = (self.fov.to_radians() / 2.0).tan();
-        Ray::new(
-            self.eye,
-            self.direction + b * self.right * scale - a * self.up * scale,
-        )
+        let direction =
+            |a: f64, b: f64| self.direction + b * self.right * scale - a * self.up * scale;
+        let mut ray = Ray::new(self.eye, direction(a, b));
+        ray.differentials = Some(Differentials {
+            x_origin: self.eye,
+            x_direction: direction(a, b + pixel),
+            y_origin: self.eye,
+            y_direction: direction(a + pixel, b),
+        });
+        ray
     }
 }
@@ -0,0 +1,413 @@
+use std::{
+    fmt,
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+use crate::{
+    camera::Camera,
+    color::Color,
+    engine::Clamp,
+    film::{Film, Pixel},
+    filter::{Filter, FilterKind},
+    scene::Scene,
+    utils::{hash_bytes, mix_seed},
+};
+
+/// Identifies the file format (and its version).
+const MAGIC: &[u8; 8] = b"RRTCKPT1";
+
+/// The largest image a checkpoint can hold (in pixels), so that a corrupt
+/// header can't make us allocate an absurd amount of memory.
+const MAX_PIXELS: usize = 1 << 28;
+
+/// A snapshot of the state of a progressive render, which can be written to
+/// disk and later used to resume the render (see
+/// `ProgressiveRenderer::resume`).
+///
+/// The random number generator is reseeded from `seed` and the pass number at
+/// the start of every pass, so `seed` and `passes` are all we need to continue
+/// the sequence of random numbers where we left off.
+pub struct Checkpoint {
+    /// The accumulated samples.
+    pub film: Film,
+
+    /// The reconstruction filter the samples were weighted with.
+    pub filter: Filter,
+
+    /// The limits the samples were clamped to.
+    pub clamp: Clamp,
+
+    /// Whether the samples were rendered in spectral mode.
+    pub spectral: bool,
+
+    /// The seed the random number generator is derived from.
+    pub seed: u64,
+
+    /// The number of passes rendered so far.
+    pub passes: u64,
+
+    /// Identifies the scene (and camera and render settings) that was rendered
+    /// (see `scene_hash`). Samples from different scenes can't be combined.
+    pub scene_hash: u64,
+}
+
+/// The errors that can happen when reading, resuming or merging checkpoints.
+#[derive(Debug)]
+pub enum CheckpointError {
+    /// Reading or writing the checkpoint failed.
+    Io(io::Error),
+
+    /// The file is not a checkpoint (or was written by an incompatible version).
+    InvalidFormat,
+
+    /// The checkpoint was rendered from a different scene.
+    SceneMismatch,
+
+    /// The checkpoint has a different image size.
+    SizeMismatch,
+
+    /// The checkpoint was rendered with a different reconstruction filter or
+    /// clamping, or one was rendered in spectral mode and the other wasn't.
+    SettingsMismatch,
+
+    /// Both checkpoints were rendered from the same seed, so they contain the
+    /// exact same samples and merging them would not reduce the noise.
+    SameSeed,
+}
+
+impl fmt::Display for CheckpointError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CheckpointError::Io(err) => write!(f, "checkpoint io error: {err}"),
+            CheckpointError::InvalidFormat => write!(f, "not a valid checkpoint file"),
+            CheckpointError::SceneMismatch => write!(f, "checkpoint is from a different scene"),
+            CheckpointError::SizeMismatch => write!(f, "checkpoint has a different image size"),
+            CheckpointError::SettingsMismatch => {
+                write!(f, "checkpoint was rendered with different settings")
+            }
+            CheckpointError::SameSeed => write!(f, "checkpoints were rendered with the same seed"),
+        }
+    }
+}
+
+impl std::error::Error for CheckpointError {}
+
+impl From<io::Error> for CheckpointError {
+    fn from(err: io::Error) -> Self {
+        CheckpointError::Io(err)
+    }
+}
+
+/// Computes the hash stored in a checkpoint, which covers everything that
+/// affects the value of the samples: the contents of the scene, the camera and
+/// the max number of bounces.
+pub fn scene_hash(scene: &Scene, camera: &Camera, max_bounces: usize) -> u64 {
+    let camera_hash = hash_bytes(format!("{camera:?}").as_bytes());
+    mix_seed(
+        mix_seed(scene.content_hash(), camera_hash),
+        max_bounces as u64,
+    )
+}
+
+impl Checkpoint {
+    /// Writes the checkpoint in a simple binary format (all numbers are little
+    /// endian):
+    ///
+    ///    magic (8 bytes), scene_hash, seed, passes, width, height (u64 each)
+    ///    filter kind (u64, index into `FilterKind::ALL`), filter radius (f64)
+    ///    direct and indirect clamp (f64 each, infinity if not clamped)
+    ///    spectral (u64, 0 or 1)
+    ///    then for each pixel: r, g, b, weight_sum, luminance_sum,
+    ///    luminance_squared_sum (f64), count (u64)
+    pub fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(MAGIC)?;
+        for value in [
+            self.scene_hash,
+            self.seed,
+            self.passes,
+            self.film.width as u64,
+            self.film.height as u64,
+        ] {
+            writer.write_all(&value.to_le_bytes())?;
+        }
+        let kind = FilterKind::ALL
+            .iter()
+            .position(|&kind| kind == self.filter.kind)
+            .unwrap();
+        writer.write_all(&(kind as u64).to_le_bytes())?;
+        writer.write_all(&self.filter.radius.to_le_bytes())?;
+        for limit in [self.clamp.direct, self.clamp.indirect] {
+            writer.write_all(&limit.unwrap_or(f64::INFINITY).to_le_bytes())?;
+        }
+        writer.write_all(&(self.spectral as u64).to_le_bytes())?;
+        for pixel in self.film.pixels() {
+            for value in [
+                pixel.sum.r(),
+                pixel.sum.g(),
+                pixel.sum.b(),
+                pixel.weight_sum,
+                pixel.luminance_sum,
+                pixel.luminance_squared_sum,
+            ] {
+                writer.write_all(&value.to_le_bytes())?;
+            }
+            writer.write_all(&(pixel.count as u64).to_le_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// Reads a checkpoint written by `Checkpoint::write`.
+    pub fn read<R: Read>(reader: &mut R) -> Result<Checkpoint, CheckpointError> {
+        let mut magic = [0; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(CheckpointError::InvalidFormat);
+        }
+
+        let scene_hash = read_u64(reader)?;
+        let seed = read_u64(reader)?;
+        let passes = read_u64(reader)?;
+        let width = read_u64(reader)? as usize;
+        let height = read_u64(reader)? as usize;
+        let num_pixels = width
+            .checked_mul(height)
+            .filter(|&n| n <= MAX_PIXELS)
+            .ok_or(CheckpointError::InvalidFormat)?;
+        let kind = *FilterKind::ALL
+            .get(read_u64(reader)? as usize)
+            .ok_or(CheckpointError::InvalidFormat)?;
+        let radius = read_f64(reader)?;
+        if radius.is_nan() || radius <= 0.0 {
+            return Err(CheckpointError::InvalidFormat);
+        }
+        let mut read_limit = || read_f64(reader).map(|x| Some(x).filter(|x| x.is_finite()));
+        let clamp = Clamp {
+            direct: read_limit()?,
+            indirect: read_limit()?,
+        };
+        let spectral = match read_u64(reader)? {
+            0 => false,
+            1 => true,
+            _ => return Err(CheckpointError::InvalidFormat),
+        };
+
+        // Don't trust the size until the pixels are actually there, the file
+        // may be truncated.
+        let mut pixels = Vec::with_capacity(num_pixels.min(1 << 20));
+        for _ in 0..num_pixels {
+            let sum = Color::new(read_f64(reader)?, read_f64(reader)?, read_f64(reader)?);
+            let weight_sum = read_f64(reader)?;
+            let luminance_sum = read_f64(reader)?;
+            let luminance_squared_sum = read_f64(reader)?;
+            let count = read_u64(reader)? as usize;
+            pixels.push(Pixel {
+                sum,
+                weight_sum,
+                luminance_sum,
+                luminance_squared_sum,
+                count,
+            });
+        }
+
+        Ok(Checkpoint {
+            film: Film::from_pixels(pixels, width, height),
+            filter: Filter::new(kind, radius),
+            clamp,
+            spectral,
+            seed,
+            passes,
+            scene_hash,
+        })
+    }
+
+    /// Writes the checkpoint to a file. The checkpoint is first written to a
+    /// temporary file which is then renamed, so that the previous checkpoint
+    /// isn't lost if the process dies while writing.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let path = path.as_ref();
+        let tmp_path = path.with_extension("tmp");
+        let mut writer = BufWriter::new(File::create(&tmp_path)?);
+        self.write(&mut writer)?;
+        writer.into_inner()?.sync_all()?;
+        std::fs::rename(tmp_path, path)
+    }
+
+    /// Reads a checkpoint from a file.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Checkpoint, CheckpointError> {
+        Checkpoint::read(&mut BufReader::new(File::open(path)?))
+    }
+
+    /// Adds the samples of another checkpoint of the same scene and settings
+    /// (e.g. rendered on another machine) to this one. The checkpoints must have been
+    /// rendered with different seeds, otherwise they would contain the same
+    /// samples. The merged checkpoint gets a new seed derived from both seeds,
+    /// so resuming it won't repeat the samples of either of them.
+    pub fn merge(&mut self, other: &Checkpoint) -> Result<(), CheckpointError> {
+        if self.scene_hash != other.scene_hash {
+            return Err(CheckpointError::SceneMismatch);
+        }
+        if self.film.width != other.film.width || self.film.height != other.film.height {
+            return Err(CheckpointError::SizeMismatch);
+        }
+        if self.filter != other.filter
+            || self.clamp != other.clamp
+            || self.spectral != other.spectral
+        {
+            return Err(CheckpointError::SettingsMismatch);
+        }
+        if self.seed == other.seed {
+            return Err(CheckpointError::SameSeed);
+        }
+        self.film.merge(&other.film);
+        self.seed = mix_seed(self.seed, other.seed);
+        self.passes += other.passes;
+        Ok(())
+    }
+}
+
+fn read_u64<R: Read>(reader: &mut R) -> io::Result<u64> {
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn read_f64<R: Read>(reader: &mut R) -> io::Result<f64> {
+    Ok(f64::from_bits(read_u64(reader)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        material::Lambertian, object::Object, progressive::ProgressiveRenderer, shape::Sphere,
+        vec3::Vec3,
+    };
+
+    fn setup() -> (Scene, Camera) {
+        let mut scene = Scene::new();
+        scene.add_object(Object::new(
+            Box::new(Sphere::new(0.5, Vec3::new(0., 0., -1.))),
+            Box::new(Lambertian::new(Color::RED)),
+        ));
+        (scene, Camera::default())
+    }
+
+    fn assert_same_film(a: &Film, b: &Film) {
+        for (a, b) in a.pixels().iter().zip(b.pixels()) {
+            assert_eq!(a.count, b.count);
+            assert_eq!(a.weight_sum, b.weight_sum);
+            assert_eq!(a.sum.r(), b.sum.r());
+            assert_eq!(a.sum.g(), b.sum.g());
+            assert_eq!(a.sum.b(), b.sum.b());
+        }
+    }
+
+    #[test]
+    fn test_write_read_roundtrip() {
+        let (scene, camera) = setup();
+        let mut renderer = ProgressiveRenderer::new(8, 6, 5);
+        renderer.set_spectral(true);
+        renderer.add_samples(&scene, &camera, 2);
+        let checkpoint = renderer.checkpoint(&scene, &camera);
+
+        let mut bytes = Vec::new();
+        checkpoint.write(&mut bytes).unwrap();
+        let read = Checkpoint::read(&mut bytes.as_slice()).unwrap();
+
+        assert_eq!(read.seed, checkpoint.seed);
+        assert_eq!(read.passes, 1);
+        assert_eq!(read.scene_hash, checkpoint.scene_hash);
+        assert_eq!(read.filter, checkpoint.filter);
+        assert_eq!(read.clamp, checkpoint.clamp);
+        assert!(read.spectral);
+        assert_same_film(&read.film, &checkpoint.film);
+    }
+
+    #[test]
+    fn test_resume_continues_render() {
+        let (scene, camera) = setup();
+
+        let mut uninterrupted = ProgressiveRenderer::new(8, 6, 5);
+        uninterrupted.add_samples(&scene, &camera, 2);
+        uninterrupted.add_samples(&scene, &camera, 2);
+
+        let mut interrupted = ProgressiveRenderer::with_seed(8, 6, 5, uninterrupted.seed());
+        interrupted.add_samples(&scene, &camera, 2);
+        let checkpoint = interrupted.checkpoint(&scene, &camera);
+        let mut resumed = ProgressiveRenderer::resume(checkpoint, &scene, &camera, 5).unwrap();
+        resumed.add_samples(&scene, &camera, 2);
+
+        assert_same_film(resumed.film(), uninterrupted.film());
+    }
+
+    #[test]
+    fn test_merge() {
+        let (scene, camera) = setup();
+        let mut a = ProgressiveRenderer::with_seed(8, 6, 5, 1);
+        let mut b = ProgressiveRenderer::with_seed(8, 6, 5, 2);
+        a.add_samples(&scene, &camera, 3);
+        b.add_samples(&scene, &camera, 2);
+
+        let mut merged = a.checkpoint(&scene, &camera);
+        merged.merge(&b.checkpoint(&scene, &camera)).unwrap();
+        assert!(merged.film.sample_counts().iter().all(|&n| n == 5));
+
+        let mut copy = a.checkpoint(&scene, &camera);
+        assert!(matches!(
+            copy.merge(&a.checkpoint(&scene, &camera)),
+            Err(CheckpointError::SameSeed)
+        ));
+
+        let c = ProgressiveRenderer::with_seed(8, 6, 5, 1);
+        assert!(matches!(
+            merged.merge(&c.checkpoint(&scene, &Camera::new(Vec3::Z, Vec3::ZERO, Vec3::Y, 60.0))),
+            Err(CheckpointError::SceneMismatch)
+        ));
+    }
+
+    #[test]
+    fn test_read_corrupt() {
+        let (scene, camera) = setup();
+        let mut renderer = ProgressiveRenderer::new(8, 6, 5);
+        renderer.add_samples(&scene, &camera, 1);
+        let mut bytes = Vec::new();
+        renderer
+            .checkpoint(&scene, &camera)
+            .write(&mut bytes)
+            .unwrap();
+        let read = |bytes: &[u8]| Checkpoint::read(&mut &bytes[..]);
+
+        // The width and height follow the magic, scene hash, seed and passes.
+        let with_size = |width: u64, height: u64| {
+            let mut bytes = bytes.clone();
+            bytes[32..40].copy_from_slice(&width.to_le_bytes());
+            bytes[40..48].copy_from_slice(&height.to_le_bytes());
+            bytes
+        };
+        assert!(matches!(
+            read(&with_size(u64::MAX, 2)),
+            Err(CheckpointError::InvalidFormat)
+        ));
+        assert!(matches!(
+            read(&with_size(1 << 20, 1 << 20)),
+            Err(CheckpointError::InvalidFormat)
+        ));
+        // A plausible size, but the pixels aren't there.
+        assert!(matches!(
+            read(&with_size(1 << 14, 1 << 14)),
+            Err(CheckpointError::Io(_))
+        ));
+
+        assert!(matches!(
+            read(&bytes[..bytes.len() / 2]),
+            Err(CheckpointError::Io(_))
+        ));
+        assert!(matches!(read(&bytes[..20]), Err(CheckpointError::Io(_))));
+        assert!(matches!(
+            read(b"not a checkpoint file"),
+            Err(CheckpointError::InvalidFormat)
+        ));
+    }
+}
@@ -59,6 +59,24 @@ impl Color {
         }
     }
 
+    pub fn r(&self) -> f64 {
+        self.r
+    }
+
+    pub fn g(&self) -> f64 {
+        self.g
+    }
+
+    pub fn b(&self) -> f64 {
+        self.b
+    }
+
+    /// The relative luminance of the color (using the Rec. 709 weights), i.e.
+    /// how bright the color is perceived to be.
+    pub fn luminance(&self) -> f64 {
+        0.2126 * self.r + 0.7152 * self.g + 0.0722 * self.b
+    }
+
     pub fn to_u8(&self) -> (u8, u8, u8) {
         // The .sqrt() is for gamma correction.
         (
@@ -69,6 +87,12 @@ impl Color {
     }
 }
 
+impl Default for Color {
+    fn default() -> Self {
+        Color::BLACK
+    }
+}
+
 impl Neg for Color {
     type Output = Color;
     fn neg(self) -> Color {
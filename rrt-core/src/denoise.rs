@@ -0,0 +1,280 @@
+use crate::{color::Color, film::Film, io::Buffer, vec3::Vec3};
+
+/// Settings for [`denoise`].
+#[derive(Clone, Debug)]
+pub struct DenoiseSettings {
+    /// The number of filter passes. Each pass doubles the radius of the filter,
+    /// so with 5 passes the filter covers a 125 x 125 pixel neighbourhood.
+    pub iterations: usize,
+
+    /// How quickly the weight of a neighbour falls off as its color gets
+    /// further from the color of the center pixel. Smaller values preserve
+    /// more detail but remove less noise.
+    pub color_sigma: f64,
+
+    /// How quickly the weight of a neighbour falls off as its normal gets
+    /// further from the normal of the center pixel.
+    pub normal_sigma: f64,
+
+    /// How quickly the weight of a neighbour falls off as its albedo gets
+    /// further from the albedo of the center pixel.
+    pub albedo_sigma: f64,
+}
+
+impl Default for DenoiseSettings {
+    fn default() -> Self {
+        DenoiseSettings {
+            iterations: 5,
+            color_sigma: 0.5,
+            normal_sigma: 0.3,
+            albedo_sigma: 0.1,
+        }
+    }
+}
+
+/// The 1D B3-spline kernel used by the à-trous wavelet transform.
+const KERNEL: [f64; 5] = [1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+
+/// Removes noise from a rendered image using an edge-avoiding à-trous wavelet
+/// filter, guided by the albedo and normal AOVs of the image.
+/// Reference: Dammertz et al., "Edge-Avoiding À-Trous Wavelet Transform for fast Global Illumination Filtering"
+///
+/// The à-trous ("with holes") filter approximates a large blur by repeatedly
+/// applying a small 5x5 kernel, where the gaps between the taps of the kernel
+/// double every iteration (1, 2, 4, 8, ... pixels). A plain blur would also
+/// blur away edges and details, so each neighbour is additionally weighted by
+/// how similar it is to the center pixel:
+///
+///    w(p, q) = h(q) * exp(-|c_p - c_q|² / σ_c²)
+///                   * exp(-|n_p - n_q|² / σ_n²)
+///                   * exp(-|a_p - a_q|² / σ_a²)
+///
+/// where c, n and a are the color, normal and albedo. The normal and albedo
+/// are (almost) noise free even at low sample counts, so they tell us where
+/// the real edges of the image are. Since the color gets smoother with each
+/// iteration, σ_c is halved every iteration.
+///
+/// Before filtering, we divide the color by the albedo (and multiply it back
+/// afterwards), so that we only filter the lighting and textures stay sharp.
+///
+/// This runs on a single thread, so it also works in wasm without threads.
+pub fn denoise(
+    image: &Buffer,
+    albedo: &[Color],
+    normal: &[Vec3],
+    settings: &DenoiseSettings,
+) -> Buffer {
+    let (width, height) = (image.width, image.height);
+    assert!(albedo.len() == width * height && normal.len() == width * height);
+
+    // Pixels with a (near) black albedo (e.g. the background) are left as is.
+    let safe_albedo: Vec<Color> = albedo
+        .iter()
+        .map(|a| {
+            let safe = |x: f64| if x < 1e-3 { 1.0 } else { x };
+            Color::new(safe(a.r()), safe(a.g()), safe(a.b()))
+        })
+        .collect();
+
+    let mut current: Vec<Color> = image
+        .pixels
+        .iter()
+        .zip(&safe_albedo)
+        .map(|(&c, &a)| c / a)
+        .collect();
+    let mut next = current.clone();
+
+    let mut color_sigma = settings.color_sigma;
+    for iteration in 0..settings.iterations {
+        let step = 1 << iteration;
+        for i in 0..height {
+            for j in 0..width {
+                let p = i * width + j;
+                let mut sum = Color::BLACK;
+                let mut total_weight = 0.0;
+                for (di, hi) in KERNEL.iter().enumerate() {
+                    for (dj, hj) in KERNEL.iter().enumerate() {
+                        let qi = i as isize + (di as isize - 2) * step;
+                        let qj = j as isize + (dj as isize - 2) * step;
+                        if qi < 0 || qj < 0 || qi >= height as isize || qj >= width as isize {
+                            continue;
+                        }
+                        let q = qi as usize * width + qj as usize;
+
+                        let color_dist = squared_distance(current[p], current[q]);
+                        let albedo_dist = squared_distance(albedo[p], albedo[q]);
+                        let normal_dist = (normal[p] - normal[q]).length_squared();
+                        let weight = hi
+                            * hj
+                            * (-color_dist / (color_sigma * color_sigma)
+                                - normal_dist / (settings.normal_sigma * settings.normal_sigma)
+                                - albedo_dist / (settings.albedo_sigma * settings.albedo_sigma))
+                                .exp();
+
+                        sum = sum + current[q] * weight;
+                        total_weight += weight;
+                    }
+                }
+                // The center pixel always has a non-zero weight.
+                next[p] = sum / total_weight;
+            }
+        }
+        std::mem::swap(&mut current, &mut next);
+        color_sigma /= 2.0;
+    }
+
+    let pixels = current
+        .iter()
+        .zip(&safe_albedo)
+        .map(|(&c, &a)| c * a)
+        .collect();
+    Buffer::new(pixels, width, height)
+}
+
+/// Denoises the current estimate of a film, using the film's AOVs as guides.
+/// If the film doesn't record AOVs, the filter is only guided by the color.
+pub fn denoise_film(film: &Film, settings: &DenoiseSettings) -> Buffer {
+    let image = film.to_buffer();
+    let (albedo, normal) = match film.aov_pixels() {
+        Some(aovs) => (
+            aovs.iter().map(|aov| aov.albedo()).collect(),
+            aovs.iter().map(|aov| aov.normal()).collect(),
+        ),
+        None => (
+            vec![Color::BLACK; image.pixels.len()],
+            vec![Vec3::ZERO; image.pixels.len()],
+        ),
+    };
+    denoise(&image, &albedo, &normal, settings)
+}
+
+fn squared_distance(a: Color, b: Color) -> f64 {
+    let d = a - b;
+    d.r() * d.r() + d.g() * d.g() + d.b() * d.b()
+}
+
+/// Removes fireflies, i.e. isolated pixels that are much brighter than their
+/// surroundings because a few of their samples found a rare path carrying a
+/// lot of light. A pixel is considered a firefly if its luminance is more than
+/// `ratio` times the luminance of the brightest of its (up to 8) neighbours, in
+/// which case it is replaced with the average of its neighbours.
+///
+/// Real features of the image are almost never a single pixel wide, so they
+/// always have a neighbour that is about as bright and are left alone. Unlike
+/// clamping the samples (see `engine::Clamp`), this is done after rendering,
+/// so it can be tuned without rendering the image again.
+pub fn remove_fireflies(image: &Buffer, ratio: f64) -> Buffer {
+    let (width, height) = (image.width as isize, image.height as isize);
+    let mut pixels = image.pixels.clone();
+
+    for i in 0..height {
+        for j in 0..width {
+            let mut max_luminance: f64 = 0.0;
+            let mut sum = Color::BLACK;
+            let mut count = 0;
+            for di in -1..=1 {
+                for dj in -1..=1 {
+                    let (qi, qj) = (i + di, j + dj);
+                    if (di, dj) == (0, 0) || qi < 0 || qj < 0 || qi >= height || qj >= width {
+                        continue;
+                    }
+                    let neighbour = image.pixels[(qi * width + qj) as usize];
+                    max_luminance = max_luminance.max(neighbour.luminance());
+                    sum = sum + neighbour;
+                    count += 1;
+                }
+            }
+
+            let p = (i * width + j) as usize;
+            if count > 0 && image.pixels[p].luminance() > ratio * max_luminance {
+                pixels[p] = sum / count as f64;
+            }
+        }
+    }
+    Buffer::new(pixels, image.width, image.height)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::{random_double, seed_rng};
+
+    fn flat_image(color: Color) -> Buffer {
+        Buffer::new(vec![color; 5 * 4], 5, 4)
+    }
+
+    #[test]
+    fn test_denoise_keeps_flat_image() {
+        let image = flat_image(Color::new(0.2, 0.4, 0.6));
+        let albedo = vec![Color::new(0.5, 0.5, 0.5); 20];
+        let normal = vec![Vec3::Y; 20];
+        let denoised = denoise(&image, &albedo, &normal, &DenoiseSettings::default());
+        for color in denoised.pixels {
+            assert!((color.r() - 0.2).abs() < 1e-9);
+            assert!((color.b() - 0.6).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_denoise_keeps_edges() {
+        // Two evenly lit surfaces meeting at a vertical edge in the middle,
+        // with different albedos and normals, rendered with noisy lighting.
+        let (width, height) = (16, 16);
+        let left = |j: usize| j < width / 2;
+        let mut albedo = Vec::new();
+        let mut normal = Vec::new();
+        for _ in 0..height {
+            for j in 0..width {
+                albedo.push(Color::WHITE * if left(j) { 0.2 } else { 0.8 });
+                normal.push(if left(j) { Vec3::Y } else { Vec3::X });
+            }
+        }
+        seed_rng(7);
+        let pixels = albedo
+            .iter()
+            .map(|&a| a * (0.7 + 0.6 * random_double()))
+            .collect();
+        let image = Buffer::new(pixels, width, height);
+
+        // The mean squared error against the noise free image.
+        let error = |image: &Buffer| {
+            let sum: f64 = image
+                .pixels
+                .iter()
+                .zip(&albedo)
+                .map(|(&c, &a)| squared_distance(c, a))
+                .sum();
+            sum / image.pixels.len() as f64
+        };
+        let denoised = denoise(&image, &albedo, &normal, &DenoiseSettings::default());
+        assert!(error(&denoised) < error(&image) / 4.0);
+
+        // The filter only averages pixels on the same side of the edge, so it
+        // doesn't change the mean of the columns along the edge (unlike a plain
+        // blur, which would pull both towards 0.5).
+        let column_mean = |image: &Buffer, j: usize| {
+            let sum: f64 = (0..height).map(|i| image.pixels[i * width + j].r()).sum();
+            sum / height as f64
+        };
+        for j in [width / 2 - 1, width / 2] {
+            let (before, after) = (column_mean(&image, j), column_mean(&denoised, j));
+            assert!(
+                (after - before).abs() < 0.05 * before,
+                "{before} -> {after}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_remove_fireflies() {
+        let mut image = flat_image(Color::WHITE * 0.5);
+        image.pixels[7] = Color::WHITE * 50.0;
+        let cleaned = remove_fireflies(&image, 4.0);
+        assert!((cleaned.pixels[7].r() - 0.5).abs() < 1e-9);
+
+        // Two bright pixels next to each other are a feature, not a firefly.
+        image.pixels[8] = Color::WHITE * 50.0;
+        let cleaned = remove_fireflies(&image, 4.0);
+        assert_eq!(cleaned.pixels[7].r(), 50.0);
+    }
+}
@@ -1,8 +1,27 @@
-use rayon::prelude::IntoParallelIterator;
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
 use rayon::prelude::*;
 
 use crate::{
-    camera::Camera, color::Color, io::Buffer, ray::Ray, scene::Scene, utils::random_double,
+    aov::{AovPixel, AovSample, FirstHit},
+    camera::Camera,
+    color::Color,
+    film::{Film, Pixel},
+    filter::Filter,
+    io::{Buffer, FrameBuffer},
+    medium::Medium,
+    ray::Ray,
+    scene::Scene,
+    spectrum::Wavelengths,
+    tile::{generate_tiles, Tile, TileOrder},
+    utils::{mix_seed, random_double, seed_rng},
+    vec3::Vec3,
 };
 
 /// Renders a scene. The algorithm for rendering a scene works roughly as such:
@@ -37,42 +56,987 @@ pub fn render(
     num_samples: usize,
     max_bounces: usize,
 ) -> Buffer {
-    let max_dim = width.max(height);
-    let rand_and_norm = |x, size| {
-        (((x as f64 + random_double()) / (size - 1) as f64) * 2.0 - 1.0)
-            / (max_dim as f64 / size as f64) as f64
-    };
-    let cast_ray = |i, j| camera.cast_ray(rand_and_norm(i, height), rand_and_norm(j, width));
-
-    let pixels = (0..height)
-        .into_par_iter()
-        // .progress()
-        .flat_map(|i| {
-            (0..width).into_par_iter().map(move |j| {
-                (0..num_samples)
-                    .into_par_iter()
-                    .map(|_| trace_ray(cast_ray(i, j), max_bounces + 1, scene))
-                    .reduce(|| Color::BLACK, |acc, e| acc + e)
-                    / num_samples as f64
-            })
+    let mut film = Film::new(width, height);
+    accumulate(scene, camera, &mut film, num_samples, max_bounces);
+    film.to_buffer()
+}
+
+/// Renders a scene like [`render`], but also renders the AOVs (depth, normals,
+/// albedo, etc ..., see `aov`) of the image.
+pub fn render_aovs(
+    scene: &Scene,
+    camera: &Camera,
+    width: usize,
+    height: usize,
+    num_samples: usize,
+    max_bounces: usize,
+) -> FrameBuffer {
+    let mut film = Film::with_aovs(width, height);
+    accumulate(scene, camera, &mut film, num_samples, max_bounces);
+    film.to_frame_buffer()
+}
+
+/// Draws `num_samples` samples for each pixel of the film and adds them to
+/// it. Unlike `render`, the samples are not averaged, so this can be called
+/// repeatedly on the same film to progressively refine the image.
+///
+/// # Arguments
+///
+/// * `scene` - The scene to render.
+/// * `film` - The film to add the samples to.
+/// * `num_samples` - Number of rays to samples per pixel.
+/// * `max_bounces` - Max number of bounces for a given ray.
+pub fn accumulate(
+    scene: &Scene,
+    camera: &Camera,
+    film: &mut Film,
+    num_samples: usize,
+    max_bounces: usize,
+) {
+    let settings = RenderSettings::new(num_samples, max_bounces);
+    render_tiles(
+        scene,
+        camera,
+        film,
+        &settings,
+        |_, _| {},
+        &CancelToken::new(),
+    )
+    .expect("render can't be cancelled without a handle to the token");
+}
+
+/// Settings that control how an image is rendered.
+#[derive(Clone, Debug)]
+pub struct RenderSettings {
+    /// Number of rays to samples per pixel.
+    pub num_samples: usize,
+
+    /// Max number of bounces for a given ray.
+    pub max_bounces: usize,
+
+    /// The image is split into square tiles of this size (in pixels).
+    pub tile_size: usize,
+
+    /// The order in which the tiles are rendered.
+    pub tile_order: TileOrder,
+
+    /// The reconstruction filter used to combine the samples into pixels.
+    pub filter: Filter,
+
+    /// Limits on the brightness of a single sample.
+    pub clamp: Clamp,
+
+    /// If set, the random number generator is reseeded from this seed at the
+    /// start of each tile, which makes the render reproducible regardless of
+    /// how the tiles get scheduled on the threads. With a filter wider than a
+    /// pixel, tiles splat into their neighbours' pixels in whatever order they
+    /// finish, so the result may differ by floating point rounding.
+    pub seed: Option<u64>,
+
+    /// Whether to render in spectral mode (see `spectrum`) rather than RGB.
+    pub spectral: bool,
+
+    /// If set, pixels are sampled adaptively (see [`render_adaptive`]), and
+    /// `num_samples` is ignored.
+    pub adaptive: Option<AdaptiveSettings>,
+}
+
+impl RenderSettings {
+    pub fn new(num_samples: usize, max_bounces: usize) -> Self {
+        RenderSettings {
+            num_samples,
+            max_bounces,
+            tile_size: 32,
+            tile_order: TileOrder::Hilbert,
+            filter: Filter::default(),
+            clamp: Clamp::default(),
+            seed: None,
+            spectral: false,
+            adaptive: None,
+        }
+    }
+}
+
+/// Limits on the brightness of the light carried by a single sample.
+///
+/// Rare paths that carry a lot of light (e.g. a diffuse bounce that happens to
+/// hit a small bright light) show up as single bright pixels (fireflies),
+/// which take a huge number of samples to average away. Clamping the light
+/// carried by each path gets rid of them much faster, at the cost of making
+/// the image darker than it should be (i.e. biased). Indirect light is usually
+/// the main culprit and can be clamped much more aggressively than direct
+/// light.
+///
+/// Each limit applies to the largest channel of the light, and the other
+/// channels are scaled down by the same amount to keep the hue.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Clamp {
+    /// The limit on light that reached the camera after bouncing off a single
+    /// surface. Light seen directly by the camera is never clamped.
+    pub direct: Option<f64>,
+
+    /// The limit on light that reached the camera after bouncing off two or
+    /// more surfaces.
+    pub indirect: Option<f64>,
+}
+
+impl Clamp {
+    /// Clamps light that reached the camera after `bounces` bounces.
+    pub fn apply(&self, bounces: usize, light: Color) -> Color {
+        let limit = match bounces {
+            0 => None,
+            1 => self.direct,
+            _ => self.indirect,
+        };
+        let max = light.r().max(light.g()).max(light.b());
+        match limit {
+            Some(limit) if max > limit => light * (limit / max),
+            _ => light,
+        }
+    }
+}
+
+/// A token that can be used to cancel a render that is in progress (e.g. from
+/// another thread). Cloning the token gives another handle to the same token.
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        CancelToken(Arc::new(AtomicBool::new(false)))
+    }
+
+    /// Requests the render to stop as soon as possible.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Returned when a render was stopped via its `CancelToken`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cancelled;
+
+/// Renders the image tile by tile and adds the samples to the film. Tiles are
+/// handed out to the worker threads in the order given by
+/// `settings.tile_order`, and each tile is rendered start to finish by a
+/// single thread.
+///
+/// Every time a tile is finished, its samples are added to the film and
+/// `on_tile` is called with the tile and the current estimate of its pixels
+/// (row by row). This can be used to display the image as it is being
+/// rendered. Since `on_tile` is called from the worker threads, it needs to be
+/// `Sync` (to send the tiles over a channel, wrap the sender in a `Mutex`).
+///
+/// Each sample is splatted into the pixels around it according to
+/// `settings.filter`. Samples near the edge of a tile reach into the
+/// neighbouring tiles, so every tile is rendered into a buffer that is padded
+/// by the radius of the filter, which is then added to the film.
+///
+/// Before starting a new tile, we check if `cancel` was cancelled, in which
+/// case we stop and return `Err(Cancelled)`. Tiles that were finished before
+/// the render was cancelled are still added to the film.
+pub fn render_tiles<F>(
+    scene: &Scene,
+    camera: &Camera,
+    film: &mut Film,
+    settings: &RenderSettings,
+    on_tile: F,
+    cancel: &CancelToken,
+) -> Result<(), Cancelled>
+where
+    F: Fn(&Tile, &[Color]) + Sync,
+{
+    let (width, height) = (film.width, film.height);
+    let record_aovs = film.has_aovs();
+    let tiles = generate_tiles(width, height, settings.tile_size, settings.tile_order);
+    let film = Mutex::new(film);
+
+    tiles
+        .into_iter()
+        .enumerate()
+        .par_bridge()
+        .try_for_each(|(index, tile)| {
+            if cancel.is_cancelled() {
+                return Err(Cancelled);
+            }
+            if let Some(seed) = settings.seed {
+                seed_rng(mix_seed(seed, index as u64));
+            }
+
+            // The tile plus `padding` pixels on each side (row-wise).
+            let padding = settings.filter.padding();
+            let padded_width = tile.width + 2 * padding;
+            let padded_height = tile.height + 2 * padding;
+            let mut pixels = vec![Pixel::EMPTY; padded_width * padded_height];
+            let mut aovs = vec![AovPixel::EMPTY; tile.width * tile.height];
+
+            for (k, (i, j)) in tile.pixels().enumerate() {
+                let center = (i - tile.row + padding) * padded_width + (j - tile.col + padding);
+                // The samples drawn inside this pixel during this pass, which
+                // decide when adaptive sampling stops.
+                let mut drawn = Pixel::EMPTY;
+                while let Some(batch) = next_batch(settings, &drawn) {
+                    for _ in 0..batch {
+                        let (u, v) = (random_double(), random_double());
+                        let ray = film_ray(camera, i as f64 + u, j as f64 + v, width, height);
+                        let mut sample = AovSample::default();
+                        let color = trace_ray(
+                            ray,
+                            settings.max_bounces,
+                            settings.clamp,
+                            settings.spectral,
+                            scene,
+                            &mut sample,
+                        );
+
+                        pixels[center].record(color);
+                        drawn.add(color);
+                        for di in 0..=2 * padding {
+                            for dj in 0..=2 * padding {
+                                // The offset of the sample from the center of the
+                                // pixel it gets splatted into.
+                                let dy = u - 0.5 - (di as f64 - padding as f64);
+                                let dx = v - 0.5 - (dj as f64 - padding as f64);
+                                let weight = settings.filter.evaluate(dx, dy);
+                                if weight != 0.0 {
+                                    let index = center + di * padded_width + dj
+                                        - padding * padded_width
+                                        - padding;
+                                    pixels[index].splat(color, weight);
+                                }
+                            }
+                        }
+                        if record_aovs {
+                            aovs[k].add(&sample);
+                        }
+                    }
+                }
+            }
+
+            let estimate: Vec<Color> = {
+                let mut film = film.lock().unwrap();
+                for (k, pixel) in pixels.iter().enumerate() {
+                    // Skip the padding that falls outside of the image.
+                    let i = (tile.row + k / padded_width).checked_sub(padding);
+                    let j = (tile.col + k % padded_width).checked_sub(padding);
+                    if let (Some(i), Some(j)) = (i, j) {
+                        if i < height && j < width {
+                            film.merge_pixel(i, j, pixel);
+                        }
+                    }
+                }
+                for ((i, j), aov) in tile.pixels().zip(&aovs) {
+                    film.merge_aov_pixel(i, j, aov);
+                }
+                tile.pixels()
+                    .map(|(i, j)| film.pixel(i * width + j))
+                    .collect()
+            };
+            on_tile(&tile, &estimate);
+            Ok(())
         })
+}
+
+/// Settings for [`render_budgeted`].
+#[derive(Clone, Debug)]
+pub struct Budget {
+    /// The wall-clock time we are allowed to spend on the render.
+    pub time: Duration,
+
+    /// Stop early if the noise estimate of the image (see
+    /// `Film::noise_estimate`) drops below this value.
+    pub target_noise: Option<f64>,
+
+    /// The number of samples per pixel drawn in each pass.
+    pub samples_per_pass: usize,
+}
+
+/// The output of [`render_budgeted`].
+pub struct BudgetedRender {
+    /// The rendered image.
+    pub image: Buffer,
+
+    /// The number of samples each pixel received (flattened row-wise). Since
+    /// the last pass is usually cut short by the deadline, some pixels get one
+    /// pass worth of samples more than others.
+    pub sample_counts: Vec<usize>,
+
+    /// The number of passes that were started.
+    pub passes: usize,
+
+    /// The noise estimate of the final image.
+    pub noise: f64,
+}
+
+/// Renders the best image we can within a time budget. Instead of drawing a
+/// fixed number of samples, we keep adding passes of `samples_per_pass`
+/// samples to the image until the time runs out or (if set) the noise of the
+/// image drops below `target_noise`. If the deadline is hit in the middle of a
+/// pass, the pass is cancelled as soon as the tiles that are in progress are
+/// finished. The first pass is always completed so that every pixel has at
+/// least some samples.
+///
+/// This relies on `std::time::Instant`, so it is not available in wasm.
+///
+/// # Arguments
+///
+/// * `scene` - The scene to render.
+/// * `width` - Image width.
+/// * `height` - Image height.
+/// * `budget` - When to stop rendering.
+/// * `max_bounces` - Max number of bounces for a given ray.
+pub fn render_budgeted(
+    scene: &Scene,
+    camera: &Camera,
+    width: usize,
+    height: usize,
+    budget: &Budget,
+    max_bounces: usize,
+) -> BudgetedRender {
+    let deadline = Instant::now() + budget.time;
+    let settings = RenderSettings::new(budget.samples_per_pass, max_bounces);
+    let mut film = Film::new(width, height);
+    let mut passes = 0;
+
+    loop {
+        let cancel = CancelToken::new();
+        let check_deadline = |_: &Tile, _: &[Color]| {
+            if passes > 0 && Instant::now() >= deadline {
+                cancel.cancel();
+            }
+        };
+        let result = render_tiles(scene, camera, &mut film, &settings, check_deadline, &cancel);
+        passes += 1;
+
+        let converged = budget
+            .target_noise
+            .map_or(false, |target| film.noise_estimate() <= target);
+        if result.is_err() || converged || Instant::now() >= deadline {
+            break;
+        }
+    }
+
+    BudgetedRender {
+        image: film.to_buffer(),
+        sample_counts: film.sample_counts(),
+        passes,
+        noise: film.noise_estimate(),
+    }
+}
+
+/// Settings for [`render_adaptive`].
+#[derive(Clone, Debug)]
+pub struct AdaptiveSettings {
+    /// Number of samples every pixel receives before we start checking whether
+    /// it has converged.
+    pub min_samples: usize,
+
+    /// Hard cap on the number of samples a single pixel can receive.
+    pub max_samples: usize,
+
+    /// Number of samples taken between two convergence checks of a pixel.
+    pub batch_size: usize,
+
+    /// A pixel is considered converged once the relative standard error of its
+    /// mean luminance drops below this threshold.
+    pub noise_threshold: f64,
+
+    /// Whether to also output a heatmap of the number of samples per pixel.
+    pub heatmap: bool,
+}
+
+impl Default for AdaptiveSettings {
+    fn default() -> Self {
+        AdaptiveSettings {
+            min_samples: 16,
+            max_samples: 1024,
+            batch_size: 16,
+            noise_threshold: 0.01,
+            heatmap: false,
+        }
+    }
+}
+
+/// The output of [`render_adaptive`].
+pub struct AdaptiveRender {
+    /// The image in the film after the render.
+    pub image: Buffer,
+
+    /// The number of samples each pixel received during this render
+    /// (flattened row-wise).
+    pub sample_counts: Vec<usize>,
+
+    /// A grayscale image where the brightness of a pixel is proportional to the
+    /// number of samples it received (white means `max_samples`). Only present
+    /// if `AdaptiveSettings::heatmap` was set.
+    pub heatmap: Option<Buffer>,
+}
+
+/// Renders a scene like [`render_tiles`], but instead of taking a fixed
+/// number of samples for each pixel, we keep sampling a pixel until our
+/// estimate of it is good enough. This way flat regions (like the sky) stop
+/// early and most of the work goes towards noisy regions of the image.
+///
+/// To decide if a pixel has converged, we keep track of the mean and variance
+/// of the luminance of its samples. The standard error of the mean is given by:
+///
+///    σ_mean = √(σ² / n)
+///
+/// which we divide by the mean to get an error that is relative to the
+/// brightness of the pixel (noise is much less noticeable in bright regions).
+/// Once this relative error drops below `noise_threshold` (and the pixel has
+/// received at least `min_samples`), we stop sampling it. Only the samples
+/// drawn by this call count, not those already in the film.
+///
+/// All the other settings (filter, clamping, spectral mode, etc ...) apply as
+/// usual, the samples are added to `film` (along with its AOVs), and the
+/// render can be cancelled like with `render_tiles`.
+///
+/// # Arguments
+///
+/// * `scene` - The scene to render.
+/// * `film` - The film to add the samples to.
+/// * `settings` - How to render the image (`num_samples` is ignored).
+/// * `adaptive` - Controls the noise threshold and the min/max sample counts.
+/// * `cancel` - Stops the render when cancelled.
+pub fn render_adaptive(
+    scene: &Scene,
+    camera: &Camera,
+    film: &mut Film,
+    settings: &RenderSettings,
+    adaptive: &AdaptiveSettings,
+    cancel: &CancelToken,
+) -> Result<AdaptiveRender, Cancelled> {
+    assert!(adaptive.min_samples > 0 && adaptive.min_samples <= adaptive.max_samples);
+    assert!(adaptive.batch_size > 0);
+
+    // The film may already hold samples from earlier passes.
+    let before = film.sample_counts();
+    let settings = RenderSettings {
+        adaptive: Some(adaptive.clone()),
+        ..settings.clone()
+    };
+    render_tiles(scene, camera, film, &settings, |_, _| {}, cancel)?;
+
+    let sample_counts: Vec<usize> = (film.sample_counts().iter().zip(before))
+        .map(|(after, before)| after - before)
         .collect();
-    Buffer::new(pixels, width, height)
+    let heatmap = adaptive.heatmap.then(|| {
+        let pixels = sample_counts
+            .iter()
+            .map(|&n| Color::WHITE * (n as f64 / adaptive.max_samples as f64))
+            .collect();
+        Buffer::new(pixels, film.width, film.height)
+    });
+
+    Ok(AdaptiveRender {
+        image: film.to_buffer(),
+        sample_counts,
+        heatmap,
+    })
+}
+
+/// The number of samples to draw next for a pixel, given the samples `drawn`
+/// for it so far, or `None` once the pixel is done.
+fn next_batch(settings: &RenderSettings, drawn: &Pixel) -> Option<usize> {
+    let batch = match &settings.adaptive {
+        None => settings.num_samples - drawn.count,
+        Some(adaptive) if drawn.count < adaptive.min_samples => adaptive.min_samples - drawn.count,
+        Some(adaptive) if drawn.relative_error() < adaptive.noise_threshold => 0,
+        Some(adaptive) => adaptive.batch_size.min(adaptive.max_samples - drawn.count),
+    };
+    (batch > 0).then_some(batch)
 }
 
-fn trace_ray(ray: Ray, bounces_left: usize, scene: &Scene) -> Color {
-    // If we've exceeded the max number of bounces, return no light.
-    if bounces_left == 0 {
-        return Color::BLACK;
+/// Gets the camera ray through the point at the (fractional) row `y` and
+/// column `x` of the image, with the differentials of a pixel.
+fn film_ray(camera: &Camera, y: f64, x: f64, width: usize, height: usize) -> Ray {
+    let max_dim = width.max(height);
+    let norm = |x: f64, size| {
+        ((x / (size - 1) as f64) * 2.0 - 1.0) / (max_dim as f64 / size as f64) as f64
+    };
+    let pixel = norm(1.0, width) - norm(0.0, width);
+    camera.cast_ray(norm(y, height), norm(x, width), pixel)
+}
+
+/// The maximum number of times a path can scatter in media (see `trace_ray`).
+const MAX_SCATTERING_EVENTS: usize = 256;
+
+/// Traces a camera ray through the scene and returns the light it carries back
+/// to the camera. This follows the ray as it bounces around the scene, keeping
+/// track of the product of the attenuations of the surfaces it hit so far (the
+/// throughput), until it escapes the scene and picks up the light from the
+/// environment. The light is clamped according to `clamp`. Along the way, the
+/// AOVs of the sample are recorded in `aov`.
+///
+/// If the environment can be sampled (see `Environment::sample`), we also
+/// sample it directly at every hit (next event estimation): we pick a
+/// direction towards a bright part of the environment and, if nothing is in
+/// the way, add the light from it weighted by the material and divided by the
+/// probability of picking the direction. The light the bounced ray picks up
+/// when it escapes is then counted as well, and the two estimates are combined
+/// with multiple importance sampling (MIS): each is weighted by how likely
+/// its strategy was to pick the direction compared to the other one (the power
+/// heuristic). Sampling the environment works best for diffuse surfaces and
+/// small bright lights like the sun, and sampling the material for glossy
+/// surfaces, so this gets the best of both.
+/// Reference: Veach & Guibas, "Optimally Combining Sampling Techniques for Monte Carlo Rendering"
+///
+/// The lights of the scene (see `light`) can't be hit by rays, so they are
+/// always sampled directly at every hit. Emissive surfaces on the other hand
+/// are only found by rays that hit them.
+///
+/// While the ray travels through a medium (see `medium`), we sample the
+/// distance to its next interaction with it (free-path sampling). If that is
+/// before the next surface, the ray scatters there instead: the phase function
+/// of the medium takes the place of the material, both for sampling the
+/// lights and for picking the new direction. Paths through dense media (e.g.
+/// subsurface scattering) can scatter hundreds of times, so scattering
+/// doesn't count as a bounce, and is limited by `MAX_SCATTERING_EVENTS`
+/// instead. Crossing the boundary of a volume (see `Object::volume`) doesn't
+/// count either.
+///
+/// In spectral mode (see `spectrum`), the path carries light at randomly
+/// sampled wavelengths, and the colors it picks up along the way are converted
+/// to spectra at these wavelengths. The light is converted back to RGB as it
+/// is added to the sample.
+fn trace_ray(
+    mut ray: Ray,
+    max_bounces: usize,
+    clamp: Clamp,
+    spectral: bool,
+    scene: &Scene,
+    aov: &mut AovSample,
+) -> Color {
+    let wavelengths = spectral.then(Wavelengths::sample);
+    let upsample = |color| upsample(wavelengths, color);
+    let to_rgb = |light| to_rgb(wavelengths, light);
+    let mut throughput = Color::WHITE;
+    let mut radiance = Color::BLACK;
+    // The pdf with which the material at the last hit picked the direction
+    // of the ray, or `None` if it can't be weighted against environment
+    // sampling (for camera rays and perfectly smooth materials).
+    let mut bsdf_pdf = None;
+    // The medium the ray travels through.
+    let mut medium = scene.fog();
+    let camera = ray.origin;
+    let mut bounces = 0;
+    let mut scattering_events = 0;
+
+    while bounces <= max_bounces {
+        let hit = scene.intersect(ray);
+
+        if let Some(current) = medium {
+            let t_max = hit.map_or(f64::INFINITY, |(_, record)| record.t);
+            let sample = current.sample(ray, t_max, throughput);
+            let light = clamp.apply(bounces, to_rgb(throughput * upsample(sample.emission)));
+            aov.add_light(bounces, light);
+            radiance = radiance + light;
+            throughput = throughput * upsample(sample.weight);
+            if let Some(t) = sample.scatter {
+                if bounces == max_bounces || scattering_events == MAX_SCATTERING_EVENTS {
+                    break;
+                }
+                let point = ray.at(t);
+                let wo = -ray.direction.unit_vector();
+                let phase = current.phase();
+                let light = direct_light(
+                    scene,
+                    point,
+                    wavelengths,
+                    |_| medium,
+                    |wi| {
+                        let value = phase.eval(wo, wi);
+                        (Color::WHITE * value, value)
+                    },
+                );
+                let light = clamp.apply(bounces + 1, to_rgb(throughput * light));
+                aov.add_light(bounces + 1, light);
+                radiance = radiance + light;
+
+                // The phase function is normalized, so sampling it exactly
+                // leaves the throughput unchanged.
+                let wi = phase.sample(wo);
+                ray = Ray::new(point, wi);
+                bsdf_pdf = Some(phase.eval(wo, wi));
+                scattering_events += 1;
+                continue;
+            }
+        }
+
+        match hit {
+            // If we hit something, continue with the outgoing ray and
+            // multiply the throughput by the attenuation of the current hit.
+            Some((object_id, mut record)) => {
+                record.wavelengths = wavelengths;
+                record.set_differentials(&ray);
+                let object = scene.object(object_id);
+                let material = &object.material;
+                if material.is_interface() {
+                    // Keep the differentials, the ray goes straight through.
+                    medium = scene.medium_behind(object, &record);
+                    ray.origin = record.p;
+                    continue;
+                }
+                if let Some(normal_mapping) = &object.normal_mapping {
+                    normal_mapping.apply(&mut record);
+                }
+
+                let frame = record.frame();
+                let wo = frame.to_local(-ray.direction.unit_vector());
+                let sample = material.sample(record, wo);
+                if bounces == 0 {
+                    aov.first_hit = Some(FirstHit {
+                        depth: (record.p - camera).length(),
+                        normal: record.shading_normal,
+                        position: record.p,
+                        albedo: sample.map_or(Color::BLACK, |sample| sample.weight),
+                        object_id,
+                        material_id: scene.material_id(object_id),
+                    });
+                    aov.lobe = sample.map(|sample| sample.lobe);
+                }
+
+                let light = throughput * upsample(material.emitted(record));
+                let light = clamp.apply(bounces, to_rgb(light));
+                aov.add_light(bounces, light);
+                radiance = radiance + light;
+
+                // Light arriving from below the surface travels through the
+                // medium on the other side of it.
+                let medium_towards = |direction: Vec3| match direction.dot(record.normal) < 0.0 {
+                    true => scene.medium_behind(object, &record),
+                    false => medium,
+                };
+
+                // The values of wavelength dependent materials are already
+                // given at the wavelengths of the path.
+                let convert = |color| match material.is_spectral() {
+                    true => color,
+                    false => upsample(color),
+                };
+
+                if bounces < max_bounces {
+                    let scattering = |direction| {
+                        let wi = frame.to_local(direction);
+                        let value = convert(material.eval(record, wo, wi));
+                        (value, material.pdf(record, wo, wi))
+                    };
+                    let light =
+                        direct_light(scene, record.p, wavelengths, medium_towards, scattering);
+                    let light = clamp.apply(bounces + 1, to_rgb(throughput * light));
+                    aov.add_light(bounces + 1, light);
+                    radiance = radiance + light;
+                }
+
+                // The light is absorbed.
+                let sample = match sample {
+                    Some(sample) => sample,
+                    None => return radiance,
+                };
+                let direction = frame.to_world(sample.wi);
+                throughput = throughput * convert(sample.weight);
+                medium = medium_towards(direction);
+                // Only perfect reflections and refractions keep a footprint
+                // small enough to track, rough ones blur the texture anyway.
+                let differentials = match sample.delta {
+                    true => record.scattered_differentials(&ray, direction),
+                    false => None,
+                };
+                ray = Ray::new(record.p, direction);
+                ray.differentials = differentials;
+                bsdf_pdf = match sample.delta {
+                    true => None,
+                    false => Some(sample.pdf),
+                };
+                bounces += 1;
+            }
+            // If we haven't hit anything, add the light from the background.
+            None => {
+                let weight = match bsdf_pdf {
+                    Some(pdf) => {
+                        let direction = ray.direction.unit_vector();
+                        power_heuristic(pdf, scene.environment().pdf(direction))
+                    }
+                    None => 1.0,
+                };
+                let light = throughput * upsample(scene.get_environment_light(ray)) * weight;
+                let light = clamp.apply(bounces, to_rgb(light));
+                aov.add_light(bounces, light);
+                radiance = radiance + light;
+                return radiance;
+            }
+        }
+    }
+
+    // If we've exceeded the max number of bounces, no more light is added.
+    radiance
+}
+
+/// The weight of a sample picked with a pdf of `a` when combined with a
+/// strategy that would have picked it with a pdf of `b`:
+///
+///    w = a² / (a² + b²)
+///
+/// The weights of the two strategies sum to 1 for every direction, which
+/// keeps the combined estimate unbiased.
+fn power_heuristic(a: f64, b: f64) -> f64 {
+    if a.is_infinite() {
+        return 1.0;
+    }
+    let (a2, b2) = (a * a, b * b);
+    if a2 + b2 <= 0.0 {
+        return 0.0;
+    }
+    a2 / (a2 + b2)
+}
+
+/// The light scattered at `point` (on a surface or in a medium) from the
+/// environment and from the lights of the scene, along the incoming ray.
+/// `scattering` gives the fraction of the light arriving from a (world)
+/// direction that is scattered along the ray, and the pdf with which the
+/// material or phase function would sample that direction, and `medium` the
+/// medium the light travels through on its way from that direction. The light
+/// is returned at the `wavelengths` of the path in spectral mode.
+///
+/// The light from the environment is weighted against the light found by
+/// sampling the material (see `trace_ray`). The lights can't be hit by rays,
+/// so there is nothing to weight them against.
+fn direct_light<'a>(
+    scene: &'a Scene,
+    point: Vec3,
+    wavelengths: Option<Wavelengths>,
+    medium: impl Fn(Vec3) -> Option<&'a dyn Medium>,
+    scattering: impl Fn(Vec3) -> (Color, f64),
+) -> Color {
+    let mut total = Color::BLACK;
+
+    let environment = scene.environment();
+    if let Some((direction, light_pdf)) = environment.sample() {
+        let (value, pdf) = scattering(direction);
+        if light_pdf > 0.0 && value.luminance() > 0.0 {
+            let transmittance =
+                scene.transmittance(point, direction, f64::INFINITY, medium(direction));
+            let light = upsample(wavelengths, transmittance * environment.eval(direction));
+            let weight = power_heuristic(light_pdf, pdf);
+            total = total + value * light * (weight / light_pdf);
+        }
+    }
+
+    for light in scene.lights() {
+        let sample = match light.sample(point) {
+            Some(sample) => sample,
+            None => continue,
+        };
+        let (value, _) = scattering(sample.direction);
+        if value.luminance() > 0.0 {
+            let transmittance = scene.transmittance(
+                point,
+                sample.direction,
+                sample.distance,
+                medium(sample.direction),
+            );
+            let light = upsample(wavelengths, transmittance * sample.irradiance);
+            total = total + value * light;
+        }
+    }
+    total
+}
+
+/// Converts a color of the scene to the values carried by a path: in spectral
+/// mode, the values of its spectrum at the wavelengths of the path.
+fn upsample(wavelengths: Option<Wavelengths>, color: Color) -> Color {
+    match wavelengths {
+        Some(wavelengths) => wavelengths.upsample(color),
+        None => color,
+    }
+}
+
+/// Converts the light carried by a path back to RGB.
+fn to_rgb(wavelengths: Option<Wavelengths>, light: Color) -> Color {
+    match wavelengths {
+        Some(wavelengths) => wavelengths.to_rgb(light),
+        None => light,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{environment::Environment, material::Lambertian, object::Object, shape::Quad};
+
+    /// White in front of the camera and black behind it.
+    #[derive(Debug)]
+    struct Front;
+
+    impl Environment for Front {
+        fn eval(&self, direction: Vec3) -> Color {
+            match direction.z < 0.0 {
+                true => Color::WHITE,
+                false => Color::BLACK,
+            }
+        }
+    }
+
+    /// The top half of the image sees the environment, which is the same
+    /// everywhere in view, so those pixels have no noise at all. The bottom
+    /// half sees a diffuse floor, which is lit by the bright front half and
+    /// the black back half of the environment, so those pixels are noisy.
+    fn half_noisy_scene() -> Scene {
+        let mut scene = Scene::new();
+        scene.set_environment(Box::new(Front));
+        let floor = Quad::new(
+            Vec3::new(-100.0, -1.0, 100.0),
+            Vec3::X * 200.0,
+            -Vec3::Z * 200.0,
+        );
+        scene.add_object(Object::new(
+            Box::new(floor),
+            Box::new(Lambertian::new(Color::WHITE * 0.5)),
+        ));
+        scene
     }
 
-    match scene.hit_closest_object(ray) {
-        // If we hit something, trace the outgoing ray and multiply
-        // it's color by the attenuation of the current hit.
-        Some((outgoing_ray, attenuation)) => {
-            attenuation * trace_ray(outgoing_ray, bounces_left - 1, scene)
+    fn adaptive_render(adaptive: &AdaptiveSettings) -> AdaptiveRender {
+        let mut film = Film::new(8, 8);
+        let mut settings = RenderSettings::new(1, 4);
+        settings.seed = Some(1);
+        let scene = half_noisy_scene();
+        let camera = Camera::default();
+        render_adaptive(
+            &scene,
+            &camera,
+            &mut film,
+            &settings,
+            adaptive,
+            &CancelToken::new(),
+        )
+        .unwrap()
+    }
+
+    fn budgeted_render(time: Duration, target_noise: Option<f64>) -> BudgetedRender {
+        let budget = Budget {
+            time,
+            target_noise,
+            samples_per_pass: 2,
+        };
+        let (scene, camera) = (half_noisy_scene(), Camera::default());
+        render_budgeted(&scene, &camera, 16, 16, &budget, 4)
+    }
+
+    #[test]
+    fn test_budget_deadline() {
+        let time = Duration::from_millis(100);
+        let start = Instant::now();
+        let render = budgeted_render(time, None);
+        let elapsed = start.elapsed();
+        // The pass in progress finishes its tiles, which only takes a moment
+        // for such a small image.
+        assert!(
+            elapsed >= time && elapsed < time + Duration::from_secs(1),
+            "{elapsed:?}"
+        );
+        assert!(render.passes > 1);
+
+        // The last pass may have been cut short.
+        let full = 2 * render.passes;
+        for &n in &render.sample_counts {
+            assert!(
+                n == full || n == full - 2,
+                "{n} samples in {} passes",
+                render.passes
+            );
         }
-        // If we haven't hit anything, return the light from the background.
-        None => scene.get_environment_light(ray),
+    }
+
+    #[test]
+    fn test_budget_target_noise() {
+        // Any noise is good enough, so we stop after the first pass, long
+        // before the deadline.
+        let start = Instant::now();
+        let render = budgeted_render(Duration::from_secs(60), Some(f64::INFINITY));
+        assert!(start.elapsed() < Duration::from_secs(10));
+        assert_eq!(render.passes, 1);
+        assert!(render.sample_counts.iter().all(|&n| n == 2));
+
+        // A noise that can't be reached doesn't stop the render.
+        let render = budgeted_render(Duration::from_millis(50), Some(0.0));
+        assert!(render.passes > 1);
+        assert!(render.noise > 0.0);
+    }
+
+    #[test]
+    fn test_adaptive_sample_caps() {
+        let mut adaptive = AdaptiveSettings {
+            min_samples: 4,
+            max_samples: 10,
+            batch_size: 4,
+            noise_threshold: 0.0,
+            heatmap: false,
+        };
+        // Nothing is ever good enough, so every pixel gets the maximum, even
+        // though it isn't a multiple of the batch size.
+        let render = adaptive_render(&adaptive);
+        assert!(render.sample_counts.iter().all(|&n| n == 10));
+
+        // Everything is good enough, so every pixel gets the minimum.
+        adaptive.noise_threshold = f64::INFINITY;
+        let render = adaptive_render(&adaptive);
+        assert!(render.sample_counts.iter().all(|&n| n == 4));
+    }
+
+    #[test]
+    fn test_adaptive_stops_when_converged() {
+        let adaptive = AdaptiveSettings {
+            min_samples: 8,
+            max_samples: 64,
+            batch_size: 8,
+            noise_threshold: 1e-3,
+            heatmap: true,
+        };
+        let render = adaptive_render(&adaptive);
+        // The fourth row sees both the environment and the floor.
+        let (top, bottom) = (&render.sample_counts[..24], &render.sample_counts[32..]);
+        assert!(top.iter().all(|&n| n == 8), "{top:?}");
+        assert!(bottom.iter().all(|&n| n == 64), "{bottom:?}");
+
+        let heatmap = render.heatmap.unwrap();
+        for (pixel, &n) in heatmap.pixels.iter().zip(&render.sample_counts) {
+            assert_eq!(pixel.r(), n as f64 / 64.0);
+        }
+    }
+
+    #[test]
+    fn test_adaptive_adds_to_film() {
+        // The samples (and AOVs) end up in the film like with any other
+        // render, and the counts only cover the samples of this render.
+        let scene = half_noisy_scene();
+        let camera = Camera::default();
+        let settings = RenderSettings::new(1, 4);
+        let adaptive = AdaptiveSettings {
+            min_samples: 4,
+            max_samples: 4,
+            ..AdaptiveSettings::default()
+        };
+        let mut film = Film::with_aovs(8, 8);
+        let render = |film: &mut Film, cancel: &CancelToken| {
+            render_adaptive(&scene, &camera, film, &settings, &adaptive, cancel)
+        };
+        render(&mut film, &CancelToken::new()).unwrap();
+        let second = render(&mut film, &CancelToken::new()).unwrap();
+        assert!(second.sample_counts.iter().all(|&n| n == 4));
+        assert!(film.sample_counts().iter().all(|&n| n == 8));
+        let aovs = film.aov_pixels().unwrap();
+        assert!(aovs[0].depth().is_infinite() && aovs[63].depth().is_finite());
+
+        // A cancelled render stops before drawing any samples.
+        let cancel = CancelToken::new();
+        cancel.cancel();
+        assert!(render(&mut film, &cancel).is_err());
+        assert!(film.sample_counts().iter().all(|&n| n == 8));
     }
 }
@@ -0,0 +1,44 @@
+mod constant;
+mod map;
+mod physical_sky;
+mod sky;
+
+pub use constant::Constant;
+pub use map::EnvironmentMap;
+pub use physical_sky::PhysicalSky;
+pub use sky::Sky;
+
+use std::fmt::Debug;
+
+use crate::{color::Color, vec3::Vec3};
+
+/// The environment surrounds the scene and determines the light arriving from
+/// infinitely far away, i.e. the light picked up by rays that escape the
+/// scene (e.g. the sky).
+///
+/// Environments implement `Debug` so that the contents of a scene can be
+/// hashed (see `Scene::content_hash`).
+pub trait Environment: Sync + Debug {
+    /// The light arriving from the given direction.
+    fn eval(&self, direction: Vec3) -> Color;
+
+    /// Picks a random direction, ideally proportionally to the light arriving
+    /// from it. Returns the (unit) direction and its probability density (with
+    /// respect to solid angle).
+    ///
+    /// Environments where most of the light comes from a small part of the
+    /// sphere (like the sun) should implement this, so the renderer can send
+    /// rays towards the bright parts directly instead of waiting for rays to
+    /// hit them by chance. Returns `None` if the environment can't be sampled,
+    /// which is fine for environments that are (mostly) smooth.
+    fn sample(&self) -> Option<(Vec3, f64)> {
+        None
+    }
+
+    /// The probability density (with respect to solid angle) of `sample`
+    /// picking the given direction.
+    fn pdf(&self, direction: Vec3) -> f64 {
+        let _ = direction;
+        0.0
+    }
+}
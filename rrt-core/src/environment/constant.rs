@@ -0,0 +1,21 @@
+use crate::{color::Color, vec3::Vec3};
+
+use super::Environment;
+
+/// The same light from every direction.
+#[derive(Debug)]
+pub struct Constant {
+    color: Color,
+}
+
+impl Constant {
+    pub fn new(color: Color) -> Self {
+        Constant { color }
+    }
+}
+
+impl Environment for Constant {
+    fn eval(&self, _direction: Vec3) -> Color {
+        self.color
+    }
+}
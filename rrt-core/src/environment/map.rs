@@ -0,0 +1,254 @@
+use std::{f64::consts::PI, fmt, io, path::Path};
+
+use crate::{color::Color, io::Buffer, utils::random_double, vec3::Vec3};
+
+use super::Environment;
+
+/// Lighting from an equirectangular (latitude-longitude) HDR image that
+/// surrounds the scene, a.k.a. image-based lighting.
+///
+/// The columns of the image map to the angle around the y axis (the center of
+/// the image is in the -z direction) and the rows map to the angle from the +y
+/// axis (the top row is straight up).
+///
+/// Most of the light of an outdoor map usually comes from a tiny part of it
+/// (the sun), which paths that bounce in random directions rarely find. To
+/// converge quickly, the map can be sampled proportionally to the luminance of
+/// its pixels (see `sample`).
+pub struct EnvironmentMap {
+    image: Buffer,
+
+    /// The rotation of the map around the y axis (in degrees).
+    rotation: f64,
+
+    /// A multiplier for the brightness of the map.
+    intensity: f64,
+
+    /// The cumulative distribution of the sampling weights within each row,
+    /// normalized to [0, 1] (`width + 1` values per row).
+    conditional_cdfs: Vec<f64>,
+
+    /// The cumulative distribution of the total sampling weight of each row,
+    /// normalized to [0, 1] (`height + 1` values).
+    marginal_cdf: Vec<f64>,
+
+    /// Identifies the contents of the image (see `Scene::content_hash`).
+    hash: u64,
+}
+
+impl EnvironmentMap {
+    pub fn new(image: Buffer) -> Self {
+        let (width, height) = (image.width, image.height);
+
+        // We sample a pixel with a probability proportional to its luminance
+        // times the solid angle it covers. Rows near the poles get squashed
+        // into a tiny solid angle, which is proportional to sin(θ).
+        let mut conditional_cdfs = Vec::with_capacity((width + 1) * height);
+        let mut marginal_cdf = vec![0.0];
+        for i in 0..height {
+            let sin_theta = (PI * (i as f64 + 0.5) / height as f64).sin();
+            let row = &image.pixels[i * width..(i + 1) * width];
+            let start = conditional_cdfs.len();
+            let mut total = 0.0;
+            conditional_cdfs.push(0.0);
+            for color in row {
+                total += color.luminance().max(0.0) * sin_theta;
+                conditional_cdfs.push(total);
+            }
+            normalize(&mut conditional_cdfs[start..]);
+            marginal_cdf.push(marginal_cdf[i] + total);
+        }
+        normalize(&mut marginal_cdf);
+
+        EnvironmentMap {
+            hash: image.content_hash(),
+            image,
+            rotation: 0.0,
+            intensity: 1.0,
+            conditional_cdfs,
+            marginal_cdf,
+        }
+    }
+
+    /// Loads an environment map from a Radiance HDR (`.hdr`) or OpenEXR
+    /// (`.exr`) file (see `Buffer::from_hdr` and `Buffer::from_exr`).
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Ok(EnvironmentMap::new(Buffer::load(path)?))
+    }
+
+    /// Rotates the map around the y axis by the given angle (in degrees).
+    pub fn set_rotation(&mut self, degrees: f64) {
+        self.rotation = degrees;
+    }
+
+    /// Scales the brightness of the map.
+    pub fn set_intensity(&mut self, intensity: f64) {
+        self.intensity = intensity;
+    }
+
+    /// Maps a direction to coordinates in the unit square.
+    fn direction_to_uv(&self, direction: Vec3) -> (f64, f64) {
+        let d = direction.rotate_about_y_axis(-self.rotation).unit_vector();
+        let u = 0.5 + d.x.atan2(-d.z) / (2.0 * PI);
+        let v = d.y.clamp(-1.0, 1.0).acos() / PI;
+        (u, v)
+    }
+
+    /// Maps coordinates in the unit square to a direction.
+    fn uv_to_direction(&self, u: f64, v: f64) -> Vec3 {
+        let (theta, phi) = (PI * v, 2.0 * PI * (u - 0.5));
+        let direction = Vec3::new(
+            theta.sin() * phi.sin(),
+            theta.cos(),
+            -theta.sin() * phi.cos(),
+        );
+        direction.rotate_about_y_axis(self.rotation)
+    }
+
+    /// Returns sin(θ) and the column and row of the pixel at (u, v).
+    fn pixel_at(&self, u: f64, v: f64) -> (f64, usize, usize) {
+        let (width, height) = (self.image.width, self.image.height);
+        let j = ((u * width as f64) as usize).min(width - 1);
+        let i = ((v * height as f64) as usize).min(height - 1);
+        ((PI * v).sin(), j, i)
+    }
+}
+
+impl Environment for EnvironmentMap {
+    fn eval(&self, direction: Vec3) -> Color {
+        let (u, v) = self.direction_to_uv(direction);
+        let (_, j, i) = self.pixel_at(u, v);
+        self.image.pixels[i * self.image.width + j] * self.intensity
+    }
+
+    /// Picks a random direction proportionally to the brightness of the map.
+    /// The map can't be sampled if it is completely black.
+    ///
+    /// We first pick a row using the marginal distribution, then a column
+    /// within the row using the conditional distribution of the row, and
+    /// finally a uniformly random point within the pixel. The density of the
+    /// resulting point (u, v) in the unit square is the probability of its
+    /// pixel times the number of pixels, and since the map covers a sphere
+    /// via θ = πv and φ = 2πu, with dω = sin(θ) dθ dφ:
+    ///
+    ///    p(ω) = p(u, v) / (2π² sin(θ))
+    fn sample(&self) -> Option<(Vec3, f64)> {
+        let (width, height) = (self.image.width, self.image.height);
+        if self.marginal_cdf[height] == 0.0 {
+            return None;
+        }
+        let i = sample_cdf(&self.marginal_cdf, random_double());
+        let row = &self.conditional_cdfs[i * (width + 1)..(i + 1) * (width + 1)];
+        let j = sample_cdf(row, random_double());
+
+        let u = (j as f64 + random_double()) / width as f64;
+        let v = (i as f64 + random_double()) / height as f64;
+        let direction = self.uv_to_direction(u, v);
+        Some((direction, self.pdf(direction)))
+    }
+
+    fn pdf(&self, direction: Vec3) -> f64 {
+        let (u, v) = self.direction_to_uv(direction);
+        let (sin_theta, j, i) = self.pixel_at(u, v);
+        if sin_theta <= 0.0 {
+            return 0.0;
+        }
+        let width = self.image.width;
+        let row_probability = self.marginal_cdf[i + 1] - self.marginal_cdf[i];
+        let row = &self.conditional_cdfs[i * (width + 1)..];
+        let pixel_probability = row_probability * (row[j + 1] - row[j]);
+        let pdf_uv = pixel_probability * (width * self.image.height) as f64;
+        pdf_uv / (2.0 * PI * PI * sin_theta)
+    }
+}
+
+/// The map is large, so instead of its pixels we show a hash of them. This is
+/// also what goes into `Scene::content_hash`.
+impl fmt::Debug for EnvironmentMap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("EnvironmentMap")
+            .field("width", &self.image.width)
+            .field("height", &self.image.height)
+            .field("hash", &self.hash)
+            .field("rotation", &self.rotation)
+            .field("intensity", &self.intensity)
+            .finish()
+    }
+}
+
+/// Divides a cumulative distribution by its last value, so that it ends at 1.
+/// Distributions that sum to 0 are left as is.
+fn normalize(cdf: &mut [f64]) {
+    let total = cdf[cdf.len() - 1];
+    if total > 0.0 {
+        for value in cdf.iter_mut() {
+            *value /= total;
+        }
+    }
+}
+
+/// Finds the bucket `k` such that `cdf[k] <= x < cdf[k + 1]`, skipping buckets
+/// with zero probability.
+fn sample_cdf(cdf: &[f64], x: f64) -> usize {
+    let k = cdf.partition_point(|&value| value <= x);
+    k.clamp(1, cdf.len() - 1) - 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::seed_rng;
+
+    /// A dim map with a single bright pixel (the "sun").
+    fn sun_map() -> EnvironmentMap {
+        let (width, height) = (16, 8);
+        let mut pixels = vec![Color::WHITE * 0.1; width * height];
+        pixels[2 * width + 5] = Color::WHITE * 1000.0;
+        let mut map = EnvironmentMap::new(Buffer::new(pixels, width, height));
+        map.set_rotation(30.0);
+        map
+    }
+
+    #[test]
+    fn test_uv_roundtrip() {
+        let map = sun_map();
+        for (u, v) in [(0.1, 0.2), (0.5, 0.5), (0.9, 0.7)] {
+            let (u2, v2) = map.direction_to_uv(map.uv_to_direction(u, v));
+            assert!((u - u2).abs() < 1e-9 && (v - v2).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_pdf_integrates_to_one() {
+        // Integrate the pdf over the sphere on a grid that is uniform in z and
+        // φ (and thus in solid angle).
+        let map = sun_map();
+        let n = 400;
+        let mut total = 0.0;
+        for a in 0..n {
+            for b in 0..2 * n {
+                let z = 2.0 * (a as f64 + 0.5) / n as f64 - 1.0;
+                let phi = PI * (b as f64 + 0.5) / n as f64;
+                let r = (1.0 - z * z).sqrt();
+                total += map.pdf(Vec3::new(r * phi.cos(), r * phi.sin(), z));
+            }
+        }
+        let integral = total / (2 * n * n) as f64 * 4.0 * PI;
+        assert!((integral - 1.0).abs() < 0.01, "integral = {}", integral);
+    }
+
+    #[test]
+    fn test_sample_finds_the_sun() {
+        seed_rng(7);
+        let map = sun_map();
+        let n = 1000;
+        let bright = (0..n)
+            .filter(|_| {
+                let (direction, pdf) = map.sample().unwrap();
+                assert!((pdf - map.pdf(direction)).abs() <= 1e-9 * pdf);
+                map.eval(direction).r() > 1.0
+            })
+            .count();
+        assert!(bright > n * 9 / 10);
+    }
+}
@@ -0,0 +1,287 @@
+use std::f64::consts::PI;
+
+use crate::{
+    color::Color,
+    utils::{random_double, random_in_cone, random_unit_vector},
+    vec3::Vec3,
+};
+
+use super::Environment;
+
+/// The luminances of the model are in kcd/m². We scale them down so that a
+/// white diffuse surface lit by the sun and sky ends up with a brightness
+/// around 1.
+const SCALE: f64 = 0.05;
+
+/// The luminance of the sun (in kcd/m²) before it passes through the
+/// atmosphere.
+const SUN_LUMINANCE: f64 = 1.6e6;
+
+/// The angular radius of the sun as seen from the earth (in degrees).
+const SUN_RADIUS: f64 = 0.265;
+
+/// The probability of `sample` picking a direction towards the sun.
+const SUN_PROBABILITY: f64 = 0.5;
+
+/// An analytic model of a clear sky, lit by the sun.
+/// Reference: Preetham et al., "A Practical Analytic Model for Daylight"
+///
+/// The model gives the luminance Y and the chromaticity (x, y) of the sky for
+/// a view direction at an angle θ from the zenith and an angle γ from the sun,
+/// each as a value at the zenith times the Perez distribution:
+///
+///    F(θ, γ) = (1 + A e^(B / cos(θ))) (1 + C e^(D γ) + E cos²(γ))
+///
+///    Y(θ, γ) = Y_zenith F(θ, γ) / F(0, θ_sun)
+///
+/// where the coefficients A to E (different for Y, x and y) and the zenith
+/// values are fits to simulations of the atmosphere, which depend on the
+/// turbidity (the amount of haze: 2 is a very clear sky, 10 is hazy) and the
+/// angle of the sun from the zenith. The model only covers the sky above the
+/// horizon, below the horizon we show the sky at the horizon.
+///
+/// The sun itself is a small disk, whose light is reddened by the atmosphere
+/// the lower it is. It is much brighter than the sky, so `sample` sends half of
+/// the rays towards it.
+#[derive(Debug)]
+pub struct PhysicalSky {
+    /// The (unit) direction towards the sun.
+    sun_direction: Vec3,
+
+    /// The amount of haze in the atmosphere.
+    turbidity: f64,
+
+    /// The angular radius of the sun disk (in degrees).
+    sun_radius: f64,
+
+    /// A multiplier for the brightness of the sky and sun.
+    intensity: f64,
+
+    /// The Perez coefficients A to E for Y, x and y.
+    coefficients: [[f64; 5]; 3],
+
+    /// Y, x and y at the zenith divided by F(0, θ_sun).
+    zenith: [f64; 3],
+
+    /// The light of the sun disk.
+    sun: Color,
+}
+
+impl PhysicalSky {
+    /// Creates a sky with the sun in the given direction (which is clamped to
+    /// the horizon if it points down).
+    pub fn new(sun_direction: Vec3, turbidity: f64) -> Self {
+        let mut sky = PhysicalSky {
+            sun_direction: Vec3::Y,
+            turbidity,
+            sun_radius: SUN_RADIUS,
+            intensity: 1.0,
+            coefficients: [[0.0; 5]; 3],
+            zenith: [0.0; 3],
+            sun: Color::BLACK,
+        };
+        sky.set_sun(sun_direction, turbidity);
+        sky
+    }
+
+    /// Moves the sun and changes the turbidity.
+    pub fn set_sun(&mut self, sun_direction: Vec3, turbidity: f64) {
+        let mut direction = sun_direction.unit_vector();
+        direction.y = direction.y.max(0.0);
+        self.sun_direction = direction.unit_vector();
+        self.turbidity = turbidity;
+
+        let t = turbidity;
+        self.coefficients = [
+            [
+                0.1787 * t - 1.4630,
+                -0.3554 * t + 0.4275,
+                -0.0227 * t + 5.3251,
+                0.1206 * t - 2.5771,
+                -0.0670 * t + 0.3703,
+            ],
+            [
+                -0.0193 * t - 0.2592,
+                -0.0665 * t + 0.0008,
+                -0.0004 * t + 0.2125,
+                -0.0641 * t - 0.8989,
+                -0.0033 * t + 0.0452,
+            ],
+            [
+                -0.0167 * t - 0.2608,
+                -0.0950 * t + 0.0092,
+                -0.0079 * t + 0.2102,
+                -0.0441 * t - 1.6537,
+                -0.0109 * t + 0.0529,
+            ],
+        ];
+
+        let theta_sun = self.sun_direction.y.acos();
+        let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta_sun);
+        let zenith_y = (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192;
+        let polynomial = |c: [[f64; 4]; 3]| {
+            let powers = [theta_sun.powi(3), theta_sun.powi(2), theta_sun, 1.0];
+            let dot = |row: [f64; 4]| (0..4).map(|k| row[k] * powers[k]).sum::<f64>();
+            t * t * dot(c[0]) + t * dot(c[1]) + dot(c[2])
+        };
+        let zenith_x = polynomial([
+            [0.00166, -0.00375, 0.00209, 0.0],
+            [-0.02903, 0.06377, -0.03202, 0.00394],
+            [0.11693, -0.21196, 0.06052, 0.25886],
+        ]);
+        let zenith_chromaticity_y = polynomial([
+            [0.00275, -0.00610, 0.00317, 0.0],
+            [-0.04214, 0.08970, -0.04153, 0.00516],
+            [0.15346, -0.26756, 0.06670, 0.26688],
+        ]);
+        let zenith = [zenith_y, zenith_x, zenith_chromaticity_y];
+        self.zenith = [0, 1, 2].map(|k| zenith[k] / perez(self.coefficients[k], 1.0, theta_sun));
+
+        self.sun = sun_color(theta_sun, t);
+    }
+
+    /// Sets the angular radius of the sun disk (in degrees). The brightness of
+    /// the disk is scaled so that the sun casts the same amount of light
+    /// regardless of its size, so a bigger sun just gives softer shadows.
+    pub fn set_sun_radius(&mut self, degrees: f64) {
+        self.sun_radius = degrees;
+    }
+
+    /// Scales the brightness of the sky and sun.
+    pub fn set_intensity(&mut self, intensity: f64) {
+        self.intensity = intensity;
+    }
+
+    /// The cosine of the angular radius of the sun disk.
+    fn cos_sun_radius(&self) -> f64 {
+        self.sun_radius.to_radians().cos()
+    }
+
+    /// The solid angle covered by the sun disk.
+    fn sun_solid_angle(&self) -> f64 {
+        2.0 * PI * (1.0 - self.cos_sun_radius())
+    }
+}
+
+impl Environment for PhysicalSky {
+    fn eval(&self, direction: Vec3) -> Color {
+        let direction = direction.unit_vector();
+        let cos_theta = direction.y.max(0.01);
+        let cos_gamma = direction.dot(self.sun_direction).clamp(-1.0, 1.0);
+        let gamma = cos_gamma.acos();
+
+        let [luminance, x, y] =
+            [0, 1, 2].map(|k| self.zenith[k] * perez(self.coefficients[k], cos_theta, gamma));
+        let mut color = xyy_to_rgb(x, y, luminance);
+
+        if cos_gamma >= self.cos_sun_radius() {
+            // Keep the light of the sun the same when its size changes.
+            let default_solid_angle = 2.0 * PI * (1.0 - SUN_RADIUS.to_radians().cos());
+            color = color + self.sun * (default_solid_angle / self.sun_solid_angle());
+        }
+        color * (SCALE * self.intensity)
+    }
+
+    /// Picks a direction towards the sun disk with probability
+    /// `SUN_PROBABILITY`, and otherwise a uniformly random direction. The
+    /// density is the mix of the densities of both strategies.
+    fn sample(&self) -> Option<(Vec3, f64)> {
+        let direction = if random_double() < SUN_PROBABILITY {
+            random_in_cone(self.sun_direction, self.cos_sun_radius())
+        } else {
+            random_unit_vector()
+        };
+        Some((direction, self.pdf(direction)))
+    }
+
+    fn pdf(&self, direction: Vec3) -> f64 {
+        let uniform = 1.0 / (4.0 * PI);
+        let in_sun = direction.unit_vector().dot(self.sun_direction) >= self.cos_sun_radius();
+        let sun = if in_sun {
+            1.0 / self.sun_solid_angle()
+        } else {
+            0.0
+        };
+        SUN_PROBABILITY * sun + (1.0 - SUN_PROBABILITY) * uniform
+    }
+}
+
+/// The Perez sky distribution F(θ, γ).
+fn perez([a, b, c, d, e]: [f64; 5], cos_theta: f64, gamma: f64) -> f64 {
+    (1.0 + a * (b / cos_theta).exp()) * (1.0 + c * (d * gamma).exp() + e * gamma.cos().powi(2))
+}
+
+/// Converts a color given as chromaticity (x, y) and luminance Y to linear sRGB.
+fn xyy_to_rgb(x: f64, y: f64, luminance: f64) -> Color {
+    if y <= 0.0 {
+        return Color::BLACK;
+    }
+    let big_x = x * luminance / y;
+    let big_z = (1.0 - x - y) * luminance / y;
+    Color::new(
+        3.2406 * big_x - 1.5372 * luminance - 0.4986 * big_z,
+        -0.9689 * big_x + 1.8758 * luminance + 0.0415 * big_z,
+        0.0557 * big_x - 0.2040 * luminance + 1.0570 * big_z,
+    )
+}
+
+/// The light of the sun after passing through the atmosphere at an angle
+/// `theta_sun` from the zenith. The atmosphere scatters light away from the
+/// sun's rays, by molecules (Rayleigh scattering, which mostly affects blue
+/// light) and by haze (aerosols, which grow with the turbidity). The fraction
+/// of light that makes it through is:
+///
+///    exp(-m (0.008735 λ^-4.08 + β λ^-1.3))
+///
+/// where λ is the wavelength in μm, β = 0.04608 T - 0.04586 and m is the
+/// relative optical mass of the air along the sun's rays (1 at the zenith).
+/// We evaluate it at a typical wavelength for each of red, green and blue.
+fn sun_color(theta_sun: f64, turbidity: f64) -> Color {
+    let beta = (0.04608 * turbidity - 0.04586).max(0.0);
+    let air_mass = 1.0 / (theta_sun.cos() + 0.15 * (93.885 - theta_sun.to_degrees()).powf(-1.253));
+    let transmittance = |lambda: f64| {
+        (-air_mass * (0.008735 * lambda.powf(-4.08) + beta * lambda.powf(-1.3))).exp()
+    };
+    Color::new(
+        transmittance(0.68),
+        transmittance(0.55),
+        transmittance(0.44),
+    ) * SUN_LUMINANCE
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::seed_rng;
+
+    fn sky() -> PhysicalSky {
+        PhysicalSky::new(Vec3::new(0.3, 0.5, -1.0), 3.0)
+    }
+
+    #[test]
+    fn test_sky_is_blue_and_sun_is_bright() {
+        let sky = sky();
+        let zenith = sky.eval(Vec3::Y);
+        assert!(zenith.b() > zenith.r() && zenith.r() > 0.0);
+        let sun = sky.eval(sky.sun_direction);
+        assert!(sun.luminance() > 1000.0 * zenith.luminance());
+        // The sun is reddened by the atmosphere.
+        assert!(sun.r() > sun.b());
+    }
+
+    #[test]
+    fn test_sample_matches_pdf() {
+        seed_rng(3);
+        let sky = sky();
+        let mut hits_sun = 0;
+        for _ in 0..1000 {
+            let (direction, pdf) = sky.sample().unwrap();
+            assert!((direction.length() - 1.0).abs() < 1e-9);
+            assert_eq!(pdf, sky.pdf(direction));
+            if pdf > 1.0 {
+                hits_sun += 1;
+            }
+        }
+        assert!((400..600).contains(&hits_sun));
+    }
+}
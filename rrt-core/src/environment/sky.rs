@@ -0,0 +1,34 @@
+use crate::{color::Color, vec3::Vec3};
+
+use super::Environment;
+
+/// A simple sky that blends between two colors depending on how far up the
+/// direction points.
+#[derive(Debug)]
+pub struct Sky {
+    /// The color straight down.
+    bottom: Color,
+
+    /// The color straight up.
+    top: Color,
+}
+
+impl Sky {
+    pub fn new(bottom: Color, top: Color) -> Self {
+        Sky { bottom, top }
+    }
+}
+
+impl Default for Sky {
+    /// White below and blue above.
+    fn default() -> Self {
+        Sky::new(Color::WHITE, Color::SKY_BLUE)
+    }
+}
+
+impl Environment for Sky {
+    fn eval(&self, direction: Vec3) -> Color {
+        let t = (direction.unit_vector().y + 1.0) / 2.0;
+        self.bottom * (1.0 - t) + self.top * t
+    }
+}
@@ -0,0 +1,279 @@
+use crate::{
+    aov::{aov_layers, AovPixel},
+    color::Color,
+    io::{Buffer, FrameBuffer},
+};
+
+/// The accumulated samples of a single pixel.
+///
+/// Samples are weighted by a reconstruction filter (see `filter`) and can
+/// contribute to the pixels around the one they were drawn in, so the estimate
+/// of the pixel is a weighted average. The sample statistics (`count`,
+/// `luminance_sum` and `luminance_squared_sum`) only cover the samples drawn
+/// inside the pixel, and are not weighted.
+#[derive(Clone, Copy, Debug)]
+pub struct Pixel {
+    /// The weighted sum of the samples.
+    pub sum: Color,
+
+    /// The sum of the weights of the samples.
+    pub weight_sum: f64,
+
+    /// The sum of the luminance of the samples, and of its square, used to
+    /// estimate the variance (i.e. the noise) of the pixel.
+    pub luminance_sum: f64,
+    pub luminance_squared_sum: f64,
+
+    /// The number of samples.
+    pub count: usize,
+}
+
+impl Pixel {
+    pub const EMPTY: Pixel = Pixel {
+        sum: Color::BLACK,
+        weight_sum: 0.0,
+        luminance_sum: 0.0,
+        luminance_squared_sum: 0.0,
+        count: 0,
+    };
+
+    /// Adds a single sample drawn inside the pixel with a weight of 1 (i.e.
+    /// with a box filter).
+    pub fn add(&mut self, color: Color) {
+        self.splat(color, 1.0);
+        self.record(color);
+    }
+
+    /// Adds a sample to the estimate of the pixel with the given filter weight.
+    pub fn splat(&mut self, color: Color, weight: f64) {
+        self.sum = self.sum + color * weight;
+        self.weight_sum += weight;
+    }
+
+    /// Adds a sample drawn inside the pixel to the sample statistics, without
+    /// adding it to the estimate (use `splat` for that).
+    pub fn record(&mut self, color: Color) {
+        self.luminance_sum += color.luminance();
+        self.luminance_squared_sum += color.luminance() * color.luminance();
+        self.count += 1;
+    }
+
+    /// Adds all the samples of another pixel to this pixel.
+    pub fn merge(&mut self, other: &Pixel) {
+        self.sum = self.sum + other.sum;
+        self.weight_sum += other.weight_sum;
+        self.luminance_sum += other.luminance_sum;
+        self.luminance_squared_sum += other.luminance_squared_sum;
+        self.count += other.count;
+    }
+
+    /// The estimate of the pixel color (the weighted average of the samples).
+    /// Pixels without any samples are black.
+    pub fn mean(&self) -> Color {
+        if self.weight_sum <= 0.0 {
+            return Color::BLACK;
+        }
+        self.sum / self.weight_sum
+    }
+
+    /// The standard error of the mean luminance divided by the mean luminance.
+    /// The variance is estimated from the (unweighted) samples drawn inside
+    /// the pixel as:
+    ///
+    ///    σ² = (Σx² - n * mean²) / (n - 1)
+    ///
+    /// and the standard error of the mean is √(σ² / n). We put a floor on the
+    /// mean so that very dark pixels don't dominate the error.
+    pub fn relative_error(&self) -> f64 {
+        if self.count < 2 {
+            return f64::INFINITY;
+        }
+        let n = self.count as f64;
+        let mean = self.luminance_sum / n;
+        let variance = ((self.luminance_squared_sum - n * mean * mean) / (n - 1.0)).max(0.0);
+        (variance / n).sqrt() / mean.max(1e-2)
+    }
+}
+
+/// An accumulation buffer. Instead of storing the final color of each pixel,
+/// we store the raw sum of all the samples drawn for the pixel as well as the
+/// number of samples. This lets us keep adding samples to the image over time
+/// (e.g. for progressive rendering) without losing precision, since the
+/// estimate of a pixel (the average) is only computed when it is read.
+pub struct Film {
+    /// Image width.
+    pub width: usize,
+
+    /// Image height.
+    pub height: usize,
+
+    /// The accumulated samples of each pixel (flattened row-wise).
+    pixels: Vec<Pixel>,
+
+    /// The accumulated AOVs of each pixel, if the film records AOVs.
+    aovs: Option<Vec<AovPixel>>,
+}
+
+impl Film {
+    pub fn new(width: usize, height: usize) -> Self {
+        Film {
+            width,
+            height,
+            pixels: vec![Pixel::EMPTY; width * height],
+            aovs: None,
+        }
+    }
+
+    /// Creates a film that also records AOVs (see `aov`).
+    pub fn with_aovs(width: usize, height: usize) -> Self {
+        Film {
+            aovs: Some(vec![AovPixel::EMPTY; width * height]),
+            ..Film::new(width, height)
+        }
+    }
+
+    /// Creates a film from previously accumulated pixels (flattened row-wise).
+    pub fn from_pixels(pixels: Vec<Pixel>, width: usize, height: usize) -> Self {
+        assert!(pixels.len() == width * height);
+        Film {
+            width,
+            height,
+            pixels,
+            aovs: None,
+        }
+    }
+
+    /// The accumulated samples of each pixel (row-wise).
+    pub fn pixels(&self) -> &[Pixel] {
+        &self.pixels
+    }
+
+    /// Adds all the samples of another film of the same size to this film.
+    pub fn merge(&mut self, other: &Film) {
+        assert!(self.width == other.width && self.height == other.height);
+        for (pixel, other) in self.pixels.iter_mut().zip(&other.pixels) {
+            pixel.merge(other);
+        }
+        if let (Some(aovs), Some(other)) = (&mut self.aovs, &other.aovs) {
+            for (aov, other) in aovs.iter_mut().zip(other) {
+                aov.merge(other);
+            }
+        }
+    }
+
+    /// Starts (or stops) recording AOVs. The samples accumulated so far are
+    /// kept, but the AOVs only cover the samples added from now on.
+    pub fn set_aovs(&mut self, enabled: bool) {
+        match (enabled, &self.aovs) {
+            (true, None) => self.aovs = Some(vec![AovPixel::EMPTY; self.pixels.len()]),
+            (false, Some(_)) => self.aovs = None,
+            _ => {}
+        }
+    }
+
+    /// Whether the film records AOVs.
+    pub fn has_aovs(&self) -> bool {
+        self.aovs.is_some()
+    }
+
+    /// The accumulated AOVs of each pixel (row-wise), if the film records AOVs.
+    pub fn aov_pixels(&self) -> Option<&[AovPixel]> {
+        self.aovs.as_deref()
+    }
+
+    /// Adds a sample to the pixel at row `i` and column `j`.
+    pub fn add_sample(&mut self, i: usize, j: usize, color: Color) {
+        self.pixels[i * self.width + j].add(color);
+    }
+
+    /// Adds the samples accumulated in `pixel` to the pixel at row `i` and
+    /// column `j`.
+    pub fn merge_pixel(&mut self, i: usize, j: usize, pixel: &Pixel) {
+        self.pixels[i * self.width + j].merge(pixel);
+    }
+
+    /// Adds the accumulated AOVs in `aov` to the pixel at row `i` and column
+    /// `j`. Does nothing if the film doesn't record AOVs.
+    pub fn merge_aov_pixel(&mut self, i: usize, j: usize, aov: &AovPixel) {
+        if let Some(aovs) = &mut self.aovs {
+            aovs[i * self.width + j].merge(aov);
+        }
+    }
+
+    /// The current estimate of the pixel at the given (flattened) index.
+    pub fn pixel(&self, index: usize) -> Color {
+        self.pixels[index].mean()
+    }
+
+    /// The number of samples drawn for the pixel at the given (flattened) index.
+    pub fn sample_count(&self, index: usize) -> usize {
+        self.pixels[index].count
+    }
+
+    /// The number of samples drawn for each pixel (row-wise).
+    pub fn sample_counts(&self) -> Vec<usize> {
+        self.pixels.iter().map(|pixel| pixel.count).collect()
+    }
+
+    /// An estimate of how noisy the image is, computed as the average relative
+    /// error of the pixels (see `Pixel::relative_error`).
+    pub fn noise_estimate(&self) -> f64 {
+        let total: f64 = self.pixels.iter().map(Pixel::relative_error).sum();
+        total / self.pixels.len() as f64
+    }
+
+    /// Iterates over the current estimate of each pixel (row-wise).
+    pub fn estimate(&self) -> impl Iterator<Item = Color> + '_ {
+        self.pixels.iter().map(Pixel::mean)
+    }
+
+    /// Copies the current estimate of the image into a `Buffer`.
+    pub fn to_buffer(&self) -> Buffer {
+        Buffer::new(self.estimate().collect(), self.width, self.height)
+    }
+
+    /// Copies the current estimate of the image and all of its AOVs into a
+    /// multi-channel frame buffer.
+    pub fn to_frame_buffer(&self) -> FrameBuffer {
+        FrameBuffer {
+            beauty: self.to_buffer(),
+            layers: self.aovs.as_deref().map(aov_layers).unwrap_or_default(),
+        }
+    }
+
+    /// Throws away all the samples.
+    pub fn clear(&mut self) {
+        self.pixels.fill(Pixel::EMPTY);
+        if let Some(aovs) = &mut self.aovs {
+            aovs.fill(AovPixel::EMPTY);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_relative_error() {
+        // Luminances 1, 2, 3 and 6: the mean is 3 and the sample variance is
+        // (4 + 1 + 0 + 9) / 3 = 14 / 3.
+        let mut pixel = Pixel::EMPTY;
+        for x in [1.0, 2.0, 3.0, 6.0] {
+            pixel.record(Color::WHITE * x);
+        }
+        let expected = (14.0 / 3.0 / 4.0f64).sqrt() / 3.0;
+        assert!((pixel.relative_error() - expected).abs() < 1e-12);
+
+        // The filter weights of the estimate (here, a sample from a
+        // neighbouring pixel) don't change the noise of the pixel itself.
+        pixel.splat(Color::WHITE * 100.0, 0.5);
+        assert!((pixel.relative_error() - expected).abs() < 1e-12);
+
+        // Merging keeps the statistics.
+        let mut merged = Pixel::EMPTY;
+        merged.merge(&pixel);
+        assert!((merged.relative_error() - expected).abs() < 1e-12);
+        assert!(Pixel::EMPTY.relative_error().is_infinite());
+    }
+}
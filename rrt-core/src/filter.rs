@@ -0,0 +1,175 @@
+use std::f64::consts::PI;
+
+/// The shape of a pixel reconstruction filter (see [`Filter`]).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FilterKind {
+    /// Every sample within the radius gets the same weight. With a radius of
+    /// 0.5 this is the plain average of the samples inside each pixel.
+    Box,
+
+    /// The weight falls off linearly with the distance to the pixel center.
+    Tent,
+
+    /// A Gaussian with a standard deviation of a third of the radius, shifted
+    /// down so that it reaches zero at the radius.
+    Gaussian,
+
+    /// The Mitchell-Netravali cubic (with B = C = 1/3), which has small
+    /// negative lobes that keep edges crisp without much ringing.
+    Mitchell,
+
+    /// The Blackman-Harris window, which is similar to a Gaussian but falls
+    /// off to zero more smoothly.
+    BlackmanHarris,
+}
+
+impl FilterKind {
+    /// All the filter kinds.
+    pub const ALL: [FilterKind; 5] = [
+        FilterKind::Box,
+        FilterKind::Tent,
+        FilterKind::Gaussian,
+        FilterKind::Mitchell,
+        FilterKind::BlackmanHarris,
+    ];
+
+    /// A short lowercase name, e.g. for command line flags.
+    pub fn name(&self) -> &'static str {
+        match self {
+            FilterKind::Box => "box",
+            FilterKind::Tent => "tent",
+            FilterKind::Gaussian => "gaussian",
+            FilterKind::Mitchell => "mitchell",
+            FilterKind::BlackmanHarris => "blackman-harris",
+        }
+    }
+}
+
+/// A pixel reconstruction filter. Rather than only counting towards the pixel
+/// it was drawn in, each sample is splatted into all the pixels whose center
+/// is within `radius` (in pixels) of the sample, weighted by the filter:
+///
+///    pixel = Σ w(x_i - center) * L_i / Σ w(x_i - center)
+///
+/// Wider, smoother filters give better anti-aliasing than averaging the
+/// samples inside of each pixel (a box filter with a radius of 0.5), at the
+/// cost of a slightly softer image.
+///
+/// The filter is separable, i.e. w(x, y) = w(x) * w(y).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Filter {
+    pub kind: FilterKind,
+
+    /// The radius of the filter in pixels (the weight is zero beyond it).
+    pub radius: f64,
+}
+
+impl Filter {
+    pub fn new(kind: FilterKind, radius: f64) -> Self {
+        assert!(radius > 0.0);
+        Filter { kind, radius }
+    }
+
+    /// The weight of a sample that is `dx` columns and `dy` rows away from the
+    /// center of a pixel.
+    pub fn evaluate(&self, dx: f64, dy: f64) -> f64 {
+        self.evaluate_1d(dx) * self.evaluate_1d(dy)
+    }
+
+    /// The number of pixels on each side of a pixel that a sample drawn inside
+    /// of it can reach.
+    pub fn padding(&self) -> usize {
+        (self.radius - 0.5).max(0.0).ceil() as usize
+    }
+
+    fn evaluate_1d(&self, x: f64) -> f64 {
+        if x.abs() >= self.radius {
+            return 0.0;
+        }
+        // The distance to the center relative to the radius (in [0, 1)).
+        let t = x.abs() / self.radius;
+        match self.kind {
+            FilterKind::Box => 1.0,
+            FilterKind::Tent => 1.0 - t,
+            FilterKind::Gaussian => {
+                let gaussian = |t: f64| (-4.5 * t * t).exp();
+                gaussian(t) - gaussian(1.0)
+            }
+            FilterKind::Mitchell => mitchell(2.0 * t),
+            FilterKind::BlackmanHarris => {
+                0.35875
+                    + 0.48829 * (PI * t).cos()
+                    + 0.14128 * (2.0 * PI * t).cos()
+                    + 0.01168 * (3.0 * PI * t).cos()
+            }
+        }
+    }
+}
+
+impl Default for Filter {
+    /// A box filter with a radius of half a pixel, which simply averages the
+    /// samples inside of each pixel.
+    fn default() -> Self {
+        Filter::new(FilterKind::Box, 0.5)
+    }
+}
+
+/// The Mitchell-Netravali cubic for x in [0, 2] (with B = C = 1/3).
+/// Reference: Mitchell & Netravali, "Reconstruction Filters in Computer Graphics"
+fn mitchell(x: f64) -> f64 {
+    let (b, c) = (1.0 / 3.0, 1.0 / 3.0);
+    let (x2, x3) = (x * x, x * x * x);
+    let value = if x < 1.0 {
+        (12.0 - 9.0 * b - 6.0 * c) * x3 + (-18.0 + 12.0 * b + 6.0 * c) * x2 + (6.0 - 2.0 * b)
+    } else {
+        (-b - 6.0 * c) * x3
+            + (6.0 * b + 30.0 * c) * x2
+            + (-12.0 * b - 48.0 * c) * x
+            + (8.0 * b + 24.0 * c)
+    };
+    value / 6.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_zero_outside_radius() {
+        for kind in FilterKind::ALL {
+            let filter = Filter::new(kind, 1.5);
+            assert!(filter.evaluate(0.0, 0.0) > 0.0);
+            assert_eq!(filter.evaluate(1.5, 0.0), 0.0);
+            assert_eq!(filter.evaluate(0.0, -2.0), 0.0);
+            // The filters (except box) go to zero continuously at the radius.
+            if kind != FilterKind::Box {
+                assert!(filter.evaluate(1.4999, 0.0).abs() < 1e-3);
+            }
+        }
+    }
+
+    #[test]
+    fn test_symmetric() {
+        for kind in FilterKind::ALL {
+            let filter = Filter::new(kind, 2.0);
+            for x in [0.1, 0.7, 1.3, 1.9] {
+                assert_eq!(filter.evaluate(x, 0.3), filter.evaluate(-x, 0.3));
+                assert_eq!(filter.evaluate(x, 0.3), filter.evaluate(0.3, x));
+            }
+        }
+    }
+
+    #[test]
+    fn test_padding() {
+        assert_eq!(Filter::default().padding(), 0);
+        assert_eq!(Filter::new(FilterKind::Gaussian, 1.0).padding(), 1);
+        assert_eq!(Filter::new(FilterKind::Mitchell, 2.0).padding(), 2);
+    }
+
+    #[test]
+    fn test_mitchell_has_negative_lobes() {
+        let filter = Filter::new(FilterKind::Mitchell, 2.0);
+        assert!(filter.evaluate(1.5, 0.0) < 0.0);
+        assert!((mitchell(1.0) - mitchell(0.9999)).abs() < 1e-3);
+    }
+}
@@ -1,6 +1,13 @@
-use crate::{ray::Ray, vec3::Vec3};
+use crate::{
+    onb::Onb,
+    ray::{Differentials, Ray},
+    spectrum::Wavelengths,
+    texture::Footprint,
+    vec3::Vec3,
+};
 
 /// Stores information about a hit between a ray and some object.
+#[derive(Clone, Copy, Debug)]
 pub struct HitRecord {
     /// The time of the hit.
     pub t: f64,
@@ -8,8 +15,47 @@ pub struct HitRecord {
     /// The point of contact between the object and ray.
     pub p: Vec3,
 
-    /// The normal vector at the point of the hit.
+    /// The normal vector of the surface at the point of the hit.
     pub normal: Vec3,
+
+    /// The normal that materials shade the hit with (see `frame`). This is
+    /// the surface normal, unless it is interpolated across a mesh (see
+    /// `Mesh`) or perturbed by a normal or bump map (see `NormalMapping`).
+    /// Only the surface normal tells which side of the surface a point is on.
+    pub shading_normal: Vec3,
+
+    /// The texture coordinates of the hit (see `texture`), both usually in
+    /// [0, 1].
+    pub u: f64,
+    pub v: f64,
+
+    /// The derivatives of the position of the hit with respect to the
+    /// texture coordinates, dp/du and dp/dv (not normalized). They are
+    /// tangent to the surface, and give the orientation of the texture on it,
+    /// e.g. for normal maps.
+    pub tangent: Vec3,
+    pub bitangent: Vec3,
+
+    /// How much the position of the hit changes from a pixel to the next one
+    /// on the image (see `set_differentials`), zero if unknown.
+    pub dpdx: Vec3,
+    pub dpdy: Vec3,
+
+    /// How much the texture coordinates change from a pixel to the next one,
+    /// used to filter textures (see `Texture::lookup`).
+    pub footprint: Footprint,
+
+    /// The direction of the ray that hit the object (not normalized).
+    pub incoming: Vec3,
+
+    /// Whether the ray hit the outside of the object. The normal always faces
+    /// the ray (see `correct_normal_direction`), so this tells us whether it
+    /// was flipped, e.g. to know whether a ray is entering or leaving glass.
+    pub front_face: bool,
+
+    /// The wavelengths carried by the ray in spectral mode (see `spectrum`),
+    /// which the renderer sets before shading the hit.
+    pub wavelengths: Option<Wavelengths>,
 }
 
 impl HitRecord {
@@ -18,12 +64,131 @@ impl HitRecord {
             t: f64::INFINITY,
             p: Vec3::ZERO,
             normal: Vec3::ZERO,
+            shading_normal: Vec3::ZERO,
+            u: 0.0,
+            v: 0.0,
+            tangent: Vec3::ZERO,
+            bitangent: Vec3::ZERO,
+            dpdx: Vec3::ZERO,
+            dpdy: Vec3::ZERO,
+            footprint: Footprint::default(),
+            incoming: Vec3::ZERO,
+            front_face: true,
+            wavelengths: None,
         }
     }
 
     pub fn correct_normal_direction(&mut self, ray: Ray) {
-        if self.normal.dot(ray.direction) > 0.0 {
+        self.incoming = ray.direction;
+        self.front_face = self.normal.dot(ray.direction) <= 0.0;
+        if !self.front_face {
             self.normal = -self.normal;
+            self.shading_normal = -self.shading_normal;
         }
     }
+
+    /// The local shading frame of the hit, in which materials work (see
+    /// `Material`), with the shading normal as its z axis.
+    pub fn frame(&self) -> Onb {
+        Onb::from_w(self.shading_normal)
+    }
+
+    /// Computes the footprint of the pixel around the hit from the ray
+    /// differentials of the ray that hit it (see `Differentials`). The offset
+    /// rays hit the plane tangent to the surface at p + dp/dx and p + dp/dy,
+    /// and the changes of the texture coordinates follow from the tangents:
+    ///
+    ///    dp/dx = du/dx * dp/du + dv/dx * dp/dv
+    ///
+    /// which we solve in the least squares sense, since dp/dx isn't exactly
+    /// in the plane of the tangents on curved surfaces. Rays without
+    /// differentials leave the footprint empty.
+    /// Reference: Pharr et al., "Physically Based Rendering", section 10.1.1
+    pub fn set_differentials(&mut self, ray: &Ray) {
+        self.dpdx = Vec3::ZERO;
+        self.dpdy = Vec3::ZERO;
+        self.footprint = Footprint::default();
+        let differentials = match ray.differentials {
+            Some(differentials) => differentials,
+            None => return,
+        };
+        let n = self.normal;
+        let offset = |origin: Vec3, direction: Vec3| {
+            let t = n.dot(self.p - origin) / n.dot(direction);
+            let offset = origin + direction * t - self.p;
+            match t.is_finite() && offset.length_squared().is_finite() {
+                true => Some(offset),
+                false => None,
+            }
+        };
+        let dpdx = offset(differentials.x_origin, differentials.x_direction);
+        let dpdy = offset(differentials.y_origin, differentials.y_direction);
+        let (dpdx, dpdy) = match (dpdx, dpdy) {
+            (Some(dpdx), Some(dpdy)) => (dpdx, dpdy),
+            _ => return,
+        };
+        self.dpdx = dpdx;
+        self.dpdy = dpdy;
+
+        let (a, b) = (self.tangent, self.bitangent);
+        let (aa, ab, bb) = (a.dot(a), a.dot(b), b.dot(b));
+        let determinant = aa * bb - ab * ab;
+        if determinant.abs() < 1e-18 {
+            return;
+        }
+        let solve = |d: Vec3| {
+            let (ad, bd) = (a.dot(d), b.dot(d));
+            let du = (bb * ad - ab * bd) / determinant;
+            let dv = (aa * bd - ab * ad) / determinant;
+            (du.clamp(-1e8, 1e8), dv.clamp(-1e8, 1e8))
+        };
+        let (dudx, dvdx) = solve(dpdx);
+        let (dudy, dvdy) = solve(dpdy);
+        self.footprint = Footprint {
+            dudx,
+            dvdx,
+            dudy,
+            dvdy,
+        };
+    }
+
+    /// The ray differentials of the ray leaving the hit in `direction` after
+    /// a perfectly smooth bounce (see `BsdfSample::delta`), so that textures
+    /// seen in mirrors and through glass are filtered too. The offset rays
+    /// leave from p + dp/dx and p + dp/dy, and are reflected or refracted
+    /// like the ray itself, ignoring the curvature of the surface (so the
+    /// footprint is too small after curved mirrors). The relative index of
+    /// refraction is found from the ray and the direction, and is taken to
+    /// be 1 at normal incidence, where it can't be.
+    pub fn scattered_differentials(&self, ray: &Ray, direction: Vec3) -> Option<Differentials> {
+        let differentials = ray.differentials?;
+        let n = self.shading_normal;
+        let incoming = ray.direction.unit_vector();
+        let outgoing = direction.unit_vector();
+        let tangential = |v: Vec3| v - n * v.dot(n);
+        let bend = |v: Vec3| -> Option<Vec3> {
+            let v = v.unit_vector();
+            if outgoing.dot(n) > 0.0 {
+                return Some(v - n * (2.0 * v.dot(n)));
+            }
+            // Refraction scales the tangential part of the direction by the
+            // ratio of the sines of the angles with the normal (Snell's law).
+            let sin_ratio = match tangential(incoming).length() {
+                sin if sin > 1e-3 => tangential(outgoing).length() / sin,
+                _ => 1.0,
+            };
+            let t = tangential(v) * sin_ratio;
+            let sin2 = t.length_squared();
+            match sin2 < 1.0 {
+                true => Some(t - n * (1.0 - sin2).sqrt()),
+                false => None,
+            }
+        };
+        Some(Differentials {
+            x_origin: self.p + self.dpdx,
+            x_direction: bend(differentials.x_direction)?,
+            y_origin: self.p + self.dpdy,
+            y_direction: bend(differentials.y_direction)?,
+        })
+    }
 }
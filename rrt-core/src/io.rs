@@ -1,4 +1,9 @@
-use crate::color::Color;
+use std::{
+    io::{self, ErrorKind},
+    path::Path,
+};
+
+use crate::{color::Color, utils::hash_bytes};
 
 /// Stores the rendered image and provides utility methods to convert it to
 /// various output formats.
@@ -29,4 +34,519 @@ impl Buffer {
         }
         s
     }
+
+    /// Loads a Radiance HDR (`.hdr`) or OpenEXR (`.exr`) image, depending on
+    /// the extension of the file (see `from_hdr` and `from_exr`).
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Buffer> {
+        let path = path.as_ref();
+        let bytes = std::fs::read(path)?;
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("hdr") => Buffer::from_hdr(&bytes),
+            Some("exr") => Buffer::from_exr(&bytes),
+            _ => Err(io::Error::new(
+                ErrorKind::InvalidInput,
+                "images must be .hdr or .exr files",
+            )),
+        }
+    }
+
+    /// A hash of the pixels of the image. Images are too large to be shown in
+    /// full by the `Debug` of the things that hold them (e.g. textures), so
+    /// they show this instead (see `Scene::content_hash`).
+    pub fn content_hash(&self) -> u64 {
+        let bytes: Vec<u8> = self
+            .pixels
+            .iter()
+            .flat_map(|c| [c.r(), c.g(), c.b()])
+            .flat_map(f64::to_le_bytes)
+            .collect();
+        hash_bytes(&bytes)
+    }
+
+    /// Decodes a Radiance HDR (`.hdr`) image, which stores each pixel as an
+    /// 8-bit mantissa per channel plus a shared 8-bit exponent (RGBE),
+    /// optionally run-length encoded per scanline. Only the standard
+    /// orientation (`-Y height +X width`) is supported.
+    /// Reference: https://radsite.lbl.gov/radiance/refer/filefmts.pdf
+    pub fn from_hdr(bytes: &[u8]) -> io::Result<Buffer> {
+        let mut reader = ByteReader::new(bytes);
+
+        // The header is a list of text lines, ended by an empty line.
+        if !reader.line()?.starts_with("#?") {
+            return Err(invalid_data("not a radiance hdr file"));
+        }
+        loop {
+            let line = reader.line()?;
+            if line.is_empty() {
+                break;
+            }
+            if line.starts_with("FORMAT=") && line != "FORMAT=32-bit_rle_rgbe" {
+                return Err(invalid_data("unsupported hdr pixel format"));
+            }
+        }
+        let resolution = reader.line()?;
+        let (height, width) = match resolution.split_whitespace().collect::<Vec<_>>()[..] {
+            ["-Y", height, "+X", width] => (parse_size(height)?, parse_size(width)?),
+            _ => return Err(invalid_data("unsupported hdr orientation")),
+        };
+
+        let mut pixels = Vec::with_capacity(width * height);
+        let mut scanline = vec![[0u8; 4]; width];
+        for _ in 0..height {
+            // New style run-length encoded scanlines start with the bytes 2, 2
+            // followed by the width. Anything else is a flat scanline.
+            let header = reader.peek(4);
+            let is_rle = (8..0x8000).contains(&width)
+                && header.map_or(false, |h| {
+                    h[0] == 2 && h[1] == 2 && ((h[2] as usize) << 8 | h[3] as usize) == width
+                });
+            if is_rle {
+                reader.bytes(4)?;
+                // Each of the 4 components is encoded separately, as a list of
+                // runs (count > 128) and literal sequences (count <= 128).
+                for component in 0..4 {
+                    let mut x = 0;
+                    while x < width {
+                        let count = reader.byte()? as usize;
+                        let (count, run) = if count > 128 {
+                            (count - 128, Some(reader.byte()?))
+                        } else {
+                            (count, None)
+                        };
+                        if count == 0 || x + count > width {
+                            return Err(invalid_data("bad hdr scanline"));
+                        }
+                        for pixel in &mut scanline[x..x + count] {
+                            pixel[component] = match run {
+                                Some(value) => value,
+                                None => reader.byte()?,
+                            };
+                        }
+                        x += count;
+                    }
+                }
+            } else {
+                for pixel in &mut scanline {
+                    pixel.copy_from_slice(reader.bytes(4)?);
+                }
+            }
+            pixels.extend(scanline.iter().map(|&[r, g, b, e]| {
+                if e == 0 {
+                    return Color::BLACK;
+                }
+                let scale = 2f64.powi(e as i32 - 136);
+                Color::new(r as f64 * scale, g as f64 * scale, b as f64 * scale)
+            }));
+        }
+        Ok(Buffer::new(pixels, width, height))
+    }
+
+    /// Decodes the R, G and B channels of an OpenEXR image. Only uncompressed
+    /// single-part scanline images (like the ones written by
+    /// `FrameBuffer::to_exr`) with 16-bit or 32-bit float channels are
+    /// supported.
+    /// Reference: https://openexr.com/en/latest/OpenEXRFileLayout.html
+    pub fn from_exr(bytes: &[u8]) -> io::Result<Buffer> {
+        let mut reader = ByteReader::new(bytes);
+        if reader.u32()? != 20000630 {
+            return Err(invalid_data("not an exr file"));
+        }
+        // Bits 9, 11 and 12 of the version field flag tiled, deep and
+        // multi-part files.
+        if reader.u32()? & 0x1a00 != 0 {
+            return Err(invalid_data("only scanline exr files are supported"));
+        }
+
+        // (channel name, pixel type), in the order they are stored.
+        let mut channels: Vec<(String, u32)> = Vec::new();
+        let mut window = None;
+        let mut compression = 0;
+        loop {
+            let name = reader.string()?;
+            if name.is_empty() {
+                break;
+            }
+            let kind = reader.string()?;
+            let size = reader.u32()? as usize;
+            let mut value = ByteReader::new(reader.bytes(size)?);
+            match (name.as_str(), kind.as_str()) {
+                ("channels", "chlist") => loop {
+                    let channel = value.string()?;
+                    if channel.is_empty() {
+                        break;
+                    }
+                    let pixel_type = value.u32()?;
+                    value.bytes(4)?; // pLinear and reserved
+                    if value.u32()? != 1 || value.u32()? != 1 {
+                        return Err(invalid_data("subsampled exr channels are not supported"));
+                    }
+                    channels.push((channel, pixel_type));
+                },
+                ("compression", "compression") => compression = value.byte()?,
+                ("dataWindow", "box2i") => {
+                    let mut coords = [0; 4];
+                    for coord in &mut coords {
+                        *coord = value.u32()? as i32;
+                    }
+                    window = Some(coords);
+                }
+                _ => {}
+            }
+        }
+        if compression != 0 {
+            return Err(invalid_data("compressed exr files are not supported"));
+        }
+        let [x_min, y_min, x_max, y_max] =
+            window.ok_or_else(|| invalid_data("exr file has no data window"))?;
+        if x_max < x_min || y_max < y_min {
+            return Err(invalid_data("bad exr data window"));
+        }
+        let width = (x_max as i64 - x_min as i64 + 1) as usize;
+        let height = (y_max as i64 - y_min as i64 + 1) as usize;
+        // Every channel of every pixel takes at least 2 bytes, so a window
+        // larger than that is corrupt (and would be a huge allocation).
+        let fits = width
+            .checked_mul(height)
+            .and_then(|n| n.checked_mul(2 * channels.len()))
+            .map_or(false, |size| size <= bytes.len());
+        if !fits {
+            return Err(invalid_data("exr data window is larger than the file"));
+        }
+
+        let find = |name: &str| {
+            channels
+                .iter()
+                .position(|(channel, _)| channel == name)
+                .ok_or_else(|| invalid_data("exr file has no R, G and B channels"))
+        };
+        let rgb = [find("R")?, find("G")?, find("B")?];
+
+        // Skip the offset table, without compression each chunk is a single
+        // scanline.
+        reader.bytes(height * 8)?;
+        let mut values = vec![[0.0; 3]; width * height];
+        for _ in 0..height {
+            let y = (reader.u32()? as i32)
+                .checked_sub(y_min)
+                .and_then(|y| usize::try_from(y).ok())
+                .filter(|&y| y < height)
+                .ok_or_else(|| invalid_data("bad exr scanline"))?;
+            reader.u32()?; // size of the chunk
+            for (c, (_, pixel_type)) in channels.iter().enumerate() {
+                for x in 0..width {
+                    let value = match pixel_type {
+                        0 => reader.u32()? as f64,
+                        1 => half_to_f64(reader.u16()?),
+                        2 => f32::from_bits(reader.u32()?) as f64,
+                        _ => return Err(invalid_data("unknown exr pixel type")),
+                    };
+                    if let Some(k) = rgb.iter().position(|&i| i == c) {
+                        values[y * width + x][k] = value;
+                    }
+                }
+            }
+        }
+        let pixels = values
+            .iter()
+            .map(|&[r, g, b]| Color::new(r, g, b))
+            .collect();
+        Ok(Buffer::new(pixels, width, height))
+    }
+}
+
+/// A named image with one or more channels per pixel (e.g. a normal layer has
+/// the channels X, Y and Z).
+pub struct Layer {
+    /// The name of the layer.
+    pub name: String,
+
+    /// The names of the channels.
+    pub channels: Vec<String>,
+
+    /// The values of the channels, interleaved per pixel (flattened row-wise).
+    pub data: Vec<f64>,
+}
+
+impl Layer {
+    pub fn new<const N: usize>(
+        name: &str,
+        channels: &[&str; N],
+        pixels: impl Iterator<Item = [f64; N]>,
+    ) -> Self {
+        Layer {
+            name: name.to_string(),
+            channels: channels.iter().map(|c| c.to_string()).collect(),
+            data: pixels.flatten().collect(),
+        }
+    }
+
+    /// The value of a channel of the pixel at the given (flattened) index.
+    pub fn get(&self, index: usize, channel: usize) -> f64 {
+        self.data[index * self.channels.len() + channel]
+    }
+}
+
+/// A multi-channel frame buffer, made of the final (beauty) image and any
+/// number of additional layers (e.g. AOVs).
+pub struct FrameBuffer {
+    /// The final image.
+    pub beauty: Buffer,
+
+    /// Additional layers, each with the same size as the beauty image.
+    pub layers: Vec<Layer>,
+}
+
+impl FrameBuffer {
+    /// Finds a layer by name.
+    pub fn layer(&self, name: &str) -> Option<&Layer> {
+        self.layers.iter().find(|layer| layer.name == name)
+    }
+
+    /// Encodes all the layers in a single (uncompressed, 32-bit float)
+    /// multi-layer OpenEXR file. The beauty image is stored in the R, G and B
+    /// channels, so it's what viewers show by default, and the other layers
+    /// use the `layer.channel` naming convention (e.g. `normal.X`).
+    /// Reference: https://openexr.com/en/latest/OpenEXRFileLayout.html
+    pub fn to_exr(&self) -> Vec<u8> {
+        let (width, height) = (self.beauty.width, self.beauty.height);
+
+        // (channel name, layer index (None for beauty), channel index).
+        let mut channels: Vec<(String, Option<usize>, usize)> = ["R", "G", "B"]
+            .iter()
+            .enumerate()
+            .map(|(c, name)| (name.to_string(), None, c))
+            .collect();
+        for (l, layer) in self.layers.iter().enumerate() {
+            for (c, name) in layer.channels.iter().enumerate() {
+                channels.push((format!("{}.{}", layer.name, name), Some(l), c));
+            }
+        }
+        // The spec requires the channels to be sorted by name.
+        channels.sort_by(|a, b| a.0.cmp(&b.0));
+
+        let mut out = Vec::new();
+        out.extend_from_slice(&20000630u32.to_le_bytes()); // magic number
+        out.extend_from_slice(&2u32.to_le_bytes()); // version 2, scanline file
+
+        let mut attribute = |name: &str, kind: &str, value: &[u8]| {
+            out.extend_from_slice(name.as_bytes());
+            out.push(0);
+            out.extend_from_slice(kind.as_bytes());
+            out.push(0);
+            out.extend_from_slice(&(value.len() as i32).to_le_bytes());
+            out.extend_from_slice(value);
+        };
+
+        let mut chlist = Vec::new();
+        for (name, _, _) in &channels {
+            chlist.extend_from_slice(name.as_bytes());
+            chlist.push(0);
+            chlist.extend_from_slice(&2i32.to_le_bytes()); // pixel type: FLOAT
+            chlist.extend_from_slice(&[0, 0, 0, 0]); // pLinear and reserved
+            chlist.extend_from_slice(&1i32.to_le_bytes()); // x sampling
+            chlist.extend_from_slice(&1i32.to_le_bytes()); // y sampling
+        }
+        chlist.push(0);
+
+        let window: Vec<u8> = [0, 0, width as i32 - 1, height as i32 - 1]
+            .iter()
+            .flat_map(|v| v.to_le_bytes())
+            .collect();
+
+        attribute("channels", "chlist", &chlist);
+        attribute("compression", "compression", &[0]); // NO_COMPRESSION
+        attribute("dataWindow", "box2i", &window);
+        attribute("displayWindow", "box2i", &window);
+        attribute("lineOrder", "lineOrder", &[0]); // INCREASING_Y
+        attribute("pixelAspectRatio", "float", &1f32.to_le_bytes());
+        attribute("screenWindowCenter", "v2f", &[0; 8]);
+        attribute("screenWindowWidth", "float", &1f32.to_le_bytes());
+        out.push(0); // end of header
+
+        // Without compression, each chunk is a single scanline. The offset
+        // table stores where each chunk starts (relative to the file start).
+        let chunk_size = 8 + channels.len() * width * 4;
+        let table_end = out.len() + height * 8;
+        for y in 0..height {
+            out.extend_from_slice(&((table_end + y * chunk_size) as u64).to_le_bytes());
+        }
+
+        for y in 0..height {
+            out.extend_from_slice(&(y as i32).to_le_bytes());
+            out.extend_from_slice(&((channels.len() * width * 4) as i32).to_le_bytes());
+            for (_, layer, c) in &channels {
+                for x in 0..width {
+                    let index = y * width + x;
+                    let value = match layer {
+                        Some(l) => self.layers[*l].get(index, *c),
+                        None => {
+                            let color = self.beauty.pixels[index];
+                            [color.r(), color.g(), color.b()][*c]
+                        }
+                    };
+                    out.extend_from_slice(&(value as f32).to_le_bytes());
+                }
+            }
+        }
+        out
+    }
+}
+
+/// Reads little endian values and strings from a byte slice.
+pub(crate) struct ByteReader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    pub(crate) fn new(bytes: &'a [u8]) -> Self {
+        ByteReader { bytes, position: 0 }
+    }
+
+    /// The number of bytes left to read.
+    pub(crate) fn remaining(&self) -> usize {
+        self.bytes.len() - self.position
+    }
+
+    fn peek(&self, n: usize) -> Option<&'a [u8]> {
+        self.bytes.get(self.position..self.position + n)
+    }
+
+    fn bytes(&mut self, n: usize) -> io::Result<&'a [u8]> {
+        let bytes = self
+            .peek(n)
+            .ok_or_else(|| io::Error::from(io::ErrorKind::UnexpectedEof))?;
+        self.position += n;
+        Ok(bytes)
+    }
+
+    fn byte(&mut self) -> io::Result<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> io::Result<u16> {
+        Ok(u16::from_le_bytes(self.bytes(2)?.try_into().unwrap()))
+    }
+
+    pub(crate) fn u32(&mut self) -> io::Result<u32> {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    /// Reads bytes up to (and skips) the given terminator.
+    fn until(&mut self, terminator: u8) -> io::Result<String> {
+        let rest = &self.bytes[self.position..];
+        let length = rest
+            .iter()
+            .position(|&b| b == terminator)
+            .ok_or_else(|| io::Error::from(io::ErrorKind::UnexpectedEof))?;
+        self.position += length + 1;
+        String::from_utf8(rest[..length].to_vec()).map_err(|_| invalid_data("invalid text"))
+    }
+
+    /// Reads a null-terminated string.
+    fn string(&mut self) -> io::Result<String> {
+        self.until(0)
+    }
+
+    /// Reads a line of text.
+    fn line(&mut self) -> io::Result<String> {
+        self.until(b'\n')
+    }
+}
+
+pub(crate) fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn parse_size(s: &str) -> io::Result<usize> {
+    s.parse().map_err(|_| invalid_data("invalid image size"))
+}
+
+/// Converts a 16-bit (half precision) float to an f64.
+fn half_to_f64(bits: u16) -> f64 {
+    let sign = if bits >> 15 == 1 { -1.0 } else { 1.0 };
+    let exponent = ((bits >> 10) & 0x1f) as i32;
+    let mantissa = (bits & 0x3ff) as f64;
+    sign * match exponent {
+        0 => mantissa * 2f64.powi(-24),
+        31 if mantissa == 0.0 => f64::INFINITY,
+        31 => f64::NAN,
+        _ => (1.0 + mantissa / 1024.0) * 2f64.powi(exponent - 15),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exr_roundtrip() {
+        let pixels = (0..6)
+            .map(|i| Color::new(i as f64, 0.5, -(i as f64) * 0.25))
+            .collect();
+        let frame = FrameBuffer {
+            beauty: Buffer::new(pixels, 3, 2),
+            layers: vec![Layer::new("depth", &["Z"], (0..6).map(|i| [i as f64]))],
+        };
+        let read = Buffer::from_exr(&frame.to_exr()).unwrap();
+        assert_eq!((read.width, read.height), (3, 2));
+        for (a, b) in read.pixels.iter().zip(&frame.beauty.pixels) {
+            assert_eq!((a.r(), a.g(), a.b()), (b.r(), b.g(), b.b()));
+        }
+    }
+
+    #[test]
+    fn test_exr_bad_data_window() {
+        let frame = FrameBuffer {
+            beauty: Buffer::new(vec![Color::WHITE; 6], 3, 2),
+            layers: Vec::new(),
+        };
+        let bytes = frame.to_exr();
+        let name = b"dataWindow\0box2i\0";
+        let start = bytes.windows(name.len()).position(|w| w == name).unwrap() + name.len() + 4;
+        let with_window = |window: [i32; 4]| {
+            let mut bytes = bytes.clone();
+            for (k, coord) in window.iter().enumerate() {
+                bytes[start + 4 * k..start + 4 * k + 4].copy_from_slice(&coord.to_le_bytes());
+            }
+            Buffer::from_exr(&bytes).map(|_| ()).unwrap_err().kind()
+        };
+        // [x_min, y_min, x_max, y_max]
+        assert_eq!(with_window([2, 0, 0, 1]), io::ErrorKind::InvalidData);
+        assert_eq!(with_window([0, 1, 2, -5]), io::ErrorKind::InvalidData);
+        assert_eq!(
+            with_window([i32::MIN, 0, i32::MAX, 1]),
+            io::ErrorKind::InvalidData
+        );
+        assert_eq!(
+            with_window([0, 0, 1 << 20, 1 << 20]),
+            io::ErrorKind::InvalidData
+        );
+        // The scanlines are numbered from 0, outside of these windows.
+        assert_eq!(with_window([0, 1000, 2, 1001]), io::ErrorKind::InvalidData);
+        assert_eq!(
+            with_window([0, i32::MIN, 2, i32::MIN + 1]),
+            io::ErrorKind::InvalidData
+        );
+    }
+
+    #[test]
+    fn test_hdr() {
+        let mut bytes = b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 2 +X 8\n".to_vec();
+        // A run-length encoded scanline: each component is a single run.
+        bytes.extend_from_slice(&[2, 2, 0, 8]);
+        for value in [128, 64, 0, 129] {
+            bytes.extend_from_slice(&[128 + 8, value]);
+        }
+        // A flat scanline.
+        for _ in 0..8 {
+            bytes.extend_from_slice(&[128, 128, 128, 128]);
+        }
+
+        let image = Buffer::from_hdr(&bytes).unwrap();
+        assert_eq!((image.width, image.height), (8, 2));
+        let first = image.pixels[0];
+        assert_eq!((first.r(), first.g(), first.b()), (1.0, 0.5, 0.0));
+        assert_eq!(image.pixels[15].r(), 0.5);
+    }
 }
@@ -1,12 +1,25 @@
+pub mod aov;
 pub mod camera;
+pub mod checkpoint;
 pub mod color;
+pub mod denoise;
 pub mod engine;
+pub mod environment;
+pub mod film;
+pub mod filter;
 pub mod hitrecord;
 pub mod io;
+pub mod light;
 pub mod material;
+pub mod medium;
 pub mod object;
+pub mod onb;
+pub mod progressive;
 pub mod ray;
 pub mod scene;
 pub mod shape;
+pub mod spectrum;
+pub mod texture;
+pub mod tile;
 pub mod utils;
 pub mod vec3;
@@ -0,0 +1,55 @@
+mod directional;
+mod point;
+mod spot;
+
+pub use directional::DirectionalLight;
+pub use point::PointLight;
+pub use spot::SpotLight;
+
+use std::fmt::Debug;
+
+use crate::{color::Color, vec3::Vec3};
+
+/// A light source that is infinitely small (or infinitely far away), so rays
+/// bouncing around the scene never hit it by chance. Instead, the renderer
+/// asks the light how much light it sends towards each hit and checks whether
+/// anything is in the way with a shadow ray.
+///
+/// Lights implement `Debug` so that the contents of a scene can be hashed
+/// (see `Scene::content_hash`).
+pub trait Light: Sync + Debug {
+    /// Picks a direction towards the light from the given point. Returns
+    /// `None` if the light doesn't reach the point at all.
+    fn sample(&self, point: Vec3) -> Option<LightSample>;
+}
+
+/// The light arriving at a point from a light source.
+pub struct LightSample {
+    /// The (unit) direction from the point towards the light.
+    pub direction: Vec3,
+
+    /// The distance to the light (infinity for directional lights). Only
+    /// objects closer than this cast a shadow.
+    pub distance: f64,
+
+    /// The light arriving at the point from the light, on a surface facing
+    /// the light (the cosine of the angle to the actual surface is taken care
+    /// of by `Material::eval`).
+    pub irradiance: Color,
+}
+
+/// The smooth falloff of a light towards the edge of its range, used to keep
+/// a light from affecting the entire scene (the light of a point light never
+/// reaches zero by itself). This is the falloff used by glTF and many game
+/// engines:
+///
+///    (1 - (d / range)⁴)²
+pub(crate) fn range_falloff(distance: f64, range: Option<f64>) -> f64 {
+    match range {
+        Some(range) => {
+            let ratio = distance / range;
+            (1.0 - ratio.powi(4)).max(0.0).powi(2)
+        }
+        None => 1.0,
+    }
+}
@@ -0,0 +1,84 @@
+use crate::{color::Color, utils::random_in_cone, vec3::Vec3};
+
+use super::{Light, LightSample};
+
+/// A light that is infinitely far away (like the sun), so its light arrives
+/// from the same direction and with the same brightness everywhere.
+///
+/// A light with an angular diameter of zero casts perfectly sharp shadows.
+/// Giving it a size (the sun is about half a degree across) makes each point
+/// pick a random direction within the disk of the light, which averages out
+/// into soft shadows.
+#[derive(Debug)]
+pub struct DirectionalLight {
+    /// The (unit) direction towards the light, i.e. the opposite of the
+    /// direction the light travels in.
+    to_light: Vec3,
+
+    /// The light arriving on a surface facing the light.
+    irradiance: Color,
+
+    /// The cosine of the angular radius of the disk of the light.
+    cos_radius: f64,
+}
+
+impl DirectionalLight {
+    /// Creates a light that travels in the given direction.
+    pub fn new(direction: Vec3, irradiance: Color) -> Self {
+        DirectionalLight {
+            to_light: -direction.unit_vector(),
+            irradiance,
+            cos_radius: 1.0,
+        }
+    }
+
+    /// Sets the angle the light covers in the sky (in degrees).
+    pub fn set_angular_diameter(&mut self, degrees: f64) {
+        self.cos_radius = (degrees / 2.0).to_radians().cos();
+    }
+}
+
+impl Light for DirectionalLight {
+    fn sample(&self, _point: Vec3) -> Option<LightSample> {
+        let direction = match self.cos_radius < 1.0 {
+            true => random_in_cone(self.to_light, self.cos_radius),
+            false => self.to_light,
+        };
+        Some(LightSample {
+            direction,
+            distance: f64::INFINITY,
+            irradiance: self.irradiance,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_direction_and_irradiance() {
+        let light = DirectionalLight::new(Vec3::new(0.0, -2.0, 0.0), Color::WHITE * 3.0);
+        for point in [Vec3::ZERO, Vec3::new(100.0, -50.0, 3.0)] {
+            let sample = light.sample(point).unwrap();
+            assert_eq!((sample.direction - Vec3::Y).length(), 0.0);
+            assert_eq!(sample.distance, f64::INFINITY);
+            assert_eq!(sample.irradiance.r(), 3.0);
+        }
+    }
+
+    #[test]
+    fn test_angular_diameter() {
+        let mut light = DirectionalLight::new(-Vec3::Y, Color::WHITE);
+        light.set_angular_diameter(10.0);
+        let cos_radius = 5f64.to_radians().cos();
+        let directions: Vec<Vec3> = (0..100)
+            .map(|_| light.sample(Vec3::ZERO).unwrap().direction)
+            .collect();
+        for direction in &directions {
+            assert!((direction.length() - 1.0).abs() < 1e-9);
+            assert!(direction.dot(Vec3::Y) >= cos_radius - 1e-9);
+        }
+        assert!(directions.iter().any(|d| d.dot(Vec3::Y) < 1.0 - 1e-9));
+    }
+}
@@ -0,0 +1,76 @@
+use crate::{color::Color, vec3::Vec3};
+
+use super::{range_falloff, Light, LightSample};
+
+/// A light that shines equally in all directions from a single point. Its
+/// light falls off with the square of the distance, as it spreads over a
+/// sphere whose area grows with the square of its radius.
+#[derive(Debug)]
+pub struct PointLight {
+    position: Vec3,
+
+    /// The light emitted per unit solid angle.
+    intensity: Color,
+
+    /// If set, the light smoothly fades out to zero at this distance.
+    range: Option<f64>,
+}
+
+impl PointLight {
+    pub fn new(position: Vec3, intensity: Color) -> Self {
+        PointLight {
+            position,
+            intensity,
+            range: None,
+        }
+    }
+
+    /// Limits the distance the light reaches (see `range_falloff`).
+    pub fn set_range(&mut self, range: f64) {
+        self.range = Some(range);
+    }
+}
+
+impl Light for PointLight {
+    fn sample(&self, point: Vec3) -> Option<LightSample> {
+        let to_light = self.position - point;
+        let distance = to_light.length();
+        let falloff = range_falloff(distance, self.range);
+        if distance == 0.0 || falloff == 0.0 {
+            return None;
+        }
+        Some(LightSample {
+            direction: to_light / distance,
+            distance,
+            irradiance: self.intensity * (falloff / (distance * distance)),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_inverse_square_and_range() {
+        let mut light = PointLight::new(Vec3::Y, Color::WHITE * 4.0);
+        let sample = light.sample(Vec3::new(0.0, -1.0, 0.0)).unwrap();
+        assert_eq!(sample.irradiance.r(), 1.0);
+        assert_eq!(sample.distance, 2.0);
+        assert_eq!((sample.direction - Vec3::Y).length(), 0.0);
+        // Twice as far away, a quarter of the light.
+        let sample = light.sample(Vec3::new(0.0, -3.0, 0.0)).unwrap();
+        assert_eq!(sample.irradiance.r(), 0.25);
+
+        // The light shines in every direction.
+        let sample = light.sample(Vec3::new(2.0, 1.0, 0.0)).unwrap();
+        assert_eq!(sample.irradiance.r(), 1.0);
+        assert_eq!((sample.direction + Vec3::X).length(), 0.0);
+
+        assert!(light.sample(Vec3::Y).is_none());
+        light.set_range(2.0);
+        assert!(light.sample(Vec3::new(0.0, -1.0, 0.0)).is_none());
+        let near = light.sample(Vec3::ZERO).unwrap().irradiance.r();
+        assert!(near > 0.0 && near < 4.0);
+    }
+}
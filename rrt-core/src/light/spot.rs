@@ -0,0 +1,115 @@
+use crate::{color::Color, vec3::Vec3};
+
+use super::{range_falloff, Light, LightSample};
+
+/// A point light that only shines within a cone. The light is at full
+/// intensity within the inner cone and smoothly fades out towards the outer
+/// cone.
+#[derive(Debug)]
+pub struct SpotLight {
+    position: Vec3,
+
+    /// The (unit) direction the light is pointing in.
+    direction: Vec3,
+
+    /// The light emitted per unit solid angle (within the inner cone).
+    intensity: Color,
+
+    /// The cosines of the angles between the direction and the edges of the
+    /// inner and outer cones.
+    cos_inner: f64,
+    cos_outer: f64,
+
+    /// If set, the light smoothly fades out to zero at this distance.
+    range: Option<f64>,
+}
+
+impl SpotLight {
+    /// Creates a spot light. The cone angles are measured from the direction
+    /// of the light to the edge of the cone (in degrees).
+    pub fn new(
+        position: Vec3,
+        direction: Vec3,
+        intensity: Color,
+        inner_angle: f64,
+        outer_angle: f64,
+    ) -> Self {
+        assert!(inner_angle <= outer_angle);
+        SpotLight {
+            position,
+            direction: direction.unit_vector(),
+            intensity,
+            cos_inner: inner_angle.to_radians().cos(),
+            cos_outer: outer_angle.to_radians().cos(),
+            range: None,
+        }
+    }
+
+    /// Limits the distance the light reaches (see `range_falloff`).
+    pub fn set_range(&mut self, range: f64) {
+        self.range = Some(range);
+    }
+
+    /// The fraction of the intensity sent in a direction at an angle with the
+    /// cosine `cos_angle` from the direction of the light (smoothstep between
+    /// the outer and inner cones).
+    fn cone_falloff(&self, cos_angle: f64) -> f64 {
+        if cos_angle >= self.cos_inner {
+            return 1.0;
+        }
+        let t = ((cos_angle - self.cos_outer) / (self.cos_inner - self.cos_outer)).clamp(0.0, 1.0);
+        t * t * (3.0 - 2.0 * t)
+    }
+}
+
+impl Light for SpotLight {
+    fn sample(&self, point: Vec3) -> Option<LightSample> {
+        let to_light = self.position - point;
+        let distance = to_light.length();
+        if distance == 0.0 {
+            return None;
+        }
+        let direction = to_light / distance;
+        let falloff =
+            self.cone_falloff(-direction.dot(self.direction)) * range_falloff(distance, self.range);
+        if falloff == 0.0 {
+            return None;
+        }
+        Some(LightSample {
+            direction,
+            distance,
+            irradiance: self.intensity * (falloff / (distance * distance)),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cone() {
+        let light = SpotLight::new(Vec3::Y, -Vec3::Y, Color::WHITE, 20.0, 40.0);
+        let at_angle = |degrees: f64| {
+            let (sin, cos) = degrees.to_radians().sin_cos();
+            light
+                .sample(Vec3::Y + Vec3::new(sin, -cos, 0.0))
+                .map_or(0.0, |s| s.irradiance.r())
+        };
+        assert_eq!(at_angle(0.0), 1.0);
+        assert_eq!(at_angle(19.0), 1.0);
+        let middle = at_angle(30.0);
+        assert!(middle > 0.0 && middle < 1.0);
+        assert_eq!(at_angle(41.0), 0.0);
+    }
+
+    #[test]
+    fn test_inverse_square_and_range() {
+        let mut light = SpotLight::new(Vec3::ZERO, -Vec3::Y, Color::WHITE * 4.0, 90.0, 90.0);
+        let sample = light.sample(Vec3::new(0.0, -2.0, 0.0)).unwrap();
+        assert_eq!(sample.irradiance.r(), 1.0);
+        assert_eq!(sample.distance, 2.0);
+        light.set_range(2.0);
+        assert!(light.sample(Vec3::new(0.0, -2.0, 0.0)).is_none());
+    }
+}
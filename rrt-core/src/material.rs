@@ -1,16 +1,176 @@
+mod conductor;
+mod dielectric;
+mod dispersive;
+mod emissive;
+mod interface;
 mod lambertian;
+mod layered;
+mod microfacet;
+mod mix;
+mod principled;
+mod subsurface;
+mod thin_film;
 
+pub use conductor::Conductor;
+pub use dielectric::Dielectric;
+pub use dispersive::{Dispersive, Ior};
+pub use emissive::Emissive;
+pub use interface::Interface;
 pub use lambertian::Lambertian;
+pub use layered::Layered;
+pub use mix::Mix;
+pub use principled::Principled;
+pub use subsurface::Subsurface;
+pub use thin_film::ThinFilm;
 
-use crate::{color::Color, hitrecord::HitRecord, ray::Ray};
+use std::fmt::Debug;
 
-/// A material determines two things given a hit record:
+use crate::{color::Color, hitrecord::HitRecord, medium::Medium, vec3::Vec3};
+
+/// A material determines how light is scattered at a hit, which is described
+/// by its BSDF (bidirectional scattering distribution function) f(wo, wi):
+/// the fraction of the light arriving from direction wi that is scattered
+/// towards direction wo.
+///
+/// All the directions are unit vectors in the local shading frame of the hit
+/// (see `HitRecord::frame`), where the normal is +z and the cosine of the
+/// angle between a direction and the normal is simply its z coordinate. Both
+/// directions point away from the surface: wo towards where the ray came
+/// from, and wi towards where the light comes from.
 ///
-///   1) what is the outgoing array (e.g. if the material is a mirror
-///        we perfectly reflect w.r.t. the normal)
-///   2) the attenuation of light (e.g. how much of each red light, blue
-///        light, and green light is absorbed)
+/// The renderer needs three things from the BSDF:
 ///
-pub trait Material: Sync {
-    fn scatter(&self, record: HitRecord) -> (Ray, Color);
+///   1) `sample`: a direction to continue the path in, ideally picked
+///      proportionally to the BSDF
+///   2) `eval`: the value of the BSDF for a given pair of directions, e.g. to
+///      weight the light from a direction picked by sampling a light
+///   3) `pdf`: the probability density of `sample` picking a given direction,
+///      to combine light sampling and BSDF sampling with multiple importance
+///      sampling (see `engine::trace_ray`)
+///
+/// Materials implement `Debug` so that the contents of a scene can be hashed
+/// (see `Scene::content_hash`).
+pub trait Material: Sync + Debug {
+    /// Picks a direction wi to continue the path in. Returns `None` if the
+    /// light is absorbed.
+    fn sample(&self, record: HitRecord, wo: Vec3) -> Option<BsdfSample>;
+
+    /// The BSDF times the cosine of the angle between wi and the normal,
+    /// i.e. the fraction of the light arriving from wi that is scattered
+    /// towards wo. This is zero for perfectly smooth materials, since they
+    /// only scatter towards directions that have no chance of being picked
+    /// any other way.
+    fn eval(&self, record: HitRecord, wo: Vec3, wi: Vec3) -> Color;
+
+    /// The probability density (with respect to solid angle) of `sample`
+    /// picking wi. Like `eval`, this is zero for perfectly smooth materials.
+    fn pdf(&self, record: HitRecord, wo: Vec3, wi: Vec3) -> f64;
+
+    /// The light emitted by the surface back along the incoming ray. Emissive
+    /// surfaces are only found by scattered rays (they aren't sampled like
+    /// lights), so small and bright ones are noisy.
+    fn emitted(&self, record: HitRecord) -> Color {
+        let _ = record;
+        Color::BLACK
+    }
+
+    /// The medium inside of objects made of this material, for materials
+    /// that scatter light below their surface (see `Subsurface`). The medium
+    /// of the object itself takes precedence (see `Object::set_medium`).
+    fn medium(&self) -> Option<&dyn Medium> {
+        None
+    }
+
+    /// The fraction of the light arriving from wo that goes through the
+    /// surface rather than being reflected or absorbed, e.g. 1 - F for glass.
+    /// This is how much light reaches the material below it in a `Layered`
+    /// material. Opaque materials let nothing through.
+    fn transmission(&self, record: HitRecord, wo: Vec3) -> Color {
+        let _ = (record, wo);
+        Color::BLACK
+    }
+
+    /// Whether the values returned by the material (the weights of its
+    /// samples and its BSDF) are already given at the wavelengths of the hit
+    /// (see `HitRecord::wavelengths`, or `Wavelengths::RGB` in RGB mode)
+    /// because they depend on the wavelength, e.g. for dispersion. The values
+    /// of other materials are RGB colors, which are converted to spectra in
+    /// spectral mode (see `Wavelengths::upsample`).
+    fn is_spectral(&self) -> bool {
+        false
+    }
+
+    /// Whether the surface only marks the boundary of a medium and lets rays
+    /// through unchanged (see `Interface`).
+    fn is_interface(&self) -> bool {
+        false
+    }
+}
+
+/// Converts a value of a part of a material made of other materials to a
+/// value of the whole (see `Material::is_spectral`): in spectral mode, the
+/// whole is spectral if any of its parts are, and then the RGB values of the
+/// other parts are converted to the wavelengths of the hit.
+fn spectral_value(
+    whole: &dyn Material,
+    part: &dyn Material,
+    record: &HitRecord,
+    value: Color,
+) -> Color {
+    match record.wavelengths {
+        Some(wavelengths) if whole.is_spectral() && !part.is_spectral() => {
+            wavelengths.upsample(value)
+        }
+        _ => value,
+    }
+}
+
+/// A direction sampled from a BSDF (see `Material::sample`).
+#[derive(Clone, Copy, Debug)]
+pub struct BsdfSample {
+    /// The sampled direction, in the local shading frame.
+    pub wi: Vec3,
+
+    /// The BSDF times the cosine divided by the pdf, i.e. the attenuation of
+    /// the light arriving along the sampled direction.
+    pub weight: Color,
+
+    /// The probability density of the sampled direction, or 0 if the
+    /// direction was picked by a perfectly smooth lobe (see `delta`).
+    pub pdf: f64,
+
+    /// Whether the direction was picked by a perfectly smooth lobe (e.g. a
+    /// mirror), which scatters light in a single direction. Such directions
+    /// can't be evaluated, so the light found along them is never weighted
+    /// against light sampling.
+    pub delta: bool,
+
+    /// The kind of scattering that picked the direction, used to split the
+    /// lighting into separate AOVs (see `aov`).
+    pub lobe: Lobe,
+}
+
+/// The different kinds of scattering a material can do.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Lobe {
+    /// Light is scattered (roughly) uniformly in all directions.
+    Diffuse,
+
+    /// Light is reflected around the mirror direction.
+    Glossy,
+
+    /// Light passes through the surface.
+    Transmission,
+}
+
+impl Lobe {
+    pub const ALL: [Lobe; 3] = [Lobe::Diffuse, Lobe::Glossy, Lobe::Transmission];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Lobe::Diffuse => "diffuse",
+            Lobe::Glossy => "glossy",
+            Lobe::Transmission => "transmission",
+        }
+    }
 }
@@ -0,0 +1,112 @@
+use crate::{color::Color, hitrecord::HitRecord, vec3::Vec3};
+
+use super::{
+    microfacet::{Fresnel, Ggx, MicrofacetReflection},
+    BsdfSample, Material,
+};
+
+/// A (rough) metal. Metals reflect light off of their surface and absorb all
+/// the light that gets through it, so they have no diffuse component. How much
+/// light is reflected (and thus their color) depends on the angle of incidence
+/// and on their complex index of refraction eta + i * k, which is measured for
+/// real metals (see the presets).
+///
+/// The roughness ranges from 0 (a perfect mirror) to 1 (a very dull metal).
+#[derive(Debug)]
+pub struct Conductor {
+    reflection: MicrofacetReflection,
+}
+
+impl Conductor {
+    pub fn new(eta: Color, k: Color, roughness: f64) -> Self {
+        Conductor {
+            reflection: MicrofacetReflection {
+                ggx: Ggx::from_roughness(roughness),
+                fresnel: Fresnel::Conductor { eta, k },
+            },
+        }
+    }
+
+    /// Gold, with the index of refraction sampled at 650, 550 and 450 nm.
+    /// Reference: https://refractiveindex.info
+    pub fn gold(roughness: f64) -> Self {
+        Conductor::new(
+            Color::new(0.143, 0.374, 1.442),
+            Color::new(3.983, 2.385, 1.603),
+            roughness,
+        )
+    }
+
+    /// Copper (see `gold`).
+    pub fn copper(roughness: f64) -> Self {
+        Conductor::new(
+            Color::new(0.200, 0.924, 1.102),
+            Color::new(3.912, 2.452, 2.142),
+            roughness,
+        )
+    }
+
+    /// Aluminum (see `gold`).
+    pub fn aluminum(roughness: f64) -> Self {
+        Conductor::new(
+            Color::new(1.657, 0.880, 0.521),
+            Color::new(9.224, 6.270, 4.837),
+            roughness,
+        )
+    }
+}
+
+/// See `MicrofacetReflection`.
+impl Material for Conductor {
+    fn sample(&self, _record: HitRecord, wo: Vec3) -> Option<BsdfSample> {
+        self.reflection.sample(wo)
+    }
+
+    fn eval(&self, _record: HitRecord, wo: Vec3, wi: Vec3) -> Color {
+        self.reflection.eval(wo, wi)
+    }
+
+    fn pdf(&self, _record: HitRecord, wo: Vec3, wi: Vec3) -> f64 {
+        self.reflection.pdf(wo, wi)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_presets() {
+        // A smooth metal reflects the ray straight back with its color, which
+        // for gold is much more red than blue.
+        let record = HitRecord::new();
+        let sample = Conductor::gold(0.0).sample(record, Vec3::Z).unwrap();
+        assert!(sample.delta && (sample.wi - Vec3::Z).length() < 1e-9);
+        assert!(sample.weight.r() > 0.9 && sample.weight.b() < 0.5);
+        // Aluminum is bright and nearly white.
+        let sample = Conductor::aluminum(0.0).sample(record, Vec3::Z).unwrap();
+        assert!(sample.weight.b() > 0.9 && sample.weight.r() > 0.85);
+    }
+
+    #[test]
+    fn test_eval_matches_sample() {
+        // The reflected energy estimated by light sampling (integrating eval
+        // over uniform directions) must match the one estimated by sampling
+        // the BRDF.
+        let material = Conductor::copper(0.8);
+        let record = HitRecord::new();
+        let wo = Vec3::new(0.5, 0.2, 1.0).unit_vector();
+        let samples = 200_000;
+        let (mut sampled, mut evaluated) = (0.0, 0.0);
+        for _ in 0..samples {
+            if let Some(sample) = material.sample(record, wo) {
+                sampled += sample.weight.g();
+            }
+            let mut wi = crate::utils::random_unit_vector();
+            wi.z = wi.z.abs();
+            evaluated += material.eval(record, wo, wi).g() * 2.0 * std::f64::consts::PI;
+        }
+        let (sampled, evaluated) = (sampled / samples as f64, evaluated / samples as f64);
+        assert!((sampled - evaluated).abs() < 0.02, "{sampled} {evaluated}");
+    }
+}
@@ -0,0 +1,221 @@
+use crate::{color::Color, hitrecord::HitRecord, utils::random_double, vec3::Vec3};
+
+use super::{
+    microfacet::{fresnel_dielectric, reflect, refract, Ggx},
+    BsdfSample, Lobe, Material,
+};
+
+/// A (rough) transparent material, like glass or water. Light is partly
+/// reflected and partly refracted, according to the Fresnel equations. With a
+/// roughness above 0, both the reflection and the refraction are blurred,
+/// which gives frosted glass.
+///
+/// Objects made of a dielectric should be closed (e.g. spheres), so that rays
+/// that enter them also leave them.
+#[derive(Debug)]
+pub struct Dielectric {
+    /// The index of refraction of the material, e.g. 1.5 for glass or 1.33 for
+    /// water (relative to the air around the object).
+    ior: f64,
+    ggx: Ggx,
+}
+
+impl Dielectric {
+    pub fn new(ior: f64, roughness: f64) -> Self {
+        assert!(ior > 0.0);
+        Dielectric {
+            ior,
+            ggx: Ggx::from_roughness(roughness),
+        }
+    }
+
+    /// The ratio of the index of refraction on the other side of the surface
+    /// to the one on the side of the ray. The normal faces the ray, so the ray
+    /// enters the object if it hit the front face.
+    fn eta(&self, record: &HitRecord) -> f64 {
+        if record.front_face {
+            self.ior
+        } else {
+            1.0 / self.ior
+        }
+    }
+
+    /// The microfacet normal that scatters wo into wi, facing +z. For a
+    /// refraction, this is the normal for which Snell's law holds:
+    ///
+    ///    h ∝ -(wo + η * wi)
+    ///
+    /// Returns `None` for degenerate configurations, and for facets that
+    /// face away from either direction.
+    fn half_vector(wo: Vec3, wi: Vec3, eta: f64) -> Option<Vec3> {
+        let h = if wi.z > 0.0 { wo + wi } else { wo + wi * eta };
+        if h.length_squared() < 1e-12 {
+            return None;
+        }
+        let h = h.unit_vector();
+        let h = if h.z < 0.0 { -h } else { h };
+        if h.dot(wo) * wo.z <= 0.0 || h.dot(wi) * wi.z <= 0.0 {
+            return None;
+        }
+        Some(h)
+    }
+
+    /// The value of the BSDF times the cosine and its pdf, both of which
+    /// depend on the same terms.
+    /// Reference: Walter et al., "Microfacet Models for Refraction through Rough Surfaces"
+    fn eval_and_pdf(&self, record: &HitRecord, wo: Vec3, wi: Vec3) -> (f64, f64) {
+        if self.ggx.is_smooth() || wo.z <= 0.0 || wi.z == 0.0 {
+            return (0.0, 0.0);
+        }
+        let eta = self.eta(record);
+        let h = match Dielectric::half_vector(wo, wi, eta) {
+            Some(h) => h,
+            None => return (0.0, 0.0),
+        };
+        let fresnel = fresnel_dielectric(wo.dot(h), eta);
+        let d = self.ggx.d(h);
+        let g2 = self.ggx.g2(wo, wi);
+        let visible_normal_pdf = self.ggx.visible_normal_pdf(wo, h);
+        if wi.z > 0.0 {
+            // The Jacobian of the reflection is 1 / (4 * wo·h).
+            let value = fresnel * d * g2 / (4.0 * wo.z);
+            (value, fresnel * visible_normal_pdf / (4.0 * wo.dot(h)))
+        } else {
+            // The Jacobian of the refraction is |wi·h| / (wo·h / η + wi·h)².
+            let denominator = (wo.dot(h) / eta + wi.dot(h)).powi(2);
+            let jacobian = wi.dot(h).abs() / denominator;
+            let value = (1.0 - fresnel) * d * g2 * wo.dot(h) * jacobian / wo.z;
+            (value, (1.0 - fresnel) * visible_normal_pdf * jacobian)
+        }
+    }
+}
+
+impl Material for Dielectric {
+    /// Samples a visible microfacet normal (see `MicrofacetReflection`), then
+    /// picks reflection or refraction with the probability given by the
+    /// Fresnel term of that facet. The Fresnel term cancels out with that
+    /// probability, so the weight of the sample is only G2(wo, wi) / G1(wo).
+    fn sample(&self, record: HitRecord, wo: Vec3) -> Option<BsdfSample> {
+        if wo.z <= 0.0 {
+            return None;
+        }
+        let eta = self.eta(&record);
+        let smooth = self.ggx.is_smooth();
+        let m = if smooth {
+            Vec3::Z
+        } else {
+            self.ggx.sample_visible_normal(wo)
+        };
+
+        let fresnel = fresnel_dielectric(wo.dot(m), eta);
+        let (wi, lobe) = match refract(wo, m, eta) {
+            Some(refracted) if random_double() >= fresnel => (refracted, Lobe::Transmission),
+            _ => (reflect(wo, m), Lobe::Glossy),
+        };
+
+        if smooth {
+            return Some(BsdfSample {
+                wi,
+                weight: Color::WHITE,
+                pdf: 0.0,
+                delta: true,
+                lobe,
+            });
+        }
+        // A rough facet can send the ray to the wrong side of the surface,
+        // in which case it is lost (see `MicrofacetReflection::sample`).
+        let valid = match lobe {
+            Lobe::Transmission => wi.z < 0.0,
+            _ => wi.z > 0.0,
+        };
+        if !valid {
+            return None;
+        }
+        Some(BsdfSample {
+            wi,
+            weight: Color::WHITE * (self.ggx.g2(wo, wi) / self.ggx.g1(wo)),
+            pdf: self.eval_and_pdf(&record, wo, wi).1,
+            delta: false,
+            lobe,
+        })
+    }
+
+    fn eval(&self, record: HitRecord, wo: Vec3, wi: Vec3) -> Color {
+        Color::WHITE * self.eval_and_pdf(&record, wo, wi).0
+    }
+
+    fn pdf(&self, record: HitRecord, wo: Vec3, wi: Vec3) -> f64 {
+        self.eval_and_pdf(&record, wo, wi).1
+    }
+
+    /// The light that isn't reflected by a smooth surface. Rough surfaces
+    /// reflect about as much on average.
+    fn transmission(&self, record: HitRecord, wo: Vec3) -> Color {
+        Color::WHITE * (1.0 - fresnel_dielectric(wo.z, self.eta(&record)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(front_face: bool) -> HitRecord {
+        let mut record = HitRecord::new();
+        record.front_face = front_face;
+        record
+    }
+
+    /// The average weight of the directions sampled for the given `wo`, i.e.
+    /// the fraction of the energy that isn't lost.
+    fn albedo(material: &Dielectric, wo: Vec3, front_face: bool) -> f64 {
+        let samples = 100_000;
+        let total: f64 = (0..samples)
+            .filter_map(|_| material.sample(record(front_face), wo))
+            .map(|sample| sample.weight.r())
+            .sum();
+        total / samples as f64
+    }
+
+    #[test]
+    fn test_energy_conservation() {
+        // Smooth glass reflects or refracts all of the light.
+        let wo = Vec3::new(1.0, 0.0, 1.0).unit_vector();
+        assert_eq!(albedo(&Dielectric::new(1.5, 0.0), wo, true), 1.0);
+        assert_eq!(albedo(&Dielectric::new(1.5, 0.0), wo, false), 1.0);
+
+        // Rough glass only loses the light that would scatter between facets.
+        for roughness in [0.3, 0.6] {
+            for front_face in [true, false] {
+                let albedo = albedo(&Dielectric::new(1.5, roughness), wo, front_face);
+                assert!(albedo <= 1.0 && albedo > 0.7, "{roughness} {albedo}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_sample_matches_eval_and_pdf() {
+        for front_face in [true, false] {
+            let material = Dielectric::new(1.5, 0.5);
+            let record = record(front_face);
+            let wo = Vec3::new(0.3, -0.2, 1.0).unit_vector();
+            for _ in 0..1000 {
+                if let Some(sample) = material.sample(record, wo) {
+                    let pdf = material.pdf(record, wo, sample.wi);
+                    let expected = material.eval(record, wo, sample.wi).r() / pdf;
+                    assert!((sample.weight.r() - expected).abs() < 1e-6, "{sample:?}");
+                    assert!((sample.pdf - pdf).abs() < 1e-6 * pdf);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_refraction_direction() {
+        // Without Fresnel reflection (ior 1), light passes straight through.
+        let sample = Dielectric::new(1.0, 0.0)
+            .sample(record(true), Vec3::Z)
+            .unwrap();
+        assert_eq!(sample.lobe, Lobe::Transmission);
+        assert!((sample.wi + Vec3::Z).length() < 1e-9);
+    }
+}
@@ -0,0 +1,338 @@
+use crate::{
+    color::Color, hitrecord::HitRecord, spectrum::Wavelengths, utils::random_double, vec3::Vec3,
+};
+
+use super::{microfacet::fresnel_dielectric, BsdfSample, Dielectric, Lobe, Material};
+
+/// A model of how the index of refraction of a transparent material varies
+/// with the wavelength of light (in nanometers). The models are fitted to
+/// measurements, and usually take the wavelength in micrometers.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Ior {
+    /// Cauchy's equation, a simple fit that works well in the visible range:
+    ///
+    ///    n(λ) = a + b / λ²
+    Cauchy { a: f64, b: f64 },
+
+    /// The Sellmeier equation, which is what glass manufacturers publish:
+    ///
+    ///    n(λ)² = 1 + Σ b_i * λ² / (λ² - c_i)
+    Sellmeier { b: [f64; 3], c: [f64; 3] },
+}
+
+impl Ior {
+    /// Borosilicate crown glass (Schott N-BK7), the most common optical
+    /// glass, with an index of refraction of about 1.52 and low dispersion.
+    /// Reference: https://refractiveindex.info
+    pub const BK7: Ior = Ior::Sellmeier {
+        b: [1.03961212, 0.231792344, 1.01046945],
+        c: [0.00600069867, 0.0200179144, 103.560653],
+    };
+
+    /// Dense flint glass (Schott SF11), which disperses light about three
+    /// times as much as BK7 and is used for prisms (see `BK7`).
+    pub const SF11: Ior = Ior::Sellmeier {
+        b: [1.73759695, 0.313747346, 1.89878101],
+        c: [0.013188707, 0.0623068142, 155.23629],
+    };
+
+    /// Diamond, whose high index of refraction and dispersion give it its
+    /// "fire" (see `BK7`).
+    pub const DIAMOND: Ior = Ior::Sellmeier {
+        b: [0.3306, 4.3356, 0.0],
+        c: [0.030625, 0.011236, 0.0],
+    };
+
+    /// The index of refraction at the given wavelength.
+    pub fn at(&self, lambda: f64) -> f64 {
+        let lambda = lambda / 1000.0;
+        let lambda2 = lambda * lambda;
+        match *self {
+            Ior::Cauchy { a, b } => a + b / lambda2,
+            Ior::Sellmeier { b, c } => {
+                let sum: f64 = (0..3).map(|i| b[i] * lambda2 / (lambda2 - c[i])).sum();
+                (1.0 + sum).sqrt()
+            }
+        }
+    }
+}
+
+/// A (rough) transparent material whose index of refraction depends on the
+/// wavelength (see `Ior`), so that it splits white light into its colors
+/// (dispersion), like a prism or a diamond. Otherwise it behaves like a
+/// `Dielectric`.
+///
+/// Light of different wavelengths refracts in different directions, so the
+/// material samples one of the wavelengths of the path (see `spectrum`) and
+/// refracts the ray as light of that wavelength would. Through smooth
+/// surfaces, the other wavelengths can't follow the ray and are dropped, and
+/// the remaining one is weighted up to make up for them. The dispersion only
+/// looks right in spectral mode: in RGB mode, the color channels are treated
+/// as light of a single wavelength each (see `Wavelengths::RGB`), which splits
+/// white light into three separate images instead of a rainbow.
+///
+/// Like with any path tracer, caustics (e.g. the rainbow a prism casts) only
+/// show up when paths bouncing off of them can hit the light by chance, which
+/// is never the case for point lights: light them with small emissive objects
+/// instead (see the `prism` example).
+#[derive(Debug)]
+pub struct Dispersive {
+    ior: Ior,
+    roughness: f64,
+}
+
+impl Dispersive {
+    pub fn new(ior: Ior, roughness: f64) -> Self {
+        Dispersive { ior, roughness }
+    }
+
+    /// A dispersive material made of BK7 glass (see `Ior::BK7`).
+    pub fn bk7(roughness: f64) -> Self {
+        Dispersive::new(Ior::BK7, roughness)
+    }
+
+    /// A dispersive material made of flint glass (see `Ior::SF11`).
+    pub fn flint(roughness: f64) -> Self {
+        Dispersive::new(Ior::SF11, roughness)
+    }
+
+    /// A dispersive material made of diamond (see `Ior::DIAMOND`).
+    pub fn diamond(roughness: f64) -> Self {
+        Dispersive::new(Ior::DIAMOND, roughness)
+    }
+
+    /// The indices of refraction at the wavelengths of the hit.
+    fn iors(&self, record: &HitRecord) -> [f64; 3] {
+        let Wavelengths(lambdas) = record.wavelengths.unwrap_or(Wavelengths::RGB);
+        lambdas.map(|lambda| self.ior.at(lambda))
+    }
+
+    /// The material as seen by light of each wavelength of the hit.
+    fn dielectrics(&self, record: &HitRecord) -> [Dielectric; 3] {
+        self.iors(record)
+            .map(|ior| Dielectric::new(ior, self.roughness))
+    }
+}
+
+impl Material for Dispersive {
+    /// Picks a wavelength uniformly and samples the dielectric it sees. The
+    /// sample could have been picked through any of the wavelengths, so its
+    /// pdf is the average of the pdfs of the wavelengths (one-sample multiple
+    /// importance sampling with the balance heuristic). For rough surfaces,
+    /// all the wavelengths can still scatter towards the sampled direction.
+    ///
+    /// For smooth surfaces, the reflection goes in the same direction for all
+    /// wavelengths, and its weight for each wavelength is its Fresnel
+    /// reflectance F divided by the probability of reflecting, the average of
+    /// F over the wavelengths. The refraction only goes in the direction of
+    /// the wavelengths with the same index of refraction as the sampled one.
+    fn sample(&self, record: HitRecord, wo: Vec3) -> Option<BsdfSample> {
+        if wo.z <= 0.0 {
+            return None;
+        }
+        let dielectrics = self.dielectrics(&record);
+        let channel = ((random_double() * 3.0) as usize).min(2);
+        let sample = dielectrics[channel].sample(record, wo)?;
+
+        if !sample.delta {
+            let pdf = self.pdf(record, wo, sample.wi);
+            if pdf <= 0.0 {
+                return None;
+            }
+            return Some(BsdfSample {
+                weight: self.eval(record, wo, sample.wi) / pdf,
+                pdf,
+                ..sample
+            });
+        }
+
+        let iors = self.iors(&record);
+        let fresnel = iors.map(|ior| {
+            let eta = if record.front_face { ior } else { 1.0 / ior };
+            fresnel_dielectric(wo.z, eta)
+        });
+        let weights = match sample.lobe {
+            Lobe::Transmission => {
+                let same = |i: usize| iors[i] == iors[channel];
+                let transmitted = |i: usize| match same(i) {
+                    true => 1.0 - fresnel[i],
+                    false => 0.0,
+                };
+                let probability = (0..3).map(transmitted).sum::<f64>() / 3.0;
+                [0, 1, 2].map(|i| transmitted(i) / probability)
+            }
+            _ => {
+                let probability = fresnel.iter().sum::<f64>() / 3.0;
+                fresnel.map(|f| f / probability)
+            }
+        };
+        Some(BsdfSample {
+            weight: Color::new(weights[0], weights[1], weights[2]),
+            ..sample
+        })
+    }
+
+    fn eval(&self, record: HitRecord, wo: Vec3, wi: Vec3) -> Color {
+        let [r, g, b] = self
+            .dielectrics(&record)
+            .map(|dielectric| dielectric.eval(record, wo, wi).r());
+        Color::new(r, g, b)
+    }
+
+    fn pdf(&self, record: HitRecord, wo: Vec3, wi: Vec3) -> f64 {
+        let dielectrics = self.dielectrics(&record);
+        dielectrics
+            .iter()
+            .map(|dielectric| dielectric.pdf(record, wo, wi))
+            .sum::<f64>()
+            / 3.0
+    }
+
+    fn transmission(&self, record: HitRecord, wo: Vec3) -> Color {
+        let [r, g, b] = self
+            .dielectrics(&record)
+            .map(|dielectric| dielectric.transmission(record, wo).r());
+        Color::new(r, g, b)
+    }
+
+    fn is_spectral(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{object::Object, ray::Ray, scene::Scene, shape::Triangle};
+
+    fn record(front_face: bool) -> HitRecord {
+        let mut record = HitRecord::new();
+        record.front_face = front_face;
+        record
+    }
+
+    #[test]
+    fn test_presets() {
+        // The indices of refraction at the sodium D line (589.3 nm).
+        assert!((Ior::BK7.at(589.3) - 1.5168).abs() < 1e-3);
+        assert!((Ior::SF11.at(589.3) - 1.7847).abs() < 1e-3);
+        assert!((Ior::DIAMOND.at(589.3) - 2.417).abs() < 2e-3);
+        let cauchy = Ior::Cauchy { a: 1.5, b: 0.004 };
+        assert!((cauchy.at(400.0) - 1.525).abs() < 1e-9);
+        // Blue light is refracted more than red light.
+        assert!(Ior::BK7.at(450.0) > Ior::BK7.at(650.0));
+    }
+
+    #[test]
+    fn test_energy_conservation() {
+        // Smooth glass reflects or refracts all of the light of every
+        // wavelength, on average.
+        let wo = Vec3::new(0.6, 0.0, 0.8);
+        for front_face in [true, false] {
+            let samples = 100_000;
+            let mut total = Color::BLACK;
+            for _ in 0..samples {
+                let sample = Dispersive::flint(0.0)
+                    .sample(record(front_face), wo)
+                    .unwrap();
+                total = total + sample.weight;
+            }
+            let average = total / samples as f64;
+            for channel in [average.r(), average.g(), average.b()] {
+                assert!((channel - 1.0).abs() < 0.02, "{average:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_dispersion() {
+        // Entering the glass, blue light bends further towards the normal
+        // than red light.
+        let wo = Vec3::new(0.6, 0.0, 0.8);
+        let mut bent = [None; 3];
+        while bent.iter().any(Option::is_none) {
+            let sample = Dispersive::flint(0.0).sample(record(true), wo).unwrap();
+            if sample.lobe == Lobe::Transmission {
+                let weight = sample.weight;
+                let channel = [weight.r(), weight.g(), weight.b()]
+                    .iter()
+                    .position(|&w| w > 0.0)
+                    .unwrap();
+                bent[channel] = Some(-sample.wi.x);
+            }
+        }
+        let [red, green, blue] = bent.map(Option::unwrap);
+        assert!(red > green && green > blue);
+    }
+
+    #[test]
+    fn test_prism_rainbow() {
+        // A point light shining through a flint glass prism (built out of
+        // triangles) casts a rainbow on the floor at y = 0: we follow light
+        // of a few wavelengths from the light through the prism, and check
+        // that the colors land in the order of their wavelengths.
+        let (bottom, side) = (1.2, 0.15);
+        let corners = [
+            Vec3::new(-side / 2.0, bottom, 0.0),
+            Vec3::new(side / 2.0, bottom, 0.0),
+            Vec3::new(0.0, bottom + side * 3f64.sqrt() / 2.0, 0.0),
+        ];
+        let mut scene = Scene::new();
+        for i in 0..3 {
+            let (a, b) = (corners[i], corners[(i + 1) % 3]);
+            let (back, front) = (Vec3::new(0.0, 0.0, -1.0), Vec3::new(0.0, 0.0, 1.0));
+            for triangle in [
+                Triangle::new(a + back, b + back, b + front),
+                Triangle::new(a + back, b + front, a + front),
+            ] {
+                scene.add_object(Object::new(
+                    Box::new(triangle),
+                    Box::new(Dispersive::flint(0.0)),
+                ));
+            }
+        }
+
+        let light = Vec3::new(-2.0, 0.2, 0.0);
+        let floor_x = |lambda: f64| {
+            let target = (corners[0] + corners[2]) / 2.0;
+            let mut ray = Ray::new(light, target - light);
+            while let Some((id, mut record)) = scene.intersect(ray) {
+                record.wavelengths = Some(Wavelengths([lambda; 3]));
+                let material = &scene.object(id).material;
+                let frame = record.frame();
+                let wo = frame.to_local(-ray.direction.unit_vector());
+                // Only follow the light that goes through the prism.
+                let sample = (0..1000)
+                    .filter_map(|_| material.sample(record, wo))
+                    .find(|sample| sample.lobe == Lobe::Transmission)
+                    .unwrap();
+                ray = Ray::new(record.p, frame.to_world(sample.wi));
+            }
+            assert!(ray.direction.y < 0.0);
+            ray.at(-ray.origin.y / ray.direction.y).x
+        };
+        let [violet, blue, green, yellow, red] = [420.0, 470.0, 530.0, 580.0, 650.0].map(floor_x);
+        assert!(violet < blue && blue < green && green < yellow && yellow < red);
+        assert!(red - violet > 0.2, "{violet} {red}");
+    }
+
+    #[test]
+    fn test_sample_matches_eval_and_pdf() {
+        let material = Dispersive::diamond(0.4);
+        let wo = Vec3::new(0.3, -0.2, 1.0).unit_vector();
+        for front_face in [true, false] {
+            let record = record(front_face);
+            for _ in 0..1000 {
+                if let Some(sample) = material.sample(record, wo) {
+                    let pdf = material.pdf(record, wo, sample.wi);
+                    let expected = material.eval(record, wo, sample.wi) / pdf;
+                    assert!(
+                        (sample.weight.g() - expected.g()).abs() < 1e-6,
+                        "{sample:?}"
+                    );
+                    assert!((sample.pdf - pdf).abs() < 1e-6 * pdf);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,51 @@
+use crate::{color::Color, hitrecord::HitRecord, texture::Texture, vec3::Vec3};
+
+use super::{BsdfSample, Lobe, Material};
+
+/// A transparent surface that glows, e.g. to add an emissive layer on top of
+/// another material (see `Layered`), like the glowing paint of a sign. Rays
+/// pass straight through it, so on its own it is a glowing ghost of a surface.
+#[derive(Debug)]
+pub struct Emissive {
+    color: Box<dyn Texture>,
+    strength: f64,
+}
+
+impl Emissive {
+    /// A surface that emits light of the given color (or texture), scaled by
+    /// `strength`.
+    pub fn new(color: impl Texture + 'static, strength: f64) -> Self {
+        Emissive {
+            color: Box::new(color),
+            strength,
+        }
+    }
+}
+
+impl Material for Emissive {
+    fn sample(&self, _record: HitRecord, wo: Vec3) -> Option<BsdfSample> {
+        Some(BsdfSample {
+            wi: -wo,
+            weight: Color::WHITE,
+            pdf: 0.0,
+            delta: true,
+            lobe: Lobe::Transmission,
+        })
+    }
+
+    fn eval(&self, _record: HitRecord, _wo: Vec3, _wi: Vec3) -> Color {
+        Color::BLACK
+    }
+
+    fn pdf(&self, _record: HitRecord, _wo: Vec3, _wi: Vec3) -> f64 {
+        0.0
+    }
+
+    fn emitted(&self, record: HitRecord) -> Color {
+        self.color.lookup(&record) * self.strength
+    }
+
+    fn transmission(&self, _record: HitRecord, _wo: Vec3) -> Color {
+        Color::WHITE
+    }
+}
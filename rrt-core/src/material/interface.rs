@@ -0,0 +1,37 @@
+use crate::{color::Color, hitrecord::HitRecord, vec3::Vec3};
+
+use super::{BsdfSample, Lobe, Material};
+
+/// The surface of an object that is only made of a medium (see
+/// `Object::volume`). Rays pass straight through it, and the renderer doesn't
+/// count crossing it as a bounce.
+#[derive(Debug)]
+pub struct Interface;
+
+impl Material for Interface {
+    fn sample(&self, _record: HitRecord, wo: Vec3) -> Option<BsdfSample> {
+        Some(BsdfSample {
+            wi: -wo,
+            weight: Color::WHITE,
+            pdf: 0.0,
+            delta: true,
+            lobe: Lobe::Transmission,
+        })
+    }
+
+    fn eval(&self, _record: HitRecord, _wo: Vec3, _wi: Vec3) -> Color {
+        Color::BLACK
+    }
+
+    fn pdf(&self, _record: HitRecord, _wo: Vec3, _wi: Vec3) -> f64 {
+        0.0
+    }
+
+    fn transmission(&self, _record: HitRecord, _wo: Vec3) -> Color {
+        Color::WHITE
+    }
+
+    fn is_interface(&self) -> bool {
+        true
+    }
+}
@@ -1,7 +1,10 @@
-use crate::{color::Color, hitrecord::HitRecord, ray::Ray, utils::random_in_unit_sphere};
+use std::f64::consts::PI;
 
-use super::Material;
+use crate::{color::Color, hitrecord::HitRecord, utils::random_cosine_direction, vec3::Vec3};
 
+use super::{BsdfSample, Lobe, Material};
+
+#[derive(Debug)]
 pub struct Lambertian {
     albedo: Color,
 }
@@ -13,9 +16,27 @@ impl Lambertian {
 }
 
 impl Material for Lambertian {
-    fn scatter(&self, record: HitRecord) -> (Ray, Color) {
-        let outgoing_ray = Ray::new(record.p, record.normal + random_in_unit_sphere());
-        let attenuation = self.albedo;
-        (outgoing_ray, attenuation)
+    /// Picks a direction proportionally to the cosine, which cancels out the
+    /// cosine and the π of the BRDF, so the weight is simply the albedo.
+    fn sample(&self, _record: HitRecord, _wo: Vec3) -> Option<BsdfSample> {
+        let wi = random_cosine_direction();
+        Some(BsdfSample {
+            wi,
+            weight: self.albedo,
+            pdf: wi.z / PI,
+            delta: false,
+            lobe: Lobe::Diffuse,
+        })
+    }
+
+    /// A Lambertian surface reflects light equally in all directions, so the
+    /// BRDF is a constant albedo / π (the π makes sure no more light is
+    /// reflected than arrives).
+    fn eval(&self, _record: HitRecord, _wo: Vec3, wi: Vec3) -> Color {
+        self.albedo * (wi.z.max(0.0) / PI)
+    }
+
+    fn pdf(&self, _record: HitRecord, _wo: Vec3, wi: Vec3) -> f64 {
+        wi.z.max(0.0) / PI
     }
 }
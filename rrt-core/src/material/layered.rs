@@ -0,0 +1,198 @@
+use crate::{color::Color, hitrecord::HitRecord, medium::Medium, utils::random_double, vec3::Vec3};
+
+use super::{spectral_value, BsdfSample, Material};
+
+/// A material on top of another one, e.g. a clear coat of varnish on wood or
+/// car paint, or a glowing layer (see `Emissive`). The light that goes through
+/// the top layer (see `Material::transmission`) reaches the bottom one, and
+/// the light the bottom layer reflects goes back out through the top one:
+///
+///    f = f_top + T_top(wo) * f_bottom * T_top(wi)
+///
+/// The layers are infinitely thin and the light only bounces once between
+/// them, so the light reflected back by the underside of the top layer is
+/// lost. This darkens the material a little, but it never creates energy.
+///
+/// Reference: Weidlich & Wilkie, "Arbitrarily Layered Micro-Facet Surfaces"
+#[derive(Debug)]
+pub struct Layered {
+    top: Box<dyn Material>,
+    bottom: Box<dyn Material>,
+}
+
+impl Layered {
+    pub fn new(top: impl Material + 'static, bottom: impl Material + 'static) -> Self {
+        Layered {
+            top: Box::new(top),
+            bottom: Box::new(bottom),
+        }
+    }
+
+    /// The fraction of the light going through the top layer along w.
+    fn top_transmission(&self, record: &HitRecord, w: Vec3) -> Color {
+        let transmission = self.top.transmission(*record, w);
+        spectral_value(self, self.top.as_ref(), record, transmission)
+    }
+
+    /// The probability of sampling the bottom layer, how much of the light
+    /// reaches it.
+    fn bottom_probability(&self, record: &HitRecord, wo: Vec3) -> f64 {
+        let transmission = self.top_transmission(record, wo);
+        ((transmission.r() + transmission.g() + transmission.b()) / 3.0).clamp(0.0, 1.0)
+    }
+
+    /// The fraction of the light scattered by the bottom layer towards wi
+    /// that makes it out: light going back up goes through the top layer
+    /// again, light going down only went through it once, on its way in.
+    fn exit_transmission(&self, record: &HitRecord, wi: Vec3) -> Color {
+        match wi.z > 0.0 {
+            true => self.top_transmission(record, wi),
+            false => Color::WHITE,
+        }
+    }
+}
+
+impl Material for Layered {
+    /// Picks the bottom layer with the probability of the light reaching it,
+    /// and the top one otherwise. Only the reflection of the top layer is
+    /// kept, since the light it transmits is what the bottom layer scatters.
+    fn sample(&self, record: HitRecord, wo: Vec3) -> Option<BsdfSample> {
+        let probability = self.bottom_probability(&record, wo);
+        let bottom = random_double() < probability;
+        let sample = match bottom {
+            true => self.bottom.sample(record, wo)?,
+            false => self.top.sample(record, wo)?,
+        };
+        if !bottom && sample.wi.z <= 0.0 {
+            return None;
+        }
+        if sample.delta {
+            let weight = match bottom {
+                true => {
+                    let weight = spectral_value(self, self.bottom.as_ref(), &record, sample.weight);
+                    weight
+                        * self.top_transmission(&record, wo)
+                        * self.exit_transmission(&record, sample.wi)
+                        / probability
+                }
+                false => {
+                    spectral_value(self, self.top.as_ref(), &record, sample.weight)
+                        / (1.0 - probability)
+                }
+            };
+            return Some(BsdfSample { weight, ..sample });
+        }
+        let pdf = self.pdf(record, wo, sample.wi);
+        if pdf <= 0.0 {
+            return None;
+        }
+        Some(BsdfSample {
+            weight: self.eval(record, wo, sample.wi) / pdf,
+            pdf,
+            ..sample
+        })
+    }
+
+    fn eval(&self, record: HitRecord, wo: Vec3, wi: Vec3) -> Color {
+        let bottom = self.bottom.eval(record, wo, wi);
+        let bottom = spectral_value(self, self.bottom.as_ref(), &record, bottom)
+            * self.top_transmission(&record, wo)
+            * self.exit_transmission(&record, wi);
+        if wi.z <= 0.0 {
+            return bottom;
+        }
+        let top = self.top.eval(record, wo, wi);
+        spectral_value(self, self.top.as_ref(), &record, top) + bottom
+    }
+
+    fn pdf(&self, record: HitRecord, wo: Vec3, wi: Vec3) -> f64 {
+        let probability = self.bottom_probability(&record, wo);
+        let bottom = self.bottom.pdf(record, wo, wi) * probability;
+        if wi.z <= 0.0 {
+            return bottom;
+        }
+        self.top.pdf(record, wo, wi) * (1.0 - probability) + bottom
+    }
+
+    /// The light emitted by the top layer, plus the light emitted by the
+    /// bottom one that goes through the top one.
+    fn emitted(&self, record: HitRecord) -> Color {
+        let wo = record.frame().to_local(-record.incoming.unit_vector());
+        self.top.emitted(record) + self.bottom.emitted(record) * self.top_transmission(&record, wo)
+    }
+
+    fn transmission(&self, record: HitRecord, wo: Vec3) -> Color {
+        let bottom = self.bottom.transmission(record, wo);
+        spectral_value(self, self.bottom.as_ref(), &record, bottom)
+            * self.top_transmission(&record, wo)
+    }
+
+    /// The medium below the bottom layer.
+    fn medium(&self) -> Option<&dyn Medium> {
+        self.bottom.medium()
+    }
+
+    fn is_spectral(&self) -> bool {
+        self.top.is_spectral() || self.bottom.is_spectral()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::{Dielectric, Emissive, Lambertian};
+
+    /// The average weight of the samples, i.e. the fraction of the light
+    /// arriving from wo that is scattered.
+    fn albedo(material: &dyn Material, wo: Vec3) -> Color {
+        let record = HitRecord::new();
+        let samples = 200_000;
+        let mut total = Color::BLACK;
+        for _ in 0..samples {
+            if let Some(sample) = material.sample(record, wo) {
+                if !sample.delta {
+                    let pdf = material.pdf(record, wo, sample.wi);
+                    let expected = material.eval(record, wo, sample.wi) / pdf;
+                    assert!((sample.weight.r() - expected.r()).abs() < 1e-6);
+                    assert!((sample.pdf - pdf).abs() < 1e-9);
+                }
+                total = total + sample.weight;
+            }
+        }
+        total / samples as f64
+    }
+
+    #[test]
+    fn test_clearcoat_conserves_energy() {
+        let clearcoat = Layered::new(Dielectric::new(1.5, 0.0), Lambertian::new(Color::WHITE));
+        for cos in [1.0f64, 0.7, 0.3, 0.1] {
+            let wo = Vec3::new((1.0 - cos * cos).sqrt(), 0.0, cos);
+            let albedo = albedo(&clearcoat, wo);
+            assert!(albedo.r() <= 1.0, "{cos} {albedo:?}");
+            // Only the light reflected back inside the coat is lost, about
+            // 10% for glass.
+            assert!(albedo.r() > 0.85, "{cos} {albedo:?}");
+        }
+    }
+
+    #[test]
+    fn test_rough_clearcoat_matches_eval_and_pdf() {
+        let clearcoat = Layered::new(
+            Dielectric::new(1.5, 0.3),
+            Lambertian::new(Color::new(0.8, 0.1, 0.1)),
+        );
+        let wo = Vec3::new(0.6, 0.0, 0.8);
+        let albedo = albedo(&clearcoat, wo);
+        assert!(albedo.r() <= 1.0 && albedo.r() > 0.6, "{albedo:?}");
+    }
+
+    #[test]
+    fn test_emissive_layer_lets_light_through() {
+        let glowing = Layered::new(
+            Emissive::new(Color::WHITE, 2.0),
+            Lambertian::new(Color::new(0.5, 0.5, 0.5)),
+        );
+        let albedo = albedo(&glowing, Vec3::new(0.0, 0.0, 1.0));
+        assert!((albedo.r() - 0.5).abs() < 0.01, "{albedo:?}");
+    }
+}
@@ -0,0 +1,399 @@
+//! The math shared by the rough (microfacet) materials.
+//!
+//! A rough surface is modelled as a lot of tiny perfectly smooth facets, whose
+//! normals (the microfacet normals m) are spread around the surface normal.
+//! Light is reflected (or refracted) by a single facet, so the rougher the
+//! surface, the blurrier the reflections. All the directions here are in the
+//! local shading frame (see `Material`), where the normal is +z, and point
+//! away from the surface.
+
+use std::f64::consts::PI;
+
+use crate::{color::Color, utils::random_double, vec3::Vec3};
+
+use super::{BsdfSample, Lobe};
+
+/// Below this alpha a surface is treated as perfectly smooth (see
+/// `BsdfSample::delta`).
+pub(crate) const SMOOTH_ALPHA: f64 = 1e-3;
+
+/// The GGX (a.k.a. Trowbridge-Reitz) distribution of microfacet normals with
+/// the height-correlated Smith shadowing-masking function.
+/// Reference: Walter et al., "Microfacet Models for Refraction through Rough Surfaces"
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Ggx {
+    /// The width of the distribution. We use alpha = roughness², which makes
+    /// the roughness feel more linear.
+    pub alpha: f64,
+}
+
+impl Ggx {
+    pub fn from_roughness(roughness: f64) -> Self {
+        Ggx {
+            alpha: (roughness * roughness).max(SMOOTH_ALPHA),
+        }
+    }
+
+    /// Whether the distribution is narrow enough to be treated as perfectly
+    /// smooth.
+    pub fn is_smooth(&self) -> bool {
+        self.alpha <= SMOOTH_ALPHA
+    }
+
+    /// The density of microfacet normals:
+    ///
+    ///    D(m) = α² / (π * (cos²θ_m * (α² - 1) + 1)²)
+    pub fn d(&self, m: Vec3) -> f64 {
+        if m.z <= 0.0 {
+            return 0.0;
+        }
+        let a2 = self.alpha * self.alpha;
+        let t = m.z * m.z * (a2 - 1.0) + 1.0;
+        a2 / (PI * t * t)
+    }
+
+    /// The Smith Λ function, from which the fraction of the facets that is
+    /// visible from direction `w` is G1(w) = 1 / (1 + Λ(w)).
+    fn lambda(&self, w: Vec3) -> f64 {
+        let cos2 = w.z * w.z;
+        if cos2 <= 0.0 {
+            return f64::INFINITY;
+        }
+        let tan2 = (1.0 - cos2) / cos2;
+        ((1.0 + self.alpha * self.alpha * tan2).sqrt() - 1.0) / 2.0
+    }
+
+    /// The fraction of the facets that is visible from direction `w`.
+    pub fn g1(&self, w: Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    /// The fraction of the facets that is visible from both `wo` and `wi`.
+    /// Facets that are visible from one direction are more likely to also be
+    /// visible from the other (they are higher up), hence "height-correlated".
+    pub fn g2(&self, wo: Vec3, wi: Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    /// Samples a microfacet normal proportionally to how much of it is visible
+    /// from `wo`, i.e. with the pdf D_wo(m) = G1(wo) * max(wo·m, 0) * D(m) / wo.z.
+    /// This avoids facets that face away from `wo`, which would give samples
+    /// with little or no contribution.
+    /// Reference: Heitz, "Sampling the GGX Distribution of Visible Normals"
+    pub fn sample_visible_normal(&self, wo: Vec3) -> Vec3 {
+        // Stretch the view direction so that the distribution becomes a
+        // hemisphere with α = 1.
+        let vh = Vec3::new(self.alpha * wo.x, self.alpha * wo.y, wo.z).unit_vector();
+
+        // An orthonormal basis around the view direction.
+        let length_squared = vh.x * vh.x + vh.y * vh.y;
+        let t1 = if length_squared > 0.0 {
+            Vec3::new(-vh.y, vh.x, 0.0) / length_squared.sqrt()
+        } else {
+            Vec3::X
+        };
+        let t2 = vh.cross(t1);
+
+        // A point on the disk, squashed towards the visible half.
+        let r = random_double().sqrt();
+        let phi = 2.0 * PI * random_double();
+        let p1 = r * phi.cos();
+        let s = 0.5 * (1.0 + vh.z);
+        let p2 = (1.0 - s) * (1.0 - p1 * p1).sqrt() + s * r * phi.sin();
+
+        // Project it onto the hemisphere and unstretch it.
+        let nh = t1 * p1 + t2 * p2 + vh * (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt();
+        Vec3::new(self.alpha * nh.x, self.alpha * nh.y, nh.z.max(1e-6)).unit_vector()
+    }
+
+    /// The probability density of `sample_visible_normal` picking `m`.
+    pub fn visible_normal_pdf(&self, wo: Vec3, m: Vec3) -> f64 {
+        if wo.z <= 0.0 {
+            return 0.0;
+        }
+        self.g1(wo) * wo.dot(m).max(0.0) * self.d(m) / wo.z
+    }
+}
+
+/// How the reflectance of a microfacet depends on the angle of incidence.
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum Fresnel {
+    /// Schlick's approximation, given the reflectance at normal incidence.
+    Schlick(Color),
+
+    /// A metal with the complex index of refraction eta + i * k.
+    Conductor { eta: Color, k: Color },
+}
+
+impl Fresnel {
+    pub fn eval(&self, cos_i: f64) -> Color {
+        match *self {
+            Fresnel::Schlick(f0) => schlick(f0, cos_i),
+            Fresnel::Conductor { eta, k } => fresnel_conductor(cos_i, eta, k),
+        }
+    }
+}
+
+/// A rough reflective surface, i.e. a surface whose facets are perfect
+/// mirrors (with the given Fresnel term), like a metal.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct MicrofacetReflection {
+    pub ggx: Ggx,
+    pub fresnel: Fresnel,
+}
+
+impl MicrofacetReflection {
+    /// Reflects wo off of a microfacet normal sampled from the visible
+    /// normals. Most of the BRDF then cancels out with the pdf, and the weight
+    /// of the sample is simply:
+    ///
+    ///    F(wo·m) * G2(wo, wi) / G1(wo)
+    ///
+    /// Returns `None` if the ray is reflected below the surface. That light
+    /// would bounce off of another facet, which the model doesn't account
+    /// for, so it is lost.
+    pub fn sample(&self, wo: Vec3) -> Option<BsdfSample> {
+        if wo.z <= 0.0 {
+            return None;
+        }
+        if self.ggx.is_smooth() {
+            return Some(BsdfSample {
+                wi: reflect(wo, Vec3::Z),
+                weight: self.fresnel.eval(wo.z),
+                pdf: 0.0,
+                delta: true,
+                lobe: Lobe::Glossy,
+            });
+        }
+        let m = self.ggx.sample_visible_normal(wo);
+        let wi = reflect(wo, m);
+        if wi.z <= 0.0 {
+            return None;
+        }
+        Some(BsdfSample {
+            wi,
+            weight: self.fresnel.eval(wo.dot(m)) * (self.ggx.g2(wo, wi) / self.ggx.g1(wo)),
+            pdf: self.pdf(wo, wi),
+            delta: false,
+            lobe: Lobe::Glossy,
+        })
+    }
+
+    /// The Cook-Torrance microfacet BRDF (times the cosine):
+    ///
+    ///    f(wo, wi) * cos θ_i = F(wo·h) * D(h) * G2(wo, wi) / (4 * cos θ_o)
+    ///
+    /// where h is the half vector between wo and wi.
+    pub fn eval(&self, wo: Vec3, wi: Vec3) -> Color {
+        if self.ggx.is_smooth() || wo.z <= 0.0 || wi.z <= 0.0 {
+            return Color::BLACK;
+        }
+        let h = (wo + wi).unit_vector();
+        let value = self.ggx.d(h) * self.ggx.g2(wo, wi) / (4.0 * wo.z);
+        self.fresnel.eval(wo.dot(h)) * value
+    }
+
+    /// The pdf of the visible normal, divided by the Jacobian of the
+    /// reflection (4 * wo·h).
+    pub fn pdf(&self, wo: Vec3, wi: Vec3) -> f64 {
+        if self.ggx.is_smooth() || wo.z <= 0.0 || wi.z <= 0.0 {
+            return 0.0;
+        }
+        let h = (wo + wi).unit_vector();
+        self.ggx.visible_normal_pdf(wo, h) / (4.0 * wo.dot(h))
+    }
+}
+
+/// Reflects `w` about the normal `m` (both pointing away from the surface).
+pub(crate) fn reflect(w: Vec3, m: Vec3) -> Vec3 {
+    m * (2.0 * w.dot(m)) - w
+}
+
+/// Refracts `w` through the facet with normal `m`, where `eta` is the ratio of
+/// the index of refraction on the other side of the surface to the one on the
+/// side of `w`. Returns `None` for total internal reflection.
+pub(crate) fn refract(w: Vec3, m: Vec3, eta: f64) -> Option<Vec3> {
+    let cos_i = w.dot(m);
+    let sin2_t = (1.0 - cos_i * cos_i).max(0.0) / (eta * eta);
+    if sin2_t >= 1.0 {
+        return None;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    Some(-w / eta + m * (cos_i / eta - cos_t))
+}
+
+/// Schlick's approximation of the Fresnel reflectance, given the reflectance
+/// `f0` at normal incidence:
+///
+///    F(θ) = f0 + (1 - f0) * (1 - cos θ)⁵
+pub(crate) fn schlick(f0: Color, cos_theta: f64) -> Color {
+    f0 + (Color::WHITE - f0) * (1.0 - cos_theta.clamp(0.0, 1.0)).powi(5)
+}
+
+/// The fraction of light reflected by a smooth dielectric (e.g. glass or
+/// water), where `cos_i` is the cosine of the angle of incidence and `eta` is
+/// the ratio of the index of refraction on the other side of the surface to
+/// the one on the side of the light. The rest of the light is refracted.
+pub(crate) fn fresnel_dielectric(cos_i: f64, eta: f64) -> f64 {
+    let cos_i = cos_i.clamp(0.0, 1.0);
+    let sin2_t = (1.0 - cos_i * cos_i) / (eta * eta);
+    if sin2_t >= 1.0 {
+        // Total internal reflection.
+        return 1.0;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    let rs = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    let rp = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    (rs * rs + rp * rp) / 2.0
+}
+
+/// The fraction of light reflected by a metal with the complex index of
+/// refraction eta + i * k (per color channel), where `cos_i` is the cosine of
+/// the angle of incidence. Unlike dielectrics, metals absorb all the light
+/// that isn't reflected.
+/// Reference: Pharr et al., "Physically Based Rendering", section 8.2.1
+pub(crate) fn fresnel_conductor(cos_i: f64, eta: Color, k: Color) -> Color {
+    let channel = |eta: f64, k: f64| {
+        let cos2 = cos_i.clamp(0.0, 1.0).powi(2);
+        let sin2 = 1.0 - cos2;
+        let t0 = eta * eta - k * k - sin2;
+        let a2_plus_b2 = (t0 * t0 + 4.0 * eta * eta * k * k).sqrt();
+        let t1 = a2_plus_b2 + cos2;
+        let a = (0.5 * (a2_plus_b2 + t0)).max(0.0).sqrt();
+        let t2 = 2.0 * cos2.sqrt() * a;
+        let rs = (t1 - t2) / (t1 + t2);
+        let t3 = cos2 * a2_plus_b2 + sin2 * sin2;
+        let t4 = t2 * sin2;
+        let rp = rs * (t3 - t4) / (t3 + t4);
+        (rs + rp) / 2.0
+    };
+    Color::new(
+        channel(eta.r(), k.r()),
+        channel(eta.g(), k.g()),
+        channel(eta.b(), k.b()),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLES: usize = 200_000;
+
+    fn direction(cos_theta: f64) -> Vec3 {
+        Vec3::new((1.0 - cos_theta * cos_theta).sqrt(), 0.0, cos_theta)
+    }
+
+    /// The fraction of the energy arriving from `wo` that a white rough mirror
+    /// reflects, estimated with visible normal sampling.
+    fn albedo(ggx: Ggx, wo: Vec3) -> f64 {
+        let mut total = 0.0;
+        for _ in 0..SAMPLES {
+            let wi = reflect(wo, ggx.sample_visible_normal(wo));
+            if wi.z > 0.0 {
+                total += ggx.g2(wo, wi) / ggx.g1(wo);
+            }
+        }
+        total / SAMPLES as f64
+    }
+
+    #[test]
+    fn test_energy_of_rough_mirror() {
+        // A single bounce on the facets can't reflect more energy than it
+        // receives. It only loses the light that would bounce off of several
+        // facets, which grows with the roughness (up to about half the light
+        // for very rough surfaces).
+        for cos_theta in [1.0, 0.5, 0.2] {
+            let mut previous = 1.0;
+            for roughness in [0.1, 0.5, 0.8] {
+                let albedo = albedo(Ggx::from_roughness(roughness), direction(cos_theta));
+                assert!(
+                    albedo <= previous + 0.01,
+                    "{roughness} {cos_theta} {albedo}"
+                );
+                assert!(albedo > 0.45, "{roughness} {cos_theta} {albedo}");
+                previous = albedo;
+            }
+        }
+        assert!(albedo(Ggx::from_roughness(0.1), direction(1.0)) > 0.999);
+    }
+
+    #[test]
+    fn test_visible_normal_sampling_matches_brdf() {
+        // Integrating the BRDF D * G2 / (4 * cos_o) over the hemisphere with
+        // uniform samples must give the same albedo as sampling visible normals.
+        let ggx = Ggx::from_roughness(0.7);
+        let wo = direction(0.6);
+        let mut total = 0.0;
+        for _ in 0..SAMPLES {
+            let z = random_double();
+            let phi = 2.0 * PI * random_double();
+            let r = (1.0 - z * z).sqrt();
+            let wi = Vec3::new(r * phi.cos(), r * phi.sin(), z);
+            let m = (wo + wi).unit_vector();
+            let brdf_cos = ggx.d(m) * ggx.g2(wo, wi) / (4.0 * wo.z);
+            total += brdf_cos * 2.0 * PI;
+        }
+        let uniform = total / SAMPLES as f64;
+        assert!((uniform - albedo(ggx, wo)).abs() < 0.02);
+    }
+
+    #[test]
+    fn test_reflection_sample_matches_eval_and_pdf() {
+        let reflection = MicrofacetReflection {
+            ggx: Ggx::from_roughness(0.4),
+            fresnel: Fresnel::Schlick(Color::WHITE * 0.5),
+        };
+        let wo = direction(0.7);
+        for _ in 0..1000 {
+            if let Some(sample) = reflection.sample(wo) {
+                let pdf = reflection.pdf(wo, sample.wi);
+                let expected = reflection.eval(wo, sample.wi) / pdf;
+                assert!((sample.weight.r() - expected.r()).abs() < 1e-6 * expected.r().max(1.0));
+                assert!((sample.pdf - pdf).abs() < 1e-6 * pdf);
+            }
+        }
+    }
+
+    #[test]
+    fn test_distribution_is_normalized() {
+        // The projected area of the facets is the area of the surface:
+        // ∫ D(m) cos θ_m dm = 1.
+        let ggx = Ggx::from_roughness(0.5);
+        let mut total = 0.0;
+        for _ in 0..SAMPLES {
+            let z = random_double();
+            let phi = 2.0 * PI * random_double();
+            let r = (1.0 - z * z).sqrt();
+            let m = Vec3::new(r * phi.cos(), r * phi.sin(), z);
+            total += ggx.d(m) * m.z * 2.0 * PI;
+        }
+        assert!((total / SAMPLES as f64 - 1.0).abs() < 0.02);
+    }
+
+    #[test]
+    fn test_fresnel() {
+        // Glass reflects about 4% of the light at normal incidence and all of
+        // it at grazing angles.
+        assert!((fresnel_dielectric(1.0, 1.5) - 0.04).abs() < 1e-9);
+        assert!(fresnel_dielectric(1e-6, 1.5) > 0.99);
+        // Total internal reflection when leaving glass at a shallow angle.
+        assert_eq!(fresnel_dielectric(0.3, 1.0 / 1.5), 1.0);
+
+        // A conductor without absorption is a dielectric.
+        let conductor = fresnel_conductor(0.7, Color::WHITE * 1.5, Color::BLACK);
+        assert!((conductor.r() - fresnel_dielectric(0.7, 1.5)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_refract() {
+        let w = direction(0.8);
+        let wt = refract(w, Vec3::Z, 1.5).unwrap();
+        // Snell's law: sin θ_i = η sin θ_t.
+        let sin_i = (1.0 - w.z * w.z).sqrt();
+        let sin_t = (1.0 - wt.z * wt.z).sqrt();
+        assert!((sin_i - 1.5 * sin_t).abs() < 1e-9);
+        assert!(wt.z < 0.0 && (wt.length() - 1.0).abs() < 1e-9);
+        assert!(refract(direction(0.3), Vec3::Z, 1.0 / 1.5).is_none());
+    }
+}
@@ -0,0 +1,150 @@
+use crate::{
+    color::Color, hitrecord::HitRecord, medium::Medium, texture::Texture, utils::random_double,
+    vec3::Vec3,
+};
+
+use super::{spectral_value, BsdfSample, Material};
+
+/// A blend of two materials by a mask, e.g. rust (a rough diffuse material)
+/// on a metal, painted with a texture. Where the mask is 0 the surface is made
+/// of the first material, where it is 1 of the second one, and in between the
+/// BSDF is the weighted average of both:
+///
+///    f = (1 - m) * f_a + m * f_b
+#[derive(Debug)]
+pub struct Mix {
+    a: Box<dyn Material>,
+    b: Box<dyn Material>,
+
+    /// The mask (only the red channel is used).
+    mask: Box<dyn Texture>,
+}
+
+impl Mix {
+    pub fn new(
+        a: impl Material + 'static,
+        b: impl Material + 'static,
+        mask: impl Texture + 'static,
+    ) -> Self {
+        Mix {
+            a: Box::new(a),
+            b: Box::new(b),
+            mask: Box::new(mask),
+        }
+    }
+
+    /// The weight of the second material at the hit.
+    fn mask(&self, record: &HitRecord) -> f64 {
+        self.mask.lookup(record).r().clamp(0.0, 1.0)
+    }
+
+    /// The weighted average of a value of both materials.
+    fn blend(&self, record: &HitRecord, value: impl Fn(&dyn Material) -> Color) -> Color {
+        let mask = self.mask(record);
+        let a = spectral_value(self, self.a.as_ref(), record, value(self.a.as_ref()));
+        let b = spectral_value(self, self.b.as_ref(), record, value(self.b.as_ref()));
+        a * (1.0 - mask) + b * mask
+    }
+}
+
+impl Material for Mix {
+    /// Picks one of the materials with the probability of its weight, and
+    /// samples it. The sample could have been picked by either material, so
+    /// its weight is the blended BSDF divided by the blended pdf, except for
+    /// perfectly smooth lobes, for which the weight of the material cancels
+    /// out with the probability of picking it.
+    fn sample(&self, record: HitRecord, wo: Vec3) -> Option<BsdfSample> {
+        let mask = self.mask(&record);
+        let material = match random_double() < mask {
+            true => self.b.as_ref(),
+            false => self.a.as_ref(),
+        };
+        let sample = material.sample(record, wo)?;
+        if sample.delta {
+            return Some(BsdfSample {
+                weight: spectral_value(self, material, &record, sample.weight),
+                ..sample
+            });
+        }
+        let pdf = self.pdf(record, wo, sample.wi);
+        if pdf <= 0.0 {
+            return None;
+        }
+        Some(BsdfSample {
+            weight: self.eval(record, wo, sample.wi) / pdf,
+            pdf,
+            ..sample
+        })
+    }
+
+    fn eval(&self, record: HitRecord, wo: Vec3, wi: Vec3) -> Color {
+        self.blend(&record, |material| material.eval(record, wo, wi))
+    }
+
+    fn pdf(&self, record: HitRecord, wo: Vec3, wi: Vec3) -> f64 {
+        let mask = self.mask(&record);
+        self.a.pdf(record, wo, wi) * (1.0 - mask) + self.b.pdf(record, wo, wi) * mask
+    }
+
+    fn emitted(&self, record: HitRecord) -> Color {
+        let mask = self.mask(&record);
+        self.a.emitted(record) * (1.0 - mask) + self.b.emitted(record) * mask
+    }
+
+    fn transmission(&self, record: HitRecord, wo: Vec3) -> Color {
+        self.blend(&record, |material| material.transmission(record, wo))
+    }
+
+    /// The medium of the first material that has one. Objects can only be
+    /// filled with a single medium, whatever the mask is.
+    fn medium(&self) -> Option<&dyn Medium> {
+        self.a.medium().or_else(|| self.b.medium())
+    }
+
+    fn is_spectral(&self) -> bool {
+        self.a.is_spectral() || self.b.is_spectral()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::{Conductor, Lambertian};
+
+    #[test]
+    fn test_sample_matches_eval_and_pdf() {
+        let mix = Mix::new(
+            Lambertian::new(Color::new(0.8, 0.2, 0.2)),
+            Conductor::gold(0.3),
+            0.25,
+        );
+        let wo = Vec3::new(0.4, 0.3, 1.0).unit_vector();
+        let record = HitRecord::new();
+        let samples = 200_000;
+        let mut total = Color::BLACK;
+        for _ in 0..samples {
+            if let Some(sample) = mix.sample(record, wo) {
+                let pdf = mix.pdf(record, wo, sample.wi);
+                let expected = mix.eval(record, wo, sample.wi) / pdf;
+                assert!((sample.weight.r() - expected.r()).abs() < 1e-6);
+                assert!((sample.pdf - pdf).abs() < 1e-9);
+                total = total + sample.weight;
+            }
+        }
+        // The albedo is the blend of the albedos of both materials.
+        let albedo = total / samples as f64;
+        let gold = (0..samples)
+            .filter_map(|_| Conductor::gold(0.3).sample(record, wo))
+            .fold(Color::BLACK, |total, sample| total + sample.weight)
+            / samples as f64;
+        let expected = Color::new(0.8, 0.2, 0.2) * 0.75 + gold * 0.25;
+        assert!(
+            (albedo.r() - expected.r()).abs() < 0.01,
+            "{albedo:?} {expected:?}"
+        );
+        assert!(
+            (albedo.b() - expected.b()).abs() < 0.01,
+            "{albedo:?} {expected:?}"
+        );
+    }
+}
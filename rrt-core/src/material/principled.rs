@@ -0,0 +1,504 @@
+use std::f64::consts::PI;
+
+use crate::{
+    color::Color,
+    hitrecord::HitRecord,
+    texture::Texture,
+    utils::{random_cosine_direction, random_double},
+    vec3::Vec3,
+};
+
+use super::{
+    microfacet::{schlick, Fresnel, Ggx, MicrofacetReflection},
+    BsdfSample, Dielectric, Lobe, Material,
+};
+
+/// An "uber" material that covers most real-world surfaces with a handful of
+/// intuitive parameters, modelled after the Disney principled BRDF (and the
+/// materials of glTF and most 3D software, so that assets can be mapped to it
+/// directly). Every parameter except the index of refraction can be driven by
+/// a texture (see `texture`).
+///
+/// The material is a mix of several lobes:
+///
+///    - a clear coat on top (e.g. car paint), which reflects some of the light
+///      and lets the rest through to the lobes below
+///    - a metal, with the base color as its reflectance
+///    - a dielectric (e.g. plastic), with a specular reflection on top of a
+///      diffuse base, plus sheen at grazing angles (e.g. cloth)
+///    - a transmissive dielectric (e.g. glass), tinted by the base color
+///
+/// where `metallic` blends from the dielectrics to the metal, and
+/// `transmission` from the opaque dielectric to the transmissive one.
+/// Reference: Burley, "Physically-Based Shading at Disney"
+#[derive(Debug)]
+pub struct Principled {
+    base_color: Box<dyn Texture>,
+    metallic: Box<dyn Texture>,
+    roughness: Box<dyn Texture>,
+
+    /// The strength of the specular reflection of dielectrics, where 0.5 is
+    /// the reflectance of most materials (4% at normal incidence).
+    specular: Box<dyn Texture>,
+    clearcoat: Box<dyn Texture>,
+    clearcoat_roughness: Box<dyn Texture>,
+
+    /// The color of the sheen (black for none).
+    sheen: Box<dyn Texture>,
+    transmission: Box<dyn Texture>,
+
+    /// The index of refraction of the transmissive dielectric.
+    ior: f64,
+    emission: Box<dyn Texture>,
+    emission_strength: f64,
+}
+
+/// The parameters of the material at a hit, looked up from the textures.
+struct Parameters {
+    base_color: Color,
+    metallic: f64,
+    specular: Color,
+    clearcoat: f64,
+    sheen: Color,
+    transmission: f64,
+    ggx: Ggx,
+    clearcoat_ggx: Ggx,
+
+    /// The transmissive dielectric.
+    glass: Dielectric,
+}
+
+/// The lobes of the material, in the same order as `Parameters::weights`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Component {
+    Clearcoat,
+    Metal,
+    Specular,
+    Diffuse,
+    Glass,
+}
+
+const COMPONENTS: [Component; 5] = [
+    Component::Clearcoat,
+    Component::Metal,
+    Component::Specular,
+    Component::Diffuse,
+    Component::Glass,
+];
+
+impl Principled {
+    /// A rough, white-ish plastic with the given base color.
+    pub fn new(base_color: impl Texture + 'static) -> Self {
+        Principled {
+            base_color: Box::new(base_color),
+            metallic: Box::new(0.0),
+            roughness: Box::new(0.5),
+            specular: Box::new(0.5),
+            clearcoat: Box::new(0.0),
+            clearcoat_roughness: Box::new(0.1),
+            sheen: Box::new(Color::BLACK),
+            transmission: Box::new(0.0),
+            ior: 1.5,
+            emission: Box::new(Color::BLACK),
+            emission_strength: 1.0,
+        }
+    }
+
+    pub fn set_base_color(&mut self, texture: impl Texture + 'static) {
+        self.base_color = Box::new(texture);
+    }
+
+    /// From 0 (a dielectric) to 1 (a metal).
+    pub fn set_metallic(&mut self, texture: impl Texture + 'static) {
+        self.metallic = Box::new(texture);
+    }
+
+    /// From 0 (perfectly smooth) to 1 (very rough).
+    pub fn set_roughness(&mut self, texture: impl Texture + 'static) {
+        self.roughness = Box::new(texture);
+    }
+
+    /// From 0 (no specular reflection) to 1 (8% reflectance at normal
+    /// incidence), 0.5 by default.
+    pub fn set_specular(&mut self, texture: impl Texture + 'static) {
+        self.specular = Box::new(texture);
+    }
+
+    /// The strength of the clear coat, from 0 (none) to 1.
+    pub fn set_clearcoat(&mut self, texture: impl Texture + 'static) {
+        self.clearcoat = Box::new(texture);
+    }
+
+    pub fn set_clearcoat_roughness(&mut self, texture: impl Texture + 'static) {
+        self.clearcoat_roughness = Box::new(texture);
+    }
+
+    /// The color of the sheen, a soft reflection at grazing angles that is
+    /// characteristic of cloth.
+    pub fn set_sheen(&mut self, texture: impl Texture + 'static) {
+        self.sheen = Box::new(texture);
+    }
+
+    /// From 0 (opaque) to 1 (fully transmissive, like glass).
+    pub fn set_transmission(&mut self, texture: impl Texture + 'static) {
+        self.transmission = Box::new(texture);
+    }
+
+    pub fn set_ior(&mut self, ior: f64) {
+        assert!(ior > 0.0);
+        self.ior = ior;
+    }
+
+    /// The color of the light emitted by the surface.
+    pub fn set_emission(&mut self, texture: impl Texture + 'static) {
+        self.emission = Box::new(texture);
+    }
+
+    /// Scales the emission, so that emitters can be brighter than 1.
+    pub fn set_emission_strength(&mut self, strength: f64) {
+        self.emission_strength = strength;
+    }
+
+    fn parameters(&self, record: &HitRecord) -> Parameters {
+        let lookup = |texture: &dyn Texture| texture.lookup(record);
+        let scalar = |texture: &dyn Texture| lookup(texture).r().clamp(0.0, 1.0);
+        let roughness = scalar(self.roughness.as_ref());
+        Parameters {
+            base_color: lookup(self.base_color.as_ref()),
+            metallic: scalar(self.metallic.as_ref()),
+            specular: Color::WHITE * (0.08 * scalar(self.specular.as_ref())),
+            clearcoat: scalar(self.clearcoat.as_ref()),
+            sheen: lookup(self.sheen.as_ref()),
+            transmission: scalar(self.transmission.as_ref()),
+            ggx: Ggx::from_roughness(roughness),
+            clearcoat_ggx: Ggx::from_roughness(scalar(self.clearcoat_roughness.as_ref())),
+            glass: Dielectric::new(self.ior, roughness),
+        }
+    }
+}
+
+impl Parameters {
+    /// The fraction of the light that gets through the clear coat to the
+    /// lobes below it.
+    fn coat_transmittance(&self, cos_o: f64) -> f64 {
+        1.0 - self.clearcoat * schlick(Color::WHITE * 0.04, cos_o).r()
+    }
+
+    /// The weight of the opaque dielectric (the specular and diffuse lobes).
+    fn opaque(&self) -> f64 {
+        (1.0 - self.metallic) * (1.0 - self.transmission)
+    }
+
+    /// The diffuse and sheen lobes, divided by cos θ_i / π. Light that is
+    /// reflected by the specular layer doesn't reach the diffuse base, so it
+    /// is darkened accordingly.
+    fn diffuse(&self, wo: Vec3, wi: Vec3) -> Color {
+        let cos_d = wi.dot((wo + wi).unit_vector()).max(0.0);
+        let base = self.base_color * (1.0 - schlick(self.specular, wo.z).r());
+        (base + self.sheen * (1.0 - cos_d).powi(5)) * self.opaque()
+    }
+
+    /// The glossy lobes, which are reflections off of the facets.
+    fn reflection(&self, component: Component) -> Option<MicrofacetReflection> {
+        let (ggx, f0) = match component {
+            Component::Clearcoat => (self.clearcoat_ggx, Color::WHITE * 0.04),
+            Component::Metal => (self.ggx, self.base_color),
+            Component::Specular => (self.ggx, self.specular),
+            Component::Diffuse | Component::Glass => return None,
+        };
+        Some(MicrofacetReflection {
+            ggx,
+            fresnel: Fresnel::Schlick(f0),
+        })
+    }
+
+    /// Whether the lobe is perfectly smooth (see `BsdfSample::delta`).
+    fn is_delta(&self, component: Component) -> bool {
+        match component {
+            Component::Clearcoat => self.clearcoat_ggx.is_smooth(),
+            Component::Metal | Component::Specular | Component::Glass => self.ggx.is_smooth(),
+            Component::Diffuse => false,
+        }
+    }
+
+    /// What the BSDF of each lobe is multiplied by (in the order of
+    /// `COMPONENTS`).
+    fn scales(&self, cos_o: f64) -> [f64; 5] {
+        let coat = self.coat_transmittance(cos_o);
+        [
+            self.clearcoat,
+            coat * self.metallic,
+            coat * self.opaque(),
+            coat,
+            coat * (1.0 - self.metallic) * self.transmission,
+        ]
+    }
+
+    /// A rough estimate of the fraction of the light that each lobe reflects
+    /// (in the order of `COMPONENTS`), used to decide which lobe to sample.
+    fn weights(&self, cos_o: f64) -> [f64; 5] {
+        let scales = self.scales(cos_o);
+        let dielectric = schlick(self.specular, cos_o).r();
+        let diffuse = (1.0 - dielectric) * (self.base_color + self.sheen).luminance();
+        [
+            scales[0] * schlick(Color::WHITE * 0.04, cos_o).r(),
+            scales[1] * schlick(self.base_color, cos_o).luminance(),
+            scales[2] * dielectric,
+            scales[3] * self.opaque() * diffuse,
+            scales[4],
+        ]
+    }
+
+    /// Samples a single lobe (without its scale).
+    fn sample_lobe(&self, component: Component, record: HitRecord, wo: Vec3) -> Option<BsdfSample> {
+        if let Some(reflection) = self.reflection(component) {
+            return reflection.sample(wo);
+        }
+        match component {
+            Component::Diffuse => {
+                let wi = random_cosine_direction();
+                Some(BsdfSample {
+                    wi,
+                    weight: self.diffuse(wo, wi),
+                    pdf: wi.z / PI,
+                    delta: false,
+                    lobe: Lobe::Diffuse,
+                })
+            }
+            _ => {
+                let mut sample = self.glass.sample(record, wo)?;
+                if sample.lobe == Lobe::Transmission {
+                    sample.weight = sample.weight * self.base_color;
+                }
+                Some(sample)
+            }
+        }
+    }
+
+    /// Evaluates a single lobe (without its scale).
+    fn eval_lobe(&self, component: Component, record: HitRecord, wo: Vec3, wi: Vec3) -> Color {
+        if let Some(reflection) = self.reflection(component) {
+            return reflection.eval(wo, wi);
+        }
+        match component {
+            Component::Diffuse if wo.z > 0.0 && wi.z > 0.0 => self.diffuse(wo, wi) * (wi.z / PI),
+            Component::Diffuse => Color::BLACK,
+            _ => {
+                let value = self.glass.eval(record, wo, wi);
+                match wi.z < 0.0 {
+                    true => value * self.base_color,
+                    false => value,
+                }
+            }
+        }
+    }
+
+    fn pdf_lobe(&self, component: Component, record: HitRecord, wo: Vec3, wi: Vec3) -> f64 {
+        if let Some(reflection) = self.reflection(component) {
+            return reflection.pdf(wo, wi);
+        }
+        match component {
+            Component::Diffuse => wi.z.max(0.0) / PI,
+            _ => self.glass.pdf(record, wo, wi),
+        }
+    }
+
+    /// The sum of the scaled lobes, except the perfectly smooth ones.
+    fn eval(&self, record: HitRecord, wo: Vec3, wi: Vec3) -> Color {
+        let scales = self.scales(wo.z);
+        let mut total = Color::BLACK;
+        for (component, scale) in COMPONENTS.into_iter().zip(scales) {
+            if scale > 0.0 && !self.is_delta(component) {
+                total = total + self.eval_lobe(component, record, wo, wi) * scale;
+            }
+        }
+        total
+    }
+
+    /// The pdfs of the lobes (except the perfectly smooth ones) weighted by
+    /// the probability of picking them.
+    fn pdf(&self, record: HitRecord, wo: Vec3, wi: Vec3) -> f64 {
+        let weights = self.weights(wo.z);
+        let total_weight: f64 = weights.iter().sum();
+        if total_weight <= 0.0 {
+            return 0.0;
+        }
+        let mut pdf = 0.0;
+        for (component, weight) in COMPONENTS.into_iter().zip(weights) {
+            if weight > 0.0 && !self.is_delta(component) {
+                pdf += weight / total_weight * self.pdf_lobe(component, record, wo, wi);
+            }
+        }
+        pdf
+    }
+}
+
+impl Material for Principled {
+    /// Picks one of the lobes with a probability proportional to its (rough)
+    /// weight and samples a direction from it. The glossy lobes sample visible
+    /// normals (see `MicrofacetReflection`), and the diffuse lobe a cosine
+    /// distribution.
+    ///
+    /// Since any of the lobes could have picked the direction, its pdf is the
+    /// average of the pdfs of all the lobes weighted by the probabilities of
+    /// picking them, and its weight is the sum of all the lobes divided by
+    /// that pdf. Perfectly smooth lobes can't be evaluated, so their samples
+    /// are simply divided by the probability of picking the lobe.
+    fn sample(&self, record: HitRecord, wo: Vec3) -> Option<BsdfSample> {
+        if wo.z <= 0.0 {
+            return None;
+        }
+        let parameters = self.parameters(&record);
+        let weights = parameters.weights(wo.z);
+        let total: f64 = weights.iter().sum();
+        if total <= 0.0 {
+            return None;
+        }
+        // Picks the lobe whose range of weights contains a random number in
+        // [0, total). Lobes without weight are never picked.
+        let mut pick = random_double() * total;
+        let mut index = 0;
+        for (i, &weight) in weights.iter().enumerate() {
+            if weight > 0.0 {
+                index = i;
+                if pick < weight {
+                    break;
+                }
+                pick -= weight;
+            }
+        }
+        let component = COMPONENTS[index];
+        let sample = parameters.sample_lobe(component, record, wo)?;
+
+        if sample.delta {
+            let scale = parameters.scales(wo.z)[index] * total / weights[index];
+            return Some(BsdfSample {
+                weight: sample.weight * scale,
+                ..sample
+            });
+        }
+        let pdf = parameters.pdf(record, wo, sample.wi);
+        if pdf <= 0.0 {
+            return None;
+        }
+        Some(BsdfSample {
+            weight: parameters.eval(record, wo, sample.wi) / pdf,
+            pdf,
+            ..sample
+        })
+    }
+
+    fn eval(&self, record: HitRecord, wo: Vec3, wi: Vec3) -> Color {
+        self.parameters(&record).eval(record, wo, wi)
+    }
+
+    fn pdf(&self, record: HitRecord, wo: Vec3, wi: Vec3) -> f64 {
+        self.parameters(&record).pdf(record, wo, wi)
+    }
+
+    fn emitted(&self, record: HitRecord) -> Color {
+        self.emission.lookup(&record) * self.emission_strength
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::random_unit_vector;
+
+    fn wo() -> Vec3 {
+        Vec3::new(0.4, 0.3, 1.0).unit_vector()
+    }
+
+    /// The average weight of the sampled directions, i.e. the albedo.
+    fn albedo(material: &Principled) -> Color {
+        let samples = 200_000;
+        let mut total = Color::BLACK;
+        for _ in 0..samples {
+            if let Some(sample) = material.sample(HitRecord::new(), wo()) {
+                total = total + sample.weight;
+            }
+        }
+        total / samples as f64
+    }
+
+    fn materials() -> Vec<Principled> {
+        let mut materials = vec![Principled::new(Color::WHITE)];
+        let mut metal = Principled::new(Color::WHITE);
+        metal.set_metallic(1.0);
+        metal.set_roughness(0.3);
+        materials.push(metal);
+        let mut coated = Principled::new(Color::WHITE);
+        coated.set_clearcoat(1.0);
+        coated.set_sheen(Color::WHITE);
+        materials.push(coated);
+        let mut glass = Principled::new(Color::WHITE);
+        glass.set_transmission(1.0);
+        glass.set_roughness(0.2);
+        materials.push(glass);
+        materials
+    }
+
+    #[test]
+    fn test_energy_conservation() {
+        for material in materials() {
+            let albedo = albedo(&material);
+            assert!(
+                albedo.r() <= 1.01 && albedo.r() > 0.7,
+                "{material:?} {albedo:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_sample_matches_eval_and_pdf() {
+        let record = HitRecord::new();
+        for material in materials() {
+            for _ in 0..1000 {
+                if let Some(sample) = material.sample(record, wo()) {
+                    let pdf = material.pdf(record, wo(), sample.wi);
+                    let expected = material.eval(record, wo(), sample.wi) / pdf;
+                    assert!(!sample.delta);
+                    assert!(
+                        (sample.weight.g() - expected.g()).abs() < 1e-6 * expected.g().max(1.0)
+                    );
+                    assert!((sample.pdf - pdf).abs() < 1e-6 * pdf);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_eval_matches_sample() {
+        let mut material = Principled::new(Color::new(0.8, 0.4, 0.2));
+        material.set_metallic(0.5);
+        material.set_roughness(0.8);
+        material.set_sheen(Color::WHITE * 0.5);
+        let mut evaluated = Color::BLACK;
+        let samples = 200_000;
+        for _ in 0..samples {
+            let mut wi = random_unit_vector();
+            wi.z = wi.z.abs();
+            evaluated = evaluated + material.eval(HitRecord::new(), wo(), wi) * (2.0 * PI);
+        }
+        let evaluated = evaluated / samples as f64;
+        let sampled = albedo(&material);
+        for (a, b) in [(evaluated.r(), sampled.r()), (evaluated.b(), sampled.b())] {
+            assert!((a - b).abs() < 0.02, "{evaluated:?} {sampled:?}");
+        }
+    }
+
+    #[test]
+    fn test_textures_and_emission() {
+        let mut material =
+            Principled::new(crate::texture::Checker::new(Color::RED, Color::BLUE, 2.0));
+        material.set_emission(Color::WHITE);
+        material.set_emission_strength(5.0);
+        let mut record = HitRecord::new();
+        record.u = 0.25;
+        assert_eq!(material.parameters(&record).base_color.r(), 1.0);
+        record.u = 0.75;
+        assert_eq!(material.parameters(&record).base_color.b(), 1.0);
+        assert_eq!(material.emitted(record).g(), 5.0);
+    }
+}
@@ -0,0 +1,217 @@
+use std::f64::consts::PI;
+
+use crate::{
+    color::Color,
+    hitrecord::HitRecord,
+    medium::{Homogeneous, Medium},
+    utils::{random_cosine_direction, random_double},
+    vec3::Vec3,
+};
+
+use super::{
+    microfacet::{Fresnel, Ggx, MicrofacetReflection},
+    BsdfSample, Lobe, Material,
+};
+
+/// A translucent material that scatters light below its surface, like skin,
+/// wax, marble or milk. Light enters the object, bounces around inside of it
+/// (a random walk through a medium, see `Medium`) and leaves it somewhere
+/// else, which softens the lighting and lets light bleed through thin parts.
+///
+/// Objects made of this material must be closed, like for `Dielectric`. The
+/// surface itself is a (rough) specular coating, and the light it doesn't
+/// reflect enters the object in a random direction (a diffuse transmission),
+/// like it would through the rough surfaces of real translucent materials.
+/// Light leaves the object the same way, ignoring internal reflection.
+/// Reference: Chiang et al., "Practical and Controllable Subsurface Scattering for Production Path Tracing"
+#[derive(Debug)]
+pub struct Subsurface {
+    medium: Homogeneous,
+    reflection: MicrofacetReflection,
+}
+
+impl Subsurface {
+    /// A material with the given (multiple scattering) albedo, the color of
+    /// the material, and mean free path, the average distance light travels
+    /// inside of it before scattering, per color channel. Larger mean free
+    /// paths make the material more translucent.
+    pub fn new(albedo: Color, mean_free_path: Color) -> Self {
+        let mut material = Subsurface {
+            medium: Homogeneous::new(Color::BLACK, Color::BLACK),
+            reflection: MicrofacetReflection {
+                ggx: Ggx::from_roughness(0.0),
+                fresnel: Fresnel::Schlick(Color::BLACK),
+            },
+        };
+        material.set_ior(1.4);
+        material.set_scattering(albedo, mean_free_path);
+        material
+    }
+
+    /// Sets the albedo and the mean free path (see `new`). The albedo is what
+    /// the material looks like after light scattered many times inside of it,
+    /// so it is mapped to the albedo of each scattering event σ_s / σ_t
+    /// (which is much closer to 1) with the fit from Chiang et al.
+    pub fn set_scattering(&mut self, albedo: Color, mean_free_path: Color) {
+        let single_scattering = |a: f64| {
+            let a = a.clamp(0.0, 1.0);
+            1.0 - (4.09712 + 4.20863 * a - (9.59217 + 41.6808 * a + 17.7126 * a * a).sqrt()).powi(2)
+        };
+        let albedo = Color::new(
+            single_scattering(albedo.r()),
+            single_scattering(albedo.g()),
+            single_scattering(albedo.b()),
+        );
+        let sigma_t = Color::WHITE / mean_free_path;
+        self.medium = Homogeneous::new(sigma_t * (Color::WHITE - albedo), sigma_t * albedo);
+    }
+
+    /// The index of refraction of the surface (1.4 by default, like skin),
+    /// which determines how much light its coating reflects.
+    pub fn set_ior(&mut self, ior: f64) {
+        let f0 = ((ior - 1.0) / (ior + 1.0)).powi(2);
+        self.reflection.fresnel = Fresnel::Schlick(Color::WHITE * f0);
+    }
+
+    /// The roughness of the coating, from 0 (the default) to 1.
+    pub fn set_roughness(&mut self, roughness: f64) {
+        self.reflection.ggx = Ggx::from_roughness(roughness);
+    }
+
+    /// The probability of reflecting off of the coating rather than entering
+    /// the object. Rays leaving the object always do.
+    fn reflectance(&self, record: &HitRecord, wo: Vec3) -> f64 {
+        match record.front_face {
+            true => self.reflection.fresnel.eval(wo.z).r(),
+            false => 0.0,
+        }
+    }
+}
+
+impl Material for Subsurface {
+    /// Picks the coating or the diffuse transmission with the probability of
+    /// the Fresnel reflectance. Like for `Principled`, the weight of rough
+    /// reflections and of transmissions is the sum of both lobes divided by
+    /// the combined pdf.
+    fn sample(&self, record: HitRecord, wo: Vec3) -> Option<BsdfSample> {
+        if wo.z <= 0.0 {
+            return None;
+        }
+        let reflectance = self.reflectance(&record, wo);
+        let sample = if random_double() < reflectance {
+            let sample = self.reflection.sample(wo)?;
+            if sample.delta {
+                return Some(BsdfSample {
+                    weight: sample.weight / reflectance,
+                    ..sample
+                });
+            }
+            sample
+        } else {
+            let wi = -random_cosine_direction();
+            BsdfSample {
+                wi,
+                weight: Color::WHITE,
+                pdf: -wi.z / PI,
+                delta: false,
+                lobe: Lobe::Transmission,
+            }
+        };
+        let pdf = self.pdf(record, wo, sample.wi);
+        if pdf <= 0.0 {
+            return None;
+        }
+        Some(BsdfSample {
+            weight: self.eval(record, wo, sample.wi) / pdf,
+            pdf,
+            ..sample
+        })
+    }
+
+    fn eval(&self, record: HitRecord, wo: Vec3, wi: Vec3) -> Color {
+        if wo.z <= 0.0 {
+            return Color::BLACK;
+        }
+        let reflectance = self.reflectance(&record, wo);
+        let transmission = (1.0 - reflectance) * (-wi.z).max(0.0) / PI;
+        let reflection = match record.front_face {
+            true => self.reflection.eval(wo, wi),
+            false => Color::BLACK,
+        };
+        reflection + Color::WHITE * transmission
+    }
+
+    fn pdf(&self, record: HitRecord, wo: Vec3, wi: Vec3) -> f64 {
+        if wo.z <= 0.0 {
+            return 0.0;
+        }
+        let reflectance = self.reflectance(&record, wo);
+        let transmission = (1.0 - reflectance) * (-wi.z).max(0.0) / PI;
+        let reflection = match record.front_face {
+            true => reflectance * self.reflection.pdf(wo, wi),
+            false => 0.0,
+        };
+        reflection + transmission
+    }
+
+    fn medium(&self) -> Option<&dyn Medium> {
+        Some(&self.medium)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ray::Ray;
+
+    fn record(front_face: bool) -> HitRecord {
+        let mut record = HitRecord::new();
+        record.front_face = front_face;
+        record
+    }
+
+    #[test]
+    fn test_sample_matches_eval_and_pdf() {
+        let mut material = Subsurface::new(Color::WHITE * 0.8, Color::WHITE * 0.1);
+        material.set_roughness(0.4);
+        let wo = Vec3::new(0.5, 0.1, 0.6).unit_vector();
+        for front_face in [true, false] {
+            let record = record(front_face);
+            let samples = 100_000;
+            let mut total = 0.0;
+            for _ in 0..samples {
+                if let Some(sample) = material.sample(record, wo) {
+                    let pdf = material.pdf(record, wo, sample.wi);
+                    let expected = material.eval(record, wo, sample.wi).r() / pdf;
+                    assert!((sample.weight.r() - expected).abs() < 1e-6);
+                    assert!((sample.pdf - pdf).abs() < 1e-9);
+                    total += sample.weight.r();
+                }
+            }
+            // Only the rough coating loses a little energy.
+            let albedo = total / samples as f64;
+            assert!(albedo <= 1.0 + 1e-3 && albedo > 0.98, "{albedo}");
+        }
+    }
+
+    #[test]
+    fn test_single_scattering_albedo() {
+        let albedo = |a: f64| {
+            let material = Subsurface::new(Color::WHITE * a, Color::WHITE);
+            // A path through the medium that scatters once has a weight of
+            // the single scattering albedo.
+            let ray = Ray::new(Vec3::ZERO, Vec3::Z);
+            let medium = material.medium().unwrap();
+            let mut sample = medium.sample(ray, f64::INFINITY, Color::WHITE);
+            while sample.scatter.is_none() {
+                sample = medium.sample(ray, f64::INFINITY, Color::WHITE);
+            }
+            sample.weight.r()
+        };
+        assert!(albedo(0.0).abs() < 1e-4);
+        assert!((albedo(1.0) - 1.0).abs() < 1e-4);
+        // Even a fairly dark material barely absorbs light at each event.
+        assert!(albedo(0.5) > 0.9);
+        assert!(albedo(0.3) < albedo(0.5) && albedo(0.5) < albedo(0.8));
+    }
+}
@@ -0,0 +1,374 @@
+use std::f64::consts::PI;
+
+use crate::{color::Color, hitrecord::HitRecord, medium::Medium, utils::random_double, vec3::Vec3};
+
+use super::{spectral_value, BsdfSample, Interface, Lobe, Material};
+
+/// A thin transparent film on top of another material, like a soap bubble,
+/// oil on water or the anti-reflective coating of a lens. Light reflected off
+/// the top of the film interferes with light reflected off the bottom of it,
+/// which reinforces some wavelengths and cancels others depending on the
+/// thickness of the film and the angle of incidence, giving iridescent
+/// colors.
+///
+/// The film is smooth: it reflects part of the light specularly, with the
+/// reflectance of the whole film (including its bottom, see `set_base_ior`),
+/// and lets the rest through to the base material. The base material is
+/// unchanged, so any reflection of its own comes on top of the film's.
+#[derive(Debug)]
+pub struct ThinFilm {
+    base: Box<dyn Material>,
+
+    /// The thickness of the film, in nanometers.
+    thickness: f64,
+
+    /// The index of refraction of the film.
+    ior: f64,
+
+    /// The index of refraction of what is below the film.
+    base_ior: f64,
+}
+
+impl ThinFilm {
+    /// A film of the given thickness (in nanometers, films thicker than a few
+    /// micrometers barely show any colors) and index of refraction on top of
+    /// the base material, which is assumed to have an index of refraction of
+    /// 1.5 (see `set_base_ior`).
+    pub fn new(base: impl Material + 'static, thickness: f64, ior: f64) -> Self {
+        assert!(thickness >= 0.0 && ior > 0.0);
+        ThinFilm {
+            base: Box::new(base),
+            thickness,
+            ior,
+            base_ior: 1.5,
+        }
+    }
+
+    /// A soap bubble: a film of soapy water with air on both sides. Objects
+    /// made of it should be closed, like for `Dielectric`.
+    pub fn soap_bubble(thickness: f64) -> Self {
+        let mut bubble = ThinFilm::new(Interface, thickness, 1.33);
+        bubble.set_base_ior(1.0);
+        bubble
+    }
+
+    /// Sets the index of refraction of what is below the film (e.g. 1.33 for
+    /// oil on water), which determines how much light the bottom of the film
+    /// reflects.
+    pub fn set_base_ior(&mut self, ior: f64) {
+        assert!(ior > 0.0);
+        self.base_ior = ior;
+    }
+
+    /// The reflectance of the film for the given cosine of the angle of
+    /// incidence, at the wavelengths of the hit in spectral mode.
+    fn reflectance(&self, record: &HitRecord, cos_i: f64) -> Color {
+        let (outside, inside) = match record.front_face {
+            true => (1.0, self.base_ior),
+            false => (self.base_ior, 1.0),
+        };
+        let film = Film {
+            thickness: self.thickness,
+            iors: [outside, self.ior, inside],
+        };
+        match record.wavelengths {
+            Some(wavelengths) => {
+                let [r, g, b] = wavelengths.0.map(|lambda| film.reflectance(lambda, cos_i));
+                Color::new(r, g, b)
+            }
+            None => film.reflectance_rgb(cos_i),
+        }
+    }
+}
+
+impl Material for ThinFilm {
+    /// Picks the reflection off of the film with the probability of its
+    /// average reflectance R, and otherwise samples the base material, whose
+    /// light is attenuated by 1 - R.
+    fn sample(&self, record: HitRecord, wo: Vec3) -> Option<BsdfSample> {
+        if wo.z <= 0.0 {
+            return None;
+        }
+        let reflectance = self.reflectance(&record, wo.z);
+        let probability = average(reflectance);
+        if random_double() < probability {
+            return Some(BsdfSample {
+                wi: Vec3::new(-wo.x, -wo.y, wo.z),
+                weight: reflectance / probability,
+                pdf: 0.0,
+                delta: true,
+                lobe: Lobe::Glossy,
+            });
+        }
+        let sample = self.base.sample(record, wo)?;
+        let weight = spectral_value(self, self.base.as_ref(), &record, sample.weight)
+            * (Color::WHITE - reflectance);
+        Some(BsdfSample {
+            weight: weight / (1.0 - probability),
+            pdf: sample.pdf * (1.0 - probability),
+            ..sample
+        })
+    }
+
+    fn eval(&self, record: HitRecord, wo: Vec3, wi: Vec3) -> Color {
+        if wo.z <= 0.0 {
+            return Color::BLACK;
+        }
+        let value = spectral_value(
+            self,
+            self.base.as_ref(),
+            &record,
+            self.base.eval(record, wo, wi),
+        );
+        value * (Color::WHITE - self.reflectance(&record, wo.z))
+    }
+
+    fn pdf(&self, record: HitRecord, wo: Vec3, wi: Vec3) -> f64 {
+        if wo.z <= 0.0 {
+            return 0.0;
+        }
+        let probability = average(self.reflectance(&record, wo.z));
+        self.base.pdf(record, wo, wi) * (1.0 - probability)
+    }
+
+    fn emitted(&self, record: HitRecord) -> Color {
+        self.base.emitted(record)
+    }
+
+    fn transmission(&self, record: HitRecord, wo: Vec3) -> Color {
+        let transmission = self.base.transmission(record, wo);
+        let transmission = spectral_value(self, self.base.as_ref(), &record, transmission);
+        transmission * (Color::WHITE - self.reflectance(&record, wo.z))
+    }
+
+    fn medium(&self) -> Option<&dyn Medium> {
+        self.base.medium()
+    }
+
+    /// The interference depends on the wavelength, so in spectral mode the
+    /// reflectance of the film is computed at the wavelengths of the path.
+    fn is_spectral(&self) -> bool {
+        true
+    }
+}
+
+/// A film of the given thickness (in nanometers) between two media, with the
+/// indices of refraction of the medium above it, the film itself and the
+/// medium below it.
+struct Film {
+    thickness: f64,
+    iors: [f64; 3],
+}
+
+/// The interfaces between the media of a film, for one polarization of light.
+struct Interfaces {
+    /// The amplitude reflection coefficients of the top (light coming from
+    /// above) and bottom (light coming from the film) of the film.
+    r12: f64,
+    r23: f64,
+}
+
+impl Film {
+    /// The cosines of the angles of the light in the film and below it, or
+    /// `None` for total internal reflection.
+    fn cosines(&self, cos_i: f64) -> Option<(f64, f64)> {
+        let [n1, n2, n3] = self.iors;
+        let sin2_i = 1.0 - cos_i * cos_i;
+        let cos = |n: f64| {
+            let sin2 = sin2_i * (n1 / n).powi(2);
+            (sin2 < 1.0).then(|| (1.0 - sin2).sqrt())
+        };
+        Some((cos(n2)?, cos(n3)?))
+    }
+
+    /// The Fresnel coefficients of the interfaces, for s and p polarized
+    /// light.
+    fn interfaces(&self, cos_1: f64, cos_2: f64, cos_3: f64) -> [Interfaces; 2] {
+        let [n1, n2, n3] = self.iors;
+        [
+            Interfaces {
+                r12: (n1 * cos_1 - n2 * cos_2) / (n1 * cos_1 + n2 * cos_2),
+                r23: (n2 * cos_2 - n3 * cos_3) / (n2 * cos_2 + n3 * cos_3),
+            },
+            Interfaces {
+                r12: (n2 * cos_1 - n1 * cos_2) / (n2 * cos_1 + n1 * cos_2),
+                r23: (n3 * cos_2 - n2 * cos_3) / (n3 * cos_2 + n2 * cos_3),
+            },
+        ]
+    }
+
+    /// The optical path difference between light reflected off of the top
+    /// and the bottom of the film, in nanometers.
+    fn path_difference(&self, cos_2: f64) -> f64 {
+        2.0 * self.iors[1] * self.thickness * cos_2
+    }
+
+    /// The reflectance of the film for light of the given wavelength (Airy's
+    /// formula). Summing the light reflected after bouncing back and forth
+    /// any number of times inside of the film, whose phase shifts by
+    /// Δ = 2π * OPD / λ with every round trip, gives
+    ///
+    ///    R = (r12² + r23² + 2 r12 r23 cos Δ) / (1 + r12² r23² + 2 r12 r23 cos Δ)
+    ///
+    /// for each polarization, and we average both polarizations.
+    /// Reference: Born and Wolf, "Principles of Optics", section 7.6.1
+    fn reflectance(&self, lambda: f64, cos_i: f64) -> f64 {
+        let (cos_2, cos_3) = match self.cosines(cos_i) {
+            Some(cosines) => cosines,
+            None => return 1.0,
+        };
+        let cos_delta = (2.0 * PI * self.path_difference(cos_2) / lambda).cos();
+        let reflectance = |Interfaces { r12, r23 }: &Interfaces| {
+            let cross = 2.0 * r12 * r23 * cos_delta;
+            (r12 * r12 + r23 * r23 + cross) / (1.0 + (r12 * r23).powi(2) + cross)
+        };
+        let [s, p] = self.interfaces(cos_i, cos_2, cos_3);
+        (reflectance(&s) + reflectance(&p)) / 2.0
+    }
+
+    /// The RGB color of the reflectance of the film. Evaluating the
+    /// reflectance at 3 wavelengths would miss most of the fringes of thick
+    /// films, so instead we integrate it against the color matching
+    /// functions analytically: the reflectance is a sum of terms in
+    /// cos(m * (Δ + φ)), where φ is the phase shift of the reflections, whose
+    /// integrals are given by the Fourier transforms of the color matching
+    /// functions (approximated by Gaussians, see `sensitivity`):
+    ///
+    ///    R = C0 + Σ_m C_m * 2 S(m * OPD, m * φ)
+    ///
+    /// The reflectances R12, R23 of the interfaces set the amplitudes:
+    ///
+    ///    C0 = R12 + T² R23 / (1 - R12 R23)    (T = 1 - R12)
+    ///    C_m = (T² R23 / (1 - R12 R23) - T) * (R12 R23)^(m/2)
+    ///
+    /// which quickly vanish, so we only keep the first 3 terms.
+    /// Reference: Belcour and Barla, "A Practical Extension to Microfacet Theory for the Modeling of Varying Iridescence"
+    fn reflectance_rgb(&self, cos_i: f64) -> Color {
+        let (cos_2, cos_3) = match self.cosines(cos_i) {
+            Some(cosines) => cosines,
+            None => return Color::WHITE,
+        };
+        let path_difference = self.path_difference(cos_2);
+        let reflectance = |&Interfaces { r12, r23 }: &Interfaces| {
+            let (reflectance_12, reflectance_23) = (r12 * r12, r23 * r23);
+            let transmittance = 1.0 - reflectance_12;
+            let r123 = (reflectance_12 * reflectance_23).sqrt();
+            let through = transmittance.powi(2) * reflectance_23 / (1.0 - r123 * r123);
+            // The phase shift of the reflections inside of the film, π for
+            // negative coefficients (r21 = -r12).
+            let phase = |r: f64| if r < 0.0 { PI } else { 0.0 };
+            let phi = phase(-r12) + phase(r23);
+
+            let mut total = Color::WHITE * (reflectance_12 + through);
+            let mut amplitude = through - transmittance;
+            for m in 1..=3 {
+                amplitude *= r123;
+                let m = m as f64;
+                total = total + sensitivity(m * path_difference, m * phi) * (2.0 * amplitude);
+            }
+            total
+        };
+        let [s, p] = self.interfaces(cos_i, cos_2, cos_3);
+        let total = (reflectance(&s) + reflectance(&p)) / 2.0;
+        Color::new(total.r().max(0.0), total.g().max(0.0), total.b().max(0.0))
+    }
+}
+
+/// The integrals of cos(2π * OPD / λ + shift) against the color matching
+/// functions (converted to linear sRGB and normalized so that white is 1),
+/// for an optical path difference in nanometers. The color matching functions
+/// are fitted with Gaussians in the frequency domain, whose Fourier
+/// transforms are Gaussians too (see `Film::reflectance_rgb`).
+fn sensitivity(path_difference: f64, shift: f64) -> Color {
+    let phase = 2.0 * PI * path_difference * 1e-9;
+    let gaussian = |value: f64, position: f64, variance: f64| {
+        value
+            * (2.0 * PI * variance).sqrt()
+            * (position * phase + shift).cos()
+            * (-phase * phase * variance).exp()
+    };
+    let x =
+        gaussian(5.4856e-13, 1.6810e+06, 4.3278e+09) + gaussian(9.7470e-14, 2.2399e+06, 4.5282e+09);
+    let y = gaussian(4.4201e-13, 1.7953e+06, 9.3046e+09);
+    let z = gaussian(5.2481e-13, 2.2084e+06, 6.6121e+09);
+    let (x, y, z) = (x / 1.0685e-7, y / 1.0685e-7, z / 1.0685e-7);
+    Color::new(
+        3.2406 * x - 1.5372 * y - 0.4986 * z,
+        -0.9689 * x + 1.8758 * y + 0.0415 * z,
+        0.0557 * x - 0.2040 * y + 1.0570 * z,
+    )
+}
+
+/// The average of the channels of a reflectance, the probability of picking
+/// the reflection.
+fn average(color: Color) -> f64 {
+    ((color.r() + color.g() + color.b()) / 3.0).clamp(0.0, 1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spectrum::Wavelengths;
+
+    #[test]
+    fn test_rgb_approximation() {
+        // The analytic RGB reflectance matches the color of the spectral
+        // reflectance, averaged over many sampled wavelengths.
+        for (thickness, iors, cos_i) in [
+            (300.0, [1.0, 1.33, 1.0], 1.0),
+            (500.0, [1.0, 1.33, 1.0], 0.7),
+            (150.0, [1.0, 1.38, 1.5], 1.0),
+            (400.0, [1.0, 1.5, 1.33], 0.9),
+        ] {
+            let film = Film { thickness, iors };
+            let samples = 50_000;
+            let mut total = Color::BLACK;
+            for _ in 0..samples {
+                let wavelengths = Wavelengths::sample();
+                let [r, g, b] = wavelengths.0.map(|lambda| film.reflectance(lambda, cos_i));
+                total = total + wavelengths.to_rgb(Color::new(r, g, b));
+            }
+            let expected = total / samples as f64;
+            let rgb = film.reflectance_rgb(cos_i);
+            let difference = rgb - expected;
+            for channel in [difference.r(), difference.g(), difference.b()] {
+                assert!(channel.abs() < 0.015, "{rgb:?} != {expected:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_without_film() {
+        // A film of thickness 0, or with the same index of refraction as
+        // what is below it, reflects like the surface below it (4% for
+        // glass at normal incidence).
+        for film in [
+            Film {
+                thickness: 0.0,
+                iors: [1.0, 1.33, 1.5],
+            },
+            Film {
+                thickness: 300.0,
+                iors: [1.0, 1.5, 1.5],
+            },
+        ] {
+            assert!((film.reflectance(550.0, 1.0) - 0.04).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_soap_bubble_conserves_energy() {
+        // A bubble reflects or transmits all of the light.
+        let bubble = ThinFilm::soap_bubble(400.0);
+        let wo = Vec3::new(0.6, 0.0, 0.8);
+        let samples = 100_000;
+        let mut total = Color::BLACK;
+        for _ in 0..samples {
+            total = total + bubble.sample(HitRecord::new(), wo).unwrap().weight;
+        }
+        let average = total / samples as f64;
+        for channel in [average.r(), average.g(), average.b()] {
+            assert!((channel - 1.0).abs() < 0.01, "{average:?}");
+        }
+    }
+}
@@ -0,0 +1,64 @@
+mod grid;
+mod heterogeneous;
+mod homogeneous;
+mod phase;
+
+pub use grid::VoxelGrid;
+pub use heterogeneous::Heterogeneous;
+pub use homogeneous::Homogeneous;
+pub use phase::HenyeyGreenstein;
+
+use std::fmt::Debug;
+
+use crate::{color::Color, ray::Ray};
+
+/// A participating medium, e.g. fog, smoke or murky water. Unlike surfaces,
+/// media interact with light everywhere along a ray: light is absorbed
+/// (turned into heat) and scattered (sent in a new direction) by the particles
+/// of the medium, so the further a ray travels through it, the more likely it
+/// is to be absorbed or scattered.
+///
+/// How densely a medium absorbs and scatters light is given by its absorption
+/// and scattering coefficients σ_a and σ_s, the probability per unit of
+/// distance that light is absorbed or scattered. Their sum is the extinction
+/// coefficient σ_t. The fraction of light that travels a distance d through
+/// a medium without interacting with it is the transmittance:
+///
+///    T(d) = exp(-∫ σ_t(x) dx)
+///
+/// Media fill the inside of an object (see `Object::volume`) or the whole
+/// scene (see `Scene::set_fog`).
+///
+/// Media implement `Debug` so that the contents of a scene can be hashed
+/// (see `Scene::content_hash`).
+pub trait Medium: Sync + Debug {
+    /// Samples the distance the ray travels through the medium before it
+    /// interacts with it (the free path), where `t_max` is where the ray
+    /// leaves the medium (or hits a surface). Distances are in units of the
+    /// ray parameter t (see `Ray::at`). `throughput` is the throughput of
+    /// the path so far, which media whose density differs per color channel
+    /// use to decide which channel to sample.
+    fn sample(&self, ray: Ray, t_max: f64, throughput: Color) -> MediumSample;
+
+    /// The transmittance along the ray from its origin to `t_max`.
+    fn transmittance(&self, ray: Ray, t_max: f64) -> Color;
+
+    /// How the medium scatters light.
+    fn phase(&self) -> HenyeyGreenstein;
+}
+
+/// The result of sampling a free path in a medium (see `Medium::sample`).
+#[derive(Clone, Copy, Debug)]
+pub struct MediumSample {
+    /// The ray parameter t at which the ray scatters, or `None` if the ray
+    /// made it to `t_max` without scattering.
+    pub scatter: Option<f64>,
+
+    /// The attenuation of the light along the sampled path, divided by the
+    /// probability of sampling it.
+    pub weight: Color,
+
+    /// The light emitted by the medium along the sampled path (e.g. by fire),
+    /// which reaches the origin of the ray unattenuated by `weight`.
+    pub emission: Color,
+}
@@ -0,0 +1,216 @@
+use std::{
+    fmt::{self, Debug},
+    io,
+    ops::{Add, Mul},
+    path::Path,
+};
+
+use crate::{
+    color::Color,
+    io::{invalid_data, ByteReader},
+    utils::hash_bytes,
+    vec3::Vec3,
+};
+
+/// A dense 3D grid of values (voxels), e.g. the density of smoke exported from
+/// a simulation. The grid is looked up with coordinates from 0 to 1 along each
+/// axis, which map to the corners of the grid, so grids of different
+/// resolutions can cover the same region (see `Heterogeneous`).
+#[derive(Clone)]
+pub struct VoxelGrid<T> {
+    width: usize,
+    height: usize,
+    depth: usize,
+    /// The values, with x varying fastest, then y, then z.
+    values: Vec<T>,
+
+    /// Identifies the values, computed once since grids are large (see
+    /// `Debug`).
+    hash: u64,
+}
+
+impl<T> VoxelGrid<T>
+where
+    T: Copy + Add<Output = T> + Mul<f64, Output = T> + Debug,
+{
+    pub fn new(width: usize, height: usize, depth: usize, values: Vec<T>) -> Self {
+        assert!(width > 0 && height > 0 && depth > 0);
+        assert_eq!(values.len(), width * height * depth);
+        let hash = hash_bytes(format!("{values:?}").as_bytes());
+        VoxelGrid {
+            width,
+            height,
+            depth,
+            values,
+            hash,
+        }
+    }
+
+    /// A grid with the same value everywhere.
+    pub fn constant(value: T) -> Self {
+        VoxelGrid::new(1, 1, 1, vec![value])
+    }
+
+    /// The value at the voxel at the given indices.
+    pub fn get(&self, x: usize, y: usize, z: usize) -> T {
+        self.values[(z * self.height + y) * self.width + x]
+    }
+
+    /// The value at the given point (from 0 to 1 along each axis), trilinearly
+    /// interpolated between the 8 closest voxels. The values are at the
+    /// centers of the voxels, so the value is constant within half a voxel of
+    /// the border of the grid.
+    pub fn lookup(&self, point: Vec3) -> T {
+        // The indices of the voxels before and after the point along an axis,
+        // and how far the point is between them.
+        let axis = |coordinate: f64, size: usize| {
+            let x = (coordinate * size as f64 - 0.5).clamp(0.0, (size - 1) as f64);
+            let i = (x as usize).min(size.saturating_sub(2));
+            (i, (i + 1).min(size - 1), x - i as f64)
+        };
+        let (x0, x1, fx) = axis(point.x, self.width);
+        let (y0, y1, fy) = axis(point.y, self.height);
+        let (z0, z1, fz) = axis(point.z, self.depth);
+
+        let lerp = |a: T, b: T, f: f64| a * (1.0 - f) + b * f;
+        let plane = |z| {
+            let front = lerp(self.get(x0, y0, z), self.get(x1, y0, z), fx);
+            let back = lerp(self.get(x0, y1, z), self.get(x1, y1, z), fx);
+            lerp(front, back, fy)
+        };
+        lerp(plane(z0), plane(z1), fz)
+    }
+}
+
+impl VoxelGrid<f64> {
+    /// The largest value of the grid, which bounds every interpolated value.
+    pub fn max(&self) -> f64 {
+        self.values.iter().cloned().fold(0.0, f64::max)
+    }
+
+    /// Loads a grid of scalars (e.g. densities) from a raw grid file (see
+    /// `from_raw`).
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::from_raw(&std::fs::read(path)?)
+    }
+
+    /// Decodes a raw grid file with 1 channel. Raw grid files are made of a
+    /// header of 4 little endian u32s, the width, height, depth and number of
+    /// channels of the grid, followed by the values of the voxels as little
+    /// endian f32s, with the channels of each voxel next to each other, and x
+    /// varying fastest, then y, then z.
+    pub fn from_raw(bytes: &[u8]) -> io::Result<Self> {
+        let (width, height, depth, values) = read_raw(bytes, 1)?;
+        Ok(VoxelGrid::new(width, height, depth, values))
+    }
+}
+
+impl VoxelGrid<Color> {
+    /// Loads a grid of colors (e.g. albedos) from a raw grid file (see
+    /// `from_raw`).
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::from_raw(&std::fs::read(path)?)
+    }
+
+    /// Decodes a raw grid file with 3 channels (red, green and blue, see
+    /// `VoxelGrid::<f64>::from_raw` for the format).
+    pub fn from_raw(bytes: &[u8]) -> io::Result<Self> {
+        let (width, height, depth, values) = read_raw(bytes, 3)?;
+        let values = values
+            .chunks_exact(3)
+            .map(|c| Color::new(c[0], c[1], c[2]))
+            .collect();
+        Ok(VoxelGrid::new(width, height, depth, values))
+    }
+}
+
+/// Grids are too large to be shown in full, so like images they show a hash
+/// of their values instead (see `Buffer::content_hash`).
+impl<T> Debug for VoxelGrid<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("VoxelGrid")
+            .field("width", &self.width)
+            .field("height", &self.height)
+            .field("depth", &self.depth)
+            .field("hash", &self.hash)
+            .finish()
+    }
+}
+
+/// Reads the size and the values of a raw grid file with the given number of
+/// channels.
+fn read_raw(bytes: &[u8], channels: usize) -> io::Result<(usize, usize, usize, Vec<f64>)> {
+    let mut reader = ByteReader::new(bytes);
+    let width = reader.u32()? as usize;
+    let height = reader.u32()? as usize;
+    let depth = reader.u32()? as usize;
+    if width == 0 || height == 0 || depth == 0 {
+        return Err(invalid_data("empty grid"));
+    }
+    if reader.u32()? as usize != channels {
+        return Err(invalid_data("unexpected number of grid channels"));
+    }
+    let count = width
+        .checked_mul(height)
+        .and_then(|n| n.checked_mul(depth))
+        .and_then(|n| n.checked_mul(channels))
+        .ok_or_else(|| invalid_data("grid too large"))?;
+    // Checked before allocating, so that a corrupt header can't ask for more
+    // memory than the file could fill.
+    if count
+        .checked_mul(4)
+        .map_or(true, |n| n > reader.remaining())
+    {
+        return Err(invalid_data("grid too large"));
+    }
+    let values = (0..count)
+        .map(|_| Ok(f32::from_bits(reader.u32()?) as f64))
+        .collect::<io::Result<_>>()?;
+    Ok((width, height, depth, values))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lookup() {
+        let grid = VoxelGrid::new(2, 1, 2, vec![0.0, 1.0, 2.0, 3.0]);
+        // The values are at the centers of the voxels.
+        assert_eq!(grid.lookup(Vec3::new(0.25, 0.5, 0.25)), 0.0);
+        assert_eq!(grid.lookup(Vec3::new(0.75, 0.5, 0.75)), 3.0);
+        assert_eq!(grid.lookup(Vec3::new(0.5, 0.5, 0.5)), 1.5);
+        assert_eq!(grid.lookup(Vec3::new(0.5, 0.0, 0.25)), 0.5);
+        // and constant within half a voxel of the border.
+        assert_eq!(grid.lookup(Vec3::new(0.0, 1.0, 1.0)), 2.0);
+        assert_eq!(
+            VoxelGrid::constant(4.0).lookup(Vec3::new(0.3, 0.6, 0.9)),
+            4.0
+        );
+        assert_eq!(grid.max(), 3.0);
+    }
+
+    #[test]
+    fn test_from_raw() {
+        let mut bytes: Vec<u8> = [1u32, 2, 1, 3]
+            .iter()
+            .flat_map(|v| v.to_le_bytes())
+            .collect();
+        bytes.extend(
+            [0.5f32, 1.0, 0.0, 0.25, 0.0, 2.0]
+                .iter()
+                .flat_map(|v| v.to_le_bytes()),
+        );
+
+        let grid = VoxelGrid::<Color>::from_raw(&bytes).unwrap();
+        assert_eq!(grid.get(0, 1, 0).b(), 2.0);
+        assert!(VoxelGrid::<f64>::from_raw(&bytes).is_err());
+        assert!(VoxelGrid::<Color>::from_raw(&bytes[..bytes.len() - 1]).is_err());
+
+        let huge: Vec<u8> = [u32::MAX, u32::MAX, u32::MAX, 3]
+            .iter()
+            .flat_map(|v| v.to_le_bytes())
+            .collect();
+        assert!(VoxelGrid::<Color>::from_raw(&huge).is_err());
+    }
+}
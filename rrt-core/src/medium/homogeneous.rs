@@ -0,0 +1,153 @@
+use crate::{color::Color, ray::Ray, utils::random_double};
+
+use super::{HenyeyGreenstein, Medium, MediumSample};
+
+/// A medium with the same density everywhere, e.g. fog or a uniformly murky
+/// liquid. The absorption and scattering coefficients (see `Medium`) are
+/// colors, since media can absorb or scatter some wavelengths more than
+/// others (e.g. the deep sea absorbs red light).
+#[derive(Debug)]
+pub struct Homogeneous {
+    sigma_a: Color,
+    sigma_s: Color,
+    phase: HenyeyGreenstein,
+}
+
+impl Homogeneous {
+    /// An isotropic medium with the given absorption and scattering
+    /// coefficients (per unit of distance).
+    pub fn new(absorption: Color, scattering: Color) -> Self {
+        Homogeneous {
+            sigma_a: absorption,
+            sigma_s: scattering,
+            phase: HenyeyGreenstein::isotropic(),
+        }
+    }
+
+    /// Sets the asymmetry of the phase function (see `HenyeyGreenstein`).
+    pub fn set_anisotropy(&mut self, g: f64) {
+        self.phase = HenyeyGreenstein::new(g);
+    }
+
+    fn sigma_t(&self) -> Color {
+        self.sigma_a + self.sigma_s
+    }
+}
+
+impl Medium for Homogeneous {
+    /// In a homogeneous medium, the free path follows an exponential
+    /// distribution with pdf σ_t * T(d), which we sample by inverting its
+    /// cumulative distribution:
+    ///
+    ///    d = -ln(1 - ξ) / σ_t
+    ///
+    /// σ_t is different for each color channel, so we pick the channel to
+    /// sample at random and use the average of the pdfs of all the channels,
+    /// weighted by the probabilities of picking them (the probability of
+    /// sampling d with any of them), as the pdf. The weight of a scattering
+    /// event at d is σ_s * T(d) / pdf, and the weight of making it through
+    /// the medium is T(d_max) / P(d > d_max).
+    ///
+    /// The weights of the channels drift apart with every scattering event,
+    /// so we pick the channels in proportion to the throughput of the path.
+    /// This keeps the weights bounded even after hundreds of events.
+    /// Reference: Pharr et al., "Physically Based Rendering" (4th edition), section 14.2.2
+    fn sample(&self, ray: Ray, t_max: f64, throughput: Color) -> MediumSample {
+        let sigma_t = self.sigma_t();
+        let channels = [sigma_t.r(), sigma_t.g(), sigma_t.b()];
+        let probabilities = match throughput.r() + throughput.g() + throughput.b() {
+            total if total > 0.0 => throughput / total,
+            _ => Color::WHITE / 3.0,
+        };
+        let channel = match random_double() {
+            x if x < probabilities.r() => 0,
+            x if x < probabilities.r() + probabilities.g() => 1,
+            _ => 2,
+        };
+        let length = ray.direction.length();
+        let distance = -(1.0 - random_double()).ln() / channels[channel];
+        let t = distance / length;
+
+        if t < t_max {
+            let transmittance = transmittance(sigma_t, distance);
+            let pdf = average(sigma_t * transmittance, probabilities);
+            MediumSample {
+                scatter: Some(t),
+                weight: self.sigma_s * transmittance / pdf,
+                emission: Color::BLACK,
+            }
+        } else {
+            let transmittance = transmittance(sigma_t, t_max * length);
+            let probability = average(transmittance, probabilities);
+            MediumSample {
+                scatter: None,
+                weight: match probability > 0.0 {
+                    true => transmittance / probability,
+                    false => Color::BLACK,
+                },
+                emission: Color::BLACK,
+            }
+        }
+    }
+
+    fn transmittance(&self, ray: Ray, t_max: f64) -> Color {
+        transmittance(self.sigma_t(), t_max * ray.direction.length())
+    }
+
+    fn phase(&self) -> HenyeyGreenstein {
+        self.phase
+    }
+}
+
+/// The transmittance over the given distance, exp(-σ_t * d) per channel.
+/// Channels without extinction let all the light through, even over an
+/// infinite distance.
+fn transmittance(sigma_t: Color, distance: f64) -> Color {
+    let channel = |sigma_t: f64| match sigma_t > 0.0 {
+        true => (-sigma_t * distance).exp(),
+        false => 1.0,
+    };
+    Color::new(
+        channel(sigma_t.r()),
+        channel(sigma_t.g()),
+        channel(sigma_t.b()),
+    )
+}
+
+/// The average of the channels of a color, weighted by the given
+/// probabilities.
+fn average(color: Color, probabilities: Color) -> f64 {
+    let weighted = color * probabilities;
+    weighted.r() + weighted.g() + weighted.b()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vec3::Vec3;
+
+    #[test]
+    fn test_sampling_is_unbiased() {
+        // The average weight of the paths that make it through the medium is
+        // the transmittance, and the average weight of the scattering events
+        // is the scattered light (1 - T) * σ_s / σ_t, whatever the throughput.
+        let medium = Homogeneous::new(Color::new(0.1, 0.5, 0.0), Color::new(0.4, 0.5, 1.0));
+        let ray = Ray::new(Vec3::ZERO, Vec3::new(0.0, 0.0, 2.0));
+        let samples = 200_000;
+        let (mut through, mut scattered) = (Color::BLACK, Color::BLACK);
+        for _ in 0..samples {
+            let sample = medium.sample(ray, 1.0, Color::new(1.0, 0.2, 0.5));
+            match sample.scatter {
+                Some(_) => scattered = scattered + sample.weight,
+                None => through = through + sample.weight,
+            }
+        }
+        let through = through / samples as f64;
+        let scattered = scattered / samples as f64;
+        let expected = medium.transmittance(ray, 1.0);
+        assert!((through.r() - expected.r()).abs() < 0.01);
+        assert!((through.b() - expected.b()).abs() < 0.01);
+        let expected_scattered = (1.0 - expected.g()) * 0.5;
+        assert!((scattered.g() - expected_scattered).abs() < 0.01);
+    }
+}
@@ -0,0 +1,90 @@
+use std::f64::consts::PI;
+
+use crate::{onb::Onb, utils::random_double, vec3::Vec3};
+
+/// The Henyey-Greenstein phase function, which describes how likely light is
+/// to be scattered in each direction by the particles of a medium (it is the
+/// BSDF of media). It is controlled by a single parameter, the asymmetry g in
+/// (-1, 1): 0 scatters light equally in all directions (isotropic), positive
+/// values scatter it mostly forwards (e.g. fog and clouds) and negative values
+/// mostly backwards.
+///
+///    p(θ) = (1 - g²) / (4π * (1 + g² - 2g * cos θ)^(3/2))
+///
+/// where θ is the angle between the direction the light was travelling in and
+/// the direction it is scattered towards.
+/// Reference: Henyey & Greenstein, "Diffuse radiation in the galaxy"
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct HenyeyGreenstein {
+    pub g: f64,
+}
+
+impl HenyeyGreenstein {
+    pub fn new(g: f64) -> Self {
+        assert!(g > -1.0 && g < 1.0);
+        HenyeyGreenstein { g }
+    }
+
+    /// Scatters light equally in all directions.
+    pub fn isotropic() -> Self {
+        HenyeyGreenstein::new(0.0)
+    }
+
+    /// The value of the phase function for light travelling along -wo that
+    /// is scattered towards wi (with the same conventions as materials, see
+    /// `Material`). It integrates to 1 over the sphere, so this is also the
+    /// pdf of `sample`.
+    pub fn eval(&self, wo: Vec3, wi: Vec3) -> f64 {
+        let cos_theta = -wo.dot(wi);
+        let g = self.g;
+        let denominator = 1.0 + g * g - 2.0 * g * cos_theta;
+        (1.0 - g * g) / (4.0 * PI * denominator * denominator.sqrt())
+    }
+
+    /// Picks a direction wi proportionally to the phase function, by
+    /// inverting its cumulative distribution.
+    pub fn sample(&self, wo: Vec3) -> Vec3 {
+        let g = self.g;
+        let xi = random_double();
+        let cos_theta = if g.abs() < 1e-3 {
+            1.0 - 2.0 * xi
+        } else {
+            let t = (1.0 - g * g) / (1.0 - g + 2.0 * g * xi);
+            ((1.0 + g * g - t * t) / (2.0 * g)).clamp(-1.0, 1.0)
+        };
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * PI * random_double();
+        Onb::from_w(-wo).to_world(Vec3::new(
+            sin_theta * phi.cos(),
+            sin_theta * phi.sin(),
+            cos_theta,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::random_unit_vector;
+
+    #[test]
+    fn test_normalized_and_mean_cosine() {
+        let wo = Vec3::new(0.3, -0.5, 0.8).unit_vector();
+        for g in [0.0, 0.7, -0.4] {
+            let phase = HenyeyGreenstein::new(g);
+            let samples = 200_000;
+
+            // ∫ p dω = 1, estimated with uniform directions.
+            let integral: f64 = (0..samples)
+                .map(|_| phase.eval(wo, random_unit_vector()) * 4.0 * PI)
+                .sum::<f64>()
+                / samples as f64;
+            assert!((integral - 1.0).abs() < 0.05, "{g} {integral}");
+
+            // The average cosine of the scattering angle is g.
+            let mean_cosine: f64 =
+                (0..samples).map(|_| -wo.dot(phase.sample(wo))).sum::<f64>() / samples as f64;
+            assert!((mean_cosine - g).abs() < 0.01, "{g} {mean_cosine}");
+        }
+    }
+}
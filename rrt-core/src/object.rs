@@ -1,16 +1,73 @@
-use crate::{material::Material, shape::Shape};
+use crate::{
+    material::{Interface, Material},
+    medium::Medium,
+    shape::Shape,
+    texture::{NormalMapping, Texture},
+};
 
-/// An object is just a combination of a material and a shape.
+/// An object is just a combination of a material and a shape, optionally
+/// filled with a medium.
+#[derive(Debug)]
 pub struct Object {
     /// The objects shape.
     pub shape: Box<dyn Shape>,
 
     /// The objects material.
     pub material: Box<dyn Material>,
+
+    /// The medium filling the inside of the object (the shape must be
+    /// closed). Objects can't overlap other objects with media, but rays
+    /// leaving an object go back to the fog of the scene (see `Scene::set_fog`).
+    pub medium: Option<Box<dyn Medium>>,
+
+    /// Perturbs the shading normal of the object, e.g. with a normal map.
+    pub normal_mapping: Option<Box<dyn NormalMapping>>,
+
+    /// Cuts out parts of the surface of the object (see `set_opacity`).
+    pub opacity: Option<Box<dyn Texture>>,
 }
 
 impl Object {
     pub fn new(shape: Box<dyn Shape>, material: Box<dyn Material>) -> Self {
-        Object { shape, material }
+        Object {
+            shape,
+            material,
+            medium: None,
+            normal_mapping: None,
+            opacity: None,
+        }
+    }
+
+    /// An object that is only made of a medium, e.g. a cloud of smoke. Its
+    /// surface only marks the boundary of the medium (see `Interface`).
+    pub fn volume(shape: Box<dyn Shape>, medium: Box<dyn Medium>) -> Self {
+        Object {
+            shape,
+            material: Box::new(Interface),
+            medium: Some(medium),
+            normal_mapping: None,
+            opacity: None,
+        }
+    }
+
+    /// Fills the inside of the object with a medium, e.g. to make murky glass
+    /// (with a `Dielectric` material).
+    pub fn set_medium(&mut self, medium: Box<dyn Medium>) {
+        self.medium = Some(medium);
+    }
+
+    /// Adds surface detail to the shading of the object, with a normal map or
+    /// a bump map (see `NormalMap` and `BumpMap`).
+    pub fn set_normal_mapping(&mut self, normal_mapping: Box<dyn NormalMapping>) {
+        self.normal_mapping = Some(normal_mapping);
+    }
+
+    /// Cuts out the parts of the surface where the opacity texture (of which
+    /// only the red channel is used) is 0, e.g. to make leaves or decals out
+    /// of quads (see `Quad`). Rays go through them as if they weren't there,
+    /// shadow rays included. Parts with an opacity between 0 and 1 let that
+    /// fraction of the rays through at random (see `Shape::intersect`).
+    pub fn set_opacity(&mut self, opacity: Box<dyn Texture>) {
+        self.opacity = Some(opacity);
     }
 }
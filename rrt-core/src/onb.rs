@@ -0,0 +1,42 @@
+use crate::vec3::Vec3;
+
+/// An orthonormal basis, i.e. three unit vectors that are perpendicular to
+/// each other. Building a basis around a normal gives us a local coordinate
+/// system where the normal is the z axis, which makes the math of materials
+/// much simpler (e.g. the cosine of the angle between a direction and the
+/// normal is just its z coordinate).
+#[derive(Clone, Copy, Debug)]
+pub struct Onb {
+    pub u: Vec3,
+    pub v: Vec3,
+    pub w: Vec3,
+}
+
+impl Onb {
+    /// Builds a basis whose w axis is the given unit vector. The u and v axes
+    /// are picked without any branches (and thus without any discontinuities
+    /// that could show up as seams in the image).
+    /// Reference: Duff et al., "Building an Orthonormal Basis, Revisited"
+    pub fn from_w(w: Vec3) -> Self {
+        let sign = 1f64.copysign(w.z);
+        let a = -1.0 / (sign + w.z);
+        let b = w.x * w.y * a;
+        Onb {
+            u: Vec3::new(1.0 + sign * w.x * w.x * a, sign * b, -sign * w.x),
+            v: Vec3::new(b, sign + w.y * w.y * a, -w.y),
+            w,
+        }
+    }
+
+    /// Converts a vector from world space to the local coordinates of the
+    /// basis.
+    pub fn to_local(&self, a: Vec3) -> Vec3 {
+        Vec3::new(a.dot(self.u), a.dot(self.v), a.dot(self.w))
+    }
+
+    /// Converts a vector from the local coordinates of the basis to world
+    /// space.
+    pub fn to_world(&self, a: Vec3) -> Vec3 {
+        self.u * a.x + self.v * a.y + self.w * a.z
+    }
+}
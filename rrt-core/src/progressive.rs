@@ -0,0 +1,282 @@
+use crate::{
+    camera::Camera,
+    checkpoint::{scene_hash, Checkpoint, CheckpointError},
+    denoise::{denoise_film, DenoiseSettings},
+    engine::{self, CancelToken, Clamp, RenderSettings},
+    film::Film,
+    filter::Filter,
+    io::Buffer,
+    scene::Scene,
+    utils::{mix_seed, random_seed},
+};
+
+/// Renders an image progressively, that is, in multiple passes where each pass
+/// adds more samples to the image. The current estimate of the image can be
+/// read at any point (e.g. to display it), and it only gets less noisy as more
+/// samples are added.
+///
+/// Each pass reseeds the random number generator from `seed` and the pass
+/// number, so a render can be checkpointed and later resumed (see
+/// `checkpoint` and `resume`) with the exact same result as if it had never
+/// been interrupted.
+pub struct ProgressiveRenderer {
+    /// The accumulated samples.
+    film: Film,
+
+    /// Max number of bounces for a given ray.
+    max_bounces: usize,
+
+    /// The reconstruction filter used to combine the samples into pixels.
+    filter: Filter,
+
+    /// Limits on the brightness of a single sample.
+    clamp: Clamp,
+
+    /// Whether to render in spectral mode (see `spectrum`).
+    spectral: bool,
+
+    /// The number of times `add_samples` has been called since the last reset.
+    passes: usize,
+
+    /// The seed the random number generator of each pass is derived from.
+    seed: u64,
+
+    /// If set, `image` returns a denoised estimate of the image.
+    denoise: Option<DenoiseSettings>,
+}
+
+impl ProgressiveRenderer {
+    pub fn new(width: usize, height: usize, max_bounces: usize) -> Self {
+        ProgressiveRenderer::with_seed(width, height, max_bounces, random_seed())
+    }
+
+    pub fn with_seed(width: usize, height: usize, max_bounces: usize, seed: u64) -> Self {
+        ProgressiveRenderer {
+            film: Film::new(width, height),
+            max_bounces,
+            filter: Filter::default(),
+            clamp: Clamp::default(),
+            spectral: false,
+            passes: 0,
+            seed,
+            denoise: None,
+        }
+    }
+
+    /// Resumes a render from a checkpoint. Fails if the checkpoint was not
+    /// rendered from the same scene, camera and max number of bounces. The
+    /// filter, clamping and spectral mode are restored from the checkpoint.
+    pub fn resume(
+        checkpoint: Checkpoint,
+        scene: &Scene,
+        camera: &Camera,
+        max_bounces: usize,
+    ) -> Result<Self, CheckpointError> {
+        if checkpoint.scene_hash != scene_hash(scene, camera, max_bounces) {
+            return Err(CheckpointError::SceneMismatch);
+        }
+        Ok(ProgressiveRenderer {
+            film: checkpoint.film,
+            max_bounces,
+            filter: checkpoint.filter,
+            clamp: checkpoint.clamp,
+            spectral: checkpoint.spectral,
+            passes: checkpoint.passes as usize,
+            seed: checkpoint.seed,
+            denoise: None,
+        })
+    }
+
+    /// Takes a snapshot of the render, which can be saved to disk.
+    pub fn checkpoint(&self, scene: &Scene, camera: &Camera) -> Checkpoint {
+        Checkpoint {
+            film: Film::from_pixels(
+                self.film.pixels().to_vec(),
+                self.film.width,
+                self.film.height,
+            ),
+            filter: self.filter,
+            clamp: self.clamp,
+            spectral: self.spectral,
+            seed: self.seed,
+            passes: self.passes as u64,
+            scene_hash: scene_hash(scene, camera, self.max_bounces),
+        }
+    }
+
+    /// Draws `num_samples` more samples for each pixel and adds them to the
+    /// accumulated image.
+    pub fn add_samples(&mut self, scene: &Scene, camera: &Camera, num_samples: usize) {
+        let settings = RenderSettings {
+            seed: Some(mix_seed(self.seed, self.passes as u64)),
+            filter: self.filter,
+            clamp: self.clamp,
+            spectral: self.spectral,
+            ..RenderSettings::new(num_samples, self.max_bounces)
+        };
+        engine::render_tiles(
+            scene,
+            camera,
+            &mut self.film,
+            &settings,
+            |_, _| {},
+            &CancelToken::new(),
+        )
+        .expect("render can't be cancelled without a handle to the token");
+        self.passes += 1;
+    }
+
+    /// Throws away all the samples drawn so far. This needs to be called
+    /// whenever the scene or camera changes.
+    pub fn reset(&mut self) {
+        self.film.clear();
+        self.passes = 0;
+    }
+
+    /// Sets the max number of bounces for a given ray. Changing this resets
+    /// the image, since the old samples would no longer be valid.
+    pub fn set_max_bounces(&mut self, max_bounces: usize) {
+        if max_bounces != self.max_bounces {
+            self.max_bounces = max_bounces;
+            self.reset();
+        }
+    }
+
+    /// Sets the reconstruction filter (see `filter`). Changing this resets the
+    /// image, since the old samples were weighted with the old filter.
+    pub fn set_filter(&mut self, filter: Filter) {
+        if filter != self.filter {
+            self.filter = filter;
+            self.reset();
+        }
+    }
+
+    /// Sets the limits on the brightness of a single sample (see `Clamp`).
+    /// Changing this resets the image.
+    pub fn set_clamp(&mut self, clamp: Clamp) {
+        if clamp != self.clamp {
+            self.clamp = clamp;
+            self.reset();
+        }
+    }
+
+    /// Sets whether to render in spectral mode (see `spectrum`). Changing this
+    /// resets the image.
+    pub fn set_spectral(&mut self, spectral: bool) {
+        if spectral != self.spectral {
+            self.spectral = spectral;
+            self.reset();
+        }
+    }
+
+    /// Sets whether to also render AOVs (see `aov`). The AOVs are only
+    /// recorded for the samples drawn after they are enabled. AOVs are not
+    /// stored in checkpoints, so the same goes for a resumed render.
+    pub fn set_aovs(&mut self, enabled: bool) {
+        self.film.set_aovs(enabled);
+    }
+
+    /// Sets whether `image` denoises the image (see `denoise`). The denoiser is
+    /// guided by the albedo and normal AOVs, so this also enables AOVs.
+    pub fn set_denoise(&mut self, settings: Option<DenoiseSettings>) {
+        if settings.is_some() {
+            self.set_aovs(true);
+        }
+        self.denoise = settings;
+    }
+
+    /// The current estimate of the image, denoised if denoising is enabled.
+    pub fn image(&self) -> Buffer {
+        match &self.denoise {
+            Some(settings) => denoise_film(&self.film, settings),
+            None => self.film.to_buffer(),
+        }
+    }
+
+    /// A read-only view of the accumulated image.
+    pub fn film(&self) -> &Film {
+        &self.film
+    }
+
+    /// The number of passes rendered since the last reset.
+    pub fn passes(&self) -> usize {
+        self.passes
+    }
+
+    /// The seed the random number generator of each pass is derived from.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{color::Color, material::Lambertian, object::Object, shape::Sphere, vec3::Vec3};
+
+    fn scene() -> Scene {
+        let mut scene = Scene::new();
+        scene.add_object(Object::new(
+            Box::new(Sphere::new(0.5, Vec3::new(0.0, 0.0, -1.0))),
+            Box::new(Lambertian::new(Color::WHITE * 0.5)),
+        ));
+        scene
+    }
+
+    fn average(image: &Buffer) -> f64 {
+        let total: f64 = image.pixels.iter().map(|pixel| pixel.luminance()).sum();
+        total / image.pixels.len() as f64
+    }
+
+    #[test]
+    fn test_passes_accumulate() {
+        let (scene, camera) = (scene(), Camera::default());
+        let mut renderer = ProgressiveRenderer::with_seed(8, 8, 4, 1);
+        renderer.add_samples(&scene, &camera, 2);
+        renderer.add_samples(&scene, &camera, 3);
+        assert_eq!(renderer.passes(), 2);
+        assert!(renderer.film().sample_counts().iter().all(|&n| n == 5));
+    }
+
+    #[test]
+    fn test_reset() {
+        let (scene, camera) = (scene(), Camera::default());
+        let mut renderer = ProgressiveRenderer::with_seed(8, 8, 4, 1);
+        renderer.add_samples(&scene, &camera, 2);
+        renderer.reset();
+        assert_eq!(renderer.passes(), 0);
+        assert!(renderer.film().sample_counts().iter().all(|&n| n == 0));
+        assert!(renderer.image().pixels.iter().all(|p| p.luminance() == 0.0));
+
+        // Changing a setting resets too, but setting the same value doesn't.
+        renderer.add_samples(&scene, &camera, 2);
+        renderer.set_max_bounces(4);
+        assert_eq!(renderer.passes(), 1);
+        renderer.set_max_bounces(5);
+        assert_eq!(renderer.passes(), 0);
+    }
+
+    #[test]
+    fn test_passes_average_to_a_single_render() {
+        let (scene, camera) = (scene(), Camera::default());
+        let mut progressive = ProgressiveRenderer::with_seed(16, 16, 4, 1);
+        progressive.add_samples(&scene, &camera, 32);
+        let first_pass = progressive.image();
+        progressive.add_samples(&scene, &camera, 32);
+        let mut single = ProgressiveRenderer::with_seed(16, 16, 4, 2);
+        single.add_samples(&scene, &camera, 64);
+
+        // Each pass draws different samples, otherwise the second pass
+        // wouldn't change the estimate.
+        let image = progressive.image();
+        assert!(image
+            .pixels
+            .iter()
+            .zip(&first_pass.pixels)
+            .any(|(a, b)| (a.luminance() - b.luminance()).abs() > 1e-6));
+
+        // Both are estimates of the same image from the same number of samples.
+        let (a, b) = (average(&image), average(&single.image()));
+        assert!((a - b).abs() < 0.01 * b, "{a} != {b}");
+    }
+}
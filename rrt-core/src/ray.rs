@@ -8,11 +8,32 @@ pub struct Ray {
 
     /// The direction of the ray.
     pub direction: Vec3,
+
+    /// The rays through the neighboring pixels, if the ray comes from the
+    /// camera (possibly through perfectly smooth bounces), which tell how
+    /// large the footprint of the pixel is where the ray hits (see
+    /// `HitRecord::set_differentials`).
+    pub differentials: Option<Differentials>,
+}
+
+/// Two rays offset from a ray by one pixel to the right (x) and one pixel
+/// down (y) on the image, whose spread is the footprint of a pixel.
+/// Reference: Igehy, "Tracing Ray Differentials"
+#[derive(Clone, Copy, Debug)]
+pub struct Differentials {
+    pub x_origin: Vec3,
+    pub x_direction: Vec3,
+    pub y_origin: Vec3,
+    pub y_direction: Vec3,
 }
 
 impl Ray {
     pub fn new(origin: Vec3, direction: Vec3) -> Self {
-        Ray { origin, direction }
+        Ray {
+            origin,
+            direction,
+            differentials: None,
+        }
     }
 
     /// Returns the point along the line drawn by the ray according to the
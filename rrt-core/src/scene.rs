@@ -1,44 +1,196 @@
-use crate::{color::Color, hitrecord::HitRecord, object::Object, ray::Ray};
+use crate::{
+    color::Color,
+    environment::{Environment, Sky},
+    hitrecord::HitRecord,
+    light::Light,
+    medium::Medium,
+    object::Object,
+    ray::Ray,
+    utils::hash_bytes,
+    vec3::Vec3,
+};
 
 /// A scene is just a list of objects and an environment that determines the
-/// ambient background lighting (if any).
+/// ambient background lighting (if any), optionally filled with fog.
 pub struct Scene {
     /// The objects in the scene.
     objects: Vec<Object>,
 
+    /// The material ID of each object. Objects with identical materials share
+    /// the same ID, which is the index of the material in `materials`.
+    material_ids: Vec<usize>,
+
+    /// The debug representation of each distinct material in the scene.
+    materials: Vec<String>,
+
     /// The background.
-    environment: fn(ray: Ray) -> Color,
+    environment: Box<dyn Environment>,
+
+    /// Point, spot and directional lights.
+    lights: Vec<Box<dyn Light>>,
+
+    /// The medium filling the space between the objects.
+    fog: Option<Box<dyn Medium>>,
 }
 
 impl Scene {
     pub fn new() -> Self {
         Scene {
             objects: Vec::new(),
-            environment: sky_environment,
+            material_ids: Vec::new(),
+            materials: Vec::new(),
+            environment: Box::<Sky>::default(),
+            lights: Vec::new(),
+            fog: None,
         }
     }
 
     pub fn add_object(&mut self, object: Object) {
+        let material = format!("{:?}", object.material);
+        let material_id = match self.materials.iter().position(|m| *m == material) {
+            Some(id) => id,
+            None => {
+                self.materials.push(material);
+                self.materials.len() - 1
+            }
+        };
+        self.material_ids.push(material_id);
         self.objects.push(object);
     }
 
-    pub fn hit_closest_object(&self, ray: Ray) -> Option<(Ray, Color)> {
+    /// Finds the closest object hit by the ray. Returns the ID of the object
+    /// (its index in the order the objects were added) and the hit record,
+    /// whose normal faces the ray.
+    pub fn intersect(&self, ray: Ray) -> Option<(usize, HitRecord)> {
         let mut record = HitRecord::new();
-        let mut closest_obj = None;
-        for object in &self.objects {
-            if object.shape.intersect(ray, &mut record) {
-                closest_obj = Some(object);
+        let mut closest_id = None;
+        for (id, object) in self.objects.iter().enumerate() {
+            if object
+                .shape
+                .intersect(ray, &mut record, object.opacity.as_deref())
+            {
+                closest_id = Some(id);
+            }
+        }
+        record.correct_normal_direction(ray);
+        closest_id.map(|id| (id, record))
+    }
+
+    /// Whether nothing is in the way between `point` and the point at
+    /// `distance` along the (unit) `direction`.
+    pub fn is_visible(&self, point: Vec3, direction: Vec3, distance: f64) -> bool {
+        match self.intersect(Ray::new(point, direction)) {
+            Some((_, record)) => record.t >= distance,
+            None => true,
+        }
+    }
+
+    /// The fraction of the light that makes it from `point` to the point at
+    /// `distance` along the (unit) `direction`, where `medium` is the medium
+    /// around `point`. This is 0 if an object is in the way, except for the
+    /// boundaries of media (see `Object::volume`), which the light crosses
+    /// while being attenuated by the media.
+    pub fn transmittance<'a>(
+        &'a self,
+        mut point: Vec3,
+        direction: Vec3,
+        mut distance: f64,
+        mut medium: Option<&'a dyn Medium>,
+    ) -> Color {
+        let mut transmittance = Color::WHITE;
+        loop {
+            let ray = Ray::new(point, direction);
+            let hit = self
+                .intersect(ray)
+                .filter(|(_, record)| record.t < distance);
+            let t_max = hit.map_or(distance, |(_, record)| record.t);
+            if let Some(medium) = medium {
+                transmittance = transmittance * medium.transmittance(ray, t_max);
             }
+            let (id, record) = match hit {
+                Some(hit) => hit,
+                None => return transmittance,
+            };
+            let object = self.object(id);
+            if !object.material.is_interface() {
+                return Color::BLACK;
+            }
+            medium = self.medium_behind(object, &record);
+            point = record.p;
+            distance -= record.t;
         }
-        closest_obj.map(|obj| obj.material.scatter(record))
+    }
+
+    /// The medium a ray is in after crossing the surface of `object` at the
+    /// hit: the medium inside of the object (or else of its material, see
+    /// `Material::medium`) when entering it, and the fog when leaving it.
+    pub fn medium_behind<'a>(
+        &'a self,
+        object: &'a Object,
+        record: &HitRecord,
+    ) -> Option<&'a dyn Medium> {
+        match record.front_face {
+            true => object
+                .medium
+                .as_deref()
+                .or_else(|| object.material.medium()),
+            false => self.fog(),
+        }
+    }
+
+    /// The object with the given ID.
+    pub fn object(&self, id: usize) -> &Object {
+        &self.objects[id]
+    }
+
+    /// The material ID of the object with the given ID.
+    pub fn material_id(&self, id: usize) -> usize {
+        self.material_ids[id]
+    }
+
+    /// Replaces the background (by default a simple gradient `Sky`).
+    pub fn set_environment(&mut self, environment: Box<dyn Environment>) {
+        self.environment = environment;
+    }
+
+    /// The background.
+    pub fn environment(&self) -> &dyn Environment {
+        self.environment.as_ref()
+    }
+
+    pub fn add_light(&mut self, light: Box<dyn Light>) {
+        self.lights.push(light);
+    }
+
+    pub fn lights(&self) -> &[Box<dyn Light>] {
+        &self.lights
+    }
+
+    /// Fills the space between the objects with a medium. The fog extends to
+    /// infinity, so no light from the environment makes it through it unless
+    /// it is very thin in some color channel.
+    pub fn set_fog(&mut self, fog: Box<dyn Medium>) {
+        self.fog = Some(fog);
+    }
+
+    /// The medium filling the space between the objects, if any.
+    pub fn fog(&self) -> Option<&dyn Medium> {
+        self.fog.as_deref()
     }
 
     pub fn get_environment_light(&self, ray: Ray) -> Color {
-        (self.environment)(ray)
+        self.environment.eval(ray.direction)
     }
-}
 
-fn sky_environment(ray: Ray) -> Color {
-    let t = (ray.direction.unit_vector().y + 1.0) / 2.0;
-    Color::WHITE * (1.0 - t) + Color::SKY_BLUE * t
+    /// A hash of the contents of the scene. Floats don't implement `Hash`, so
+    /// instead we hash the debug representation of the objects, the
+    /// environment, the lights and the fog, which is stable across runs and
+    /// platforms.
+    pub fn content_hash(&self) -> u64 {
+        let contents = format!(
+            "{:?} {:?} {:?} {:?}",
+            self.objects, self.environment, self.lights, self.fog
+        );
+        hash_bytes(contents.as_bytes())
+    }
 }
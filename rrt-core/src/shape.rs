@@ -1,13 +1,103 @@
+mod cuboid;
+mod mesh;
+mod quad;
 mod sphere;
+mod triangle;
 
+pub use cuboid::Cuboid;
+pub use mesh::Mesh;
+pub use quad::Quad;
 pub use sphere::Sphere;
+pub use triangle::Triangle;
 
-use crate::{hitrecord::HitRecord, ray::Ray};
+use std::fmt::Debug;
+
+use crate::{hitrecord::HitRecord, ray::Ray, texture::Texture, utils::random_double};
 
 const T_MIN: f64 = 0.001;
 
 /// A shape is as a mathematical model for which we can compute the intersection
-/// with an array.
-pub trait Shape: Sync {
-    fn intersect(&self, ray: Ray, record: &mut HitRecord) -> bool;
+/// with an array. Shapes implement `Debug` so that the contents of a scene can
+/// be hashed (see `Scene::content_hash`).
+pub trait Shape: Sync + Debug {
+    /// Finds the closest hit of the ray with the shape that is closer than
+    /// `record.t`, and fills in the record with it. Hits where the `opacity`
+    /// texture (if any, see `Object::set_opacity`) cuts the surface out are
+    /// skipped, so that the ray can hit the shape further away.
+    fn intersect(&self, ray: Ray, record: &mut HitRecord, opacity: Option<&dyn Texture>) -> bool;
+}
+
+/// Whether a hit is kept by an opacity texture (of which only the red channel
+/// is used). Partially opaque hits are kept at random with the probability of
+/// their opacity, so on average the right amount of light goes through them
+/// (e.g. for the soft edges of leaves), at the cost of some noise.
+fn is_opaque(opacity: Option<&dyn Texture>, record: &HitRecord) -> bool {
+    let opacity = match opacity {
+        Some(opacity) => opacity.value(record.u, record.v, record.p).r(),
+        None => return true,
+    };
+    opacity >= 1.0 || (opacity > 0.0 && random_double() < opacity)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{color::Color, vec3::Vec3};
+
+    /// Cuts out the half of the surface with u < 0.5.
+    #[derive(Debug)]
+    struct Half;
+
+    impl Texture for Half {
+        fn value(&self, u: f64, _v: f64, _p: Vec3) -> Color {
+            Color::WHITE * (u >= 0.5) as u8 as f64
+        }
+    }
+
+    fn intersect(shape: &dyn Shape, ray: Ray, opacity: Option<&dyn Texture>) -> Option<f64> {
+        let mut record = HitRecord::new();
+        match shape.intersect(ray, &mut record, opacity) {
+            true => Some(record.t),
+            false => None,
+        }
+    }
+
+    #[test]
+    fn test_cut_out_hits_are_skipped() {
+        // The ray enters the sphere where u < 0.5 and leaves it where u > 0.5.
+        let sphere = Sphere::new(1.0, Vec3::ZERO);
+        let ray = Ray::new(Vec3::new(0.0, 0.0, 5.0), -Vec3::Z);
+        assert_eq!(intersect(&sphere, ray, None), Some(4.0));
+        assert_eq!(intersect(&sphere, ray, Some(&Half)), Some(6.0));
+
+        let quad = Quad::new(Vec3::ZERO, Vec3::X, Vec3::Y);
+        let ray = |x| Ray::new(Vec3::new(x, 0.5, 1.0), -Vec3::Z);
+        assert_eq!(intersect(&quad, ray(0.25), Some(&Half)), None);
+        assert_eq!(intersect(&quad, ray(0.75), Some(&Half)), Some(1.0));
+
+        // Two layers of triangles, the one behind twice as wide, so that the
+        // rays hit it where u > 0.5.
+        let positions = vec![
+            Vec3::ZERO,
+            Vec3::X,
+            Vec3::Y,
+            Vec3::new(-1.0, 0.0, -1.0),
+            Vec3::new(1.0, 0.0, -1.0),
+            Vec3::new(-1.0, 1.0, -1.0),
+        ];
+        let mesh = Mesh::new(positions, vec![[0, 1, 2], [3, 4, 5]]);
+        let ray = |x| Ray::new(Vec3::new(x, 0.1, 1.0), -Vec3::Z);
+        assert_eq!(intersect(&mesh, ray(0.25), Some(&Half)), Some(2.0));
+        assert_eq!(intersect(&mesh, ray(0.75), Some(&Half)), Some(1.0));
+    }
+
+    #[test]
+    fn test_partial_opacity() {
+        let quad = Quad::new(Vec3::ZERO, Vec3::X, Vec3::Y);
+        let ray = Ray::new(Vec3::new(0.5, 0.5, 1.0), -Vec3::Z);
+        let hits = (0..100_000)
+            .filter(|_| intersect(&quad, ray, Some(&0.25)).is_some())
+            .count();
+        assert!((hits as f64 / 100_000.0 - 0.25).abs() < 0.01, "{hits}");
+    }
 }
@@ -0,0 +1,112 @@
+use crate::{hitrecord::HitRecord, ray::Ray, texture::Texture, vec3::Vec3};
+
+use super::{is_opaque, Shape, T_MIN};
+
+/// An axis-aligned box.
+#[derive(Debug)]
+pub struct Cuboid {
+    min: Vec3,
+    max: Vec3,
+}
+
+impl Cuboid {
+    /// The box with the given opposite corners.
+    pub fn new(corner: Vec3, opposite: Vec3) -> Self {
+        Cuboid {
+            min: Vec3::new(
+                corner.x.min(opposite.x),
+                corner.y.min(opposite.y),
+                corner.z.min(opposite.z),
+            ),
+            max: Vec3::new(
+                corner.x.max(opposite.x),
+                corner.y.max(opposite.y),
+                corner.z.max(opposite.z),
+            ),
+        }
+    }
+
+    /// Fills in the hit of the ray with the face of the box perpendicular to
+    /// `axis` on the side given by `sign`, at t.
+    fn fill_record(&self, ray: Ray, t: f64, axis: usize, sign: f64, record: &mut HitRecord) {
+        record.t = t;
+        record.p = ray.at(t);
+        let mut normal = [0.0; 3];
+        normal[axis] = sign;
+        record.normal = Vec3::new(normal[0], normal[1], normal[2]);
+        record.shading_normal = record.normal;
+
+        // The texture coordinates go from 0 to 1 across each face, along the
+        // two other axes.
+        let (min, max) = (
+            [self.min.x, self.min.y, self.min.z],
+            [self.max.x, self.max.y, self.max.z],
+        );
+        let p = [record.p.x, record.p.y, record.p.z];
+        let coordinate = |axis: usize| (p[axis] - min[axis]) / (max[axis] - min[axis]);
+        record.u = coordinate((axis + 1) % 3);
+        record.v = coordinate((axis + 2) % 3);
+        let edge = |axis: usize| {
+            let mut edge = [0.0; 3];
+            edge[axis] = max[axis] - min[axis];
+            Vec3::new(edge[0], edge[1], edge[2])
+        };
+        record.tangent = edge((axis + 1) % 3);
+        record.bitangent = edge((axis + 2) % 3);
+    }
+}
+
+impl Shape for Cuboid {
+    fn intersect(&self, ray: Ray, record: &mut HitRecord, opacity: Option<&dyn Texture>) -> bool {
+        // The box is the intersection of 3 slabs, the regions between two
+        // parallel planes (e.g. min.x <= x <= max.x). The ray is inside of a
+        // slab for t between the values at which it crosses its two planes:
+        //
+        //    t = (min.x - p.x) / d.x  and  t = (max.x - p.x) / d.x
+        //
+        // so it is inside of the box between the latest entry into a slab and
+        // the earliest exit out of one. If the ray is parallel to a slab, the
+        // divisions give ±∞, which works out as long as the ray isn't exactly
+        // on one of its planes.
+        let origin = [ray.origin.x, ray.origin.y, ray.origin.z];
+        let direction = [ray.direction.x, ray.direction.y, ray.direction.z];
+        let min = [self.min.x, self.min.y, self.min.z];
+        let max = [self.max.x, self.max.y, self.max.z];
+
+        // The entry and exit, with the axis of the slab they happen at.
+        let (mut entry, mut exit) = ((f64::NEG_INFINITY, 0), (f64::INFINITY, 0));
+        for axis in 0..3 {
+            let t0 = (min[axis] - origin[axis]) / direction[axis];
+            let t1 = (max[axis] - origin[axis]) / direction[axis];
+            if t0.min(t1) > entry.0 {
+                entry = (t0.min(t1), axis);
+            }
+            if t0.max(t1) < exit.0 {
+                exit = (t0.max(t1), axis);
+            }
+        }
+        if entry.0 > exit.0 {
+            return false;
+        }
+
+        // Like for spheres, we want the closest hit in front of the ray, which
+        // is the exit if the ray starts inside of the box (or if the entry is
+        // cut out, see `Object::set_opacity`).
+        let hits = [
+            (entry.0, entry.1, -direction[entry.1].signum()),
+            (exit.0, exit.1, direction[exit.1].signum()),
+        ];
+        for (t, axis, sign) in hits {
+            if t < T_MIN || t > record.t {
+                continue;
+            }
+            let mut hit = *record;
+            self.fill_record(ray, t, axis, sign, &mut hit);
+            if is_opaque(opacity, &hit) {
+                *record = hit;
+                return true;
+            }
+        }
+        false
+    }
+}
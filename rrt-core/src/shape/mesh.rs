@@ -0,0 +1,652 @@
+use std::{collections::HashMap, fmt, io, path::Path};
+
+use crate::{
+    hitrecord::HitRecord, io::invalid_data, ray::Ray, texture::Texture, utils::hash_bytes,
+    vec3::Vec3,
+};
+
+use super::{is_opaque, triangle::intersect_triangle, Shape};
+
+/// The largest number of triangles in a leaf of the bounding volume
+/// hierarchy.
+const LEAF_SIZE: usize = 4;
+
+/// A triangle mesh, e.g. a model loaded from an OBJ file (see `load_obj`).
+/// Its vertices can have normals, which are interpolated across the
+/// triangles for smooth shading, and texture coordinates. Without texture
+/// coordinates, the texture coordinates of a hit are its barycentric
+/// coordinates in its triangle (like for `Triangle`).
+///
+/// The triangles are stored in a bounding volume hierarchy (a tree of boxes,
+/// each of which bounds a group of triangles), so that a ray only has to be
+/// tested against the few triangles whose boxes it goes through.
+pub struct Mesh {
+    positions: Vec<Vec3>,
+    normals: Vec<Vec3>,
+    uvs: Vec<(f64, f64)>,
+    triangles: Vec<[usize; 3]>,
+    nodes: Vec<Node>,
+
+    /// Identifies the contents of the mesh (see `Scene::content_hash`),
+    /// updated whenever they change.
+    hash: u64,
+}
+
+/// A node of the bounding volume hierarchy, stored depth first so that the
+/// first child of an inner node is the next node.
+struct Node {
+    min: Vec3,
+    max: Vec3,
+
+    /// For leaves, the range of their triangles, and for inner nodes, the
+    /// index of their second child (with a count of 0).
+    start: usize,
+    count: usize,
+}
+
+impl Mesh {
+    /// A mesh made of the given triangles, each made of the indices of its 3
+    /// vertices, counterclockwise seen from its front.
+    pub fn new(positions: Vec<Vec3>, triangles: Vec<[usize; 3]>) -> Self {
+        assert!(triangles.iter().flatten().all(|&i| i < positions.len()));
+        let mut mesh = Mesh {
+            positions,
+            normals: Vec::new(),
+            uvs: Vec::new(),
+            triangles,
+            nodes: Vec::new(),
+            hash: 0,
+        };
+        mesh.build_hierarchy();
+        mesh.update_hash();
+        mesh
+    }
+
+    /// Sets the normals of the vertices, for smooth shading.
+    pub fn set_normals(&mut self, normals: Vec<Vec3>) {
+        assert_eq!(normals.len(), self.positions.len());
+        self.normals = normals.into_iter().map(Vec3::unit_vector).collect();
+        self.update_hash();
+    }
+
+    /// Sets the texture coordinates of the vertices.
+    pub fn set_uvs(&mut self, uvs: Vec<(f64, f64)>) {
+        assert_eq!(uvs.len(), self.positions.len());
+        self.uvs = uvs;
+        self.update_hash();
+    }
+
+    /// Sets the normals of the vertices to the average of the normals of the
+    /// triangles around them, weighted by their areas. Vertices at the same
+    /// position get the same normal, even if they are split to have different
+    /// texture coordinates (e.g. along the seams of a texture).
+    pub fn smooth_normals(&mut self) {
+        let mut sums: HashMap<[u64; 3], Vec3> = HashMap::new();
+        for &[a, b, c] in &self.triangles {
+            let (a, b, c) = (self.positions[a], self.positions[b], self.positions[c]);
+            // The cross product is twice the area of the triangle.
+            let normal = (b - a).cross(c - a);
+            for p in [a, b, c] {
+                let sum = sums.entry(position_key(p)).or_insert(Vec3::ZERO);
+                *sum = *sum + normal;
+            }
+        }
+        self.normals = self
+            .positions
+            .iter()
+            .map(|&p| match sums.get(&position_key(p)) {
+                Some(&sum) if sum.length_squared() > 0.0 => sum.unit_vector(),
+                _ => Vec3::Y,
+            })
+            .collect();
+        self.update_hash();
+    }
+
+    /// Loads a mesh from a Wavefront OBJ file (see `from_obj`).
+    pub fn load_obj<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::from_obj(&std::fs::read_to_string(path)?)
+    }
+
+    /// Parses a Wavefront OBJ file. Only the geometry is read (the `v`, `vt`,
+    /// `vn` and `f` statements): all the objects and groups of the file make
+    /// up a single mesh, and materials are ignored. Faces with more than 3
+    /// vertices are split into triangles, so they should be convex. The
+    /// normals and texture coordinates are only used if all the faces have
+    /// them.
+    /// Reference: https://paulbourke.net/dataformats/obj/
+    pub fn from_obj(text: &str) -> io::Result<Self> {
+        let mut positions = Vec::new();
+        let mut uvs = Vec::new();
+        let mut normals = Vec::new();
+        let mut faces: Vec<Vec<[Option<usize>; 3]>> = Vec::new();
+        for line in text.lines() {
+            let mut words = line.split_whitespace();
+            let numbers = |words: std::str::SplitWhitespace| -> io::Result<Vec<f64>> {
+                words
+                    .map(|word| word.parse().map_err(|_| invalid_data("invalid number")))
+                    .collect()
+            };
+            match words.next() {
+                Some("v") => match numbers(words)?[..] {
+                    [x, y, z, ..] => positions.push(Vec3::new(x, y, z)),
+                    _ => return Err(invalid_data("vertex without 3 coordinates")),
+                },
+                Some("vt") => match numbers(words)?[..] {
+                    [u, v, ..] => uvs.push((u, v)),
+                    [u] => uvs.push((u, 0.0)),
+                    _ => return Err(invalid_data("texture coordinates without values")),
+                },
+                Some("vn") => match numbers(words)?[..] {
+                    [x, y, z] => normals.push(Vec3::new(x, y, z)),
+                    _ => return Err(invalid_data("normal without 3 coordinates")),
+                },
+                Some("f") => {
+                    let counts = [positions.len(), uvs.len(), normals.len()];
+                    let face = words
+                        .map(|word| parse_face_vertex(word, counts))
+                        .collect::<io::Result<Vec<_>>>()?;
+                    if face.len() < 3 {
+                        return Err(invalid_data("face with less than 3 vertices"));
+                    }
+                    faces.push(face);
+                }
+                _ => {}
+            }
+        }
+
+        // Vertices are made of a position, texture coordinates and a normal,
+        // which are indexed separately in the file, so each combination used
+        // by a face becomes a vertex of the mesh.
+        let corners = || faces.iter().flatten();
+        let has_uvs = corners().all(|corner| corner[1].is_some());
+        let has_normals = corners().all(|corner| corner[2].is_some());
+        let mut vertices = HashMap::new();
+        let mut mesh_positions = Vec::new();
+        let mut mesh_uvs = Vec::new();
+        let mut mesh_normals = Vec::new();
+        let mut triangles = Vec::new();
+        for face in &faces {
+            let mut indices = Vec::with_capacity(face.len());
+            for &corner in face {
+                let corner = [
+                    corner[0],
+                    corner[1].filter(|_| has_uvs),
+                    corner[2].filter(|_| has_normals),
+                ];
+                let index = *vertices.entry(corner).or_insert_with(|| {
+                    mesh_positions.push(positions[corner[0].unwrap()]);
+                    mesh_uvs.extend(corner[1].map(|i| uvs[i]));
+                    mesh_normals.extend(corner[2].map(|i| normals[i]));
+                    mesh_positions.len() - 1
+                });
+                indices.push(index);
+            }
+            // A fan of triangles around the first vertex.
+            for i in 1..indices.len() - 1 {
+                triangles.push([indices[0], indices[i], indices[i + 1]]);
+            }
+        }
+
+        let mut mesh = Mesh::new(mesh_positions, triangles);
+        if has_uvs {
+            mesh.set_uvs(mesh_uvs);
+        }
+        if has_normals {
+            mesh.set_normals(mesh_normals);
+        }
+        Ok(mesh)
+    }
+
+    /// Splits each triangle into 4, by adding a vertex in the middle of each
+    /// edge. The surface is unchanged (it is not smoothed), but there are more
+    /// vertices to displace (see `displace`).
+    pub fn subdivide(&mut self) {
+        let mut midpoints = HashMap::new();
+        let mut triangles = Vec::with_capacity(self.triangles.len() * 4);
+        for &[a, b, c] in &self.triangles {
+            let mut midpoint = |i: usize, j: usize| {
+                *midpoints.entry((i.min(j), i.max(j))).or_insert_with(|| {
+                    self.positions
+                        .push((self.positions[i] + self.positions[j]) * 0.5);
+                    if !self.uvs.is_empty() {
+                        let ((ui, vi), (uj, vj)) = (self.uvs[i], self.uvs[j]);
+                        self.uvs.push(((ui + uj) * 0.5, (vi + vj) * 0.5));
+                    }
+                    if !self.normals.is_empty() {
+                        let normal = self.normals[i] + self.normals[j];
+                        let normal = match normal.length_squared() > 0.0 {
+                            true => normal.unit_vector(),
+                            false => self.normals[i],
+                        };
+                        self.normals.push(normal);
+                    }
+                    self.positions.len() - 1
+                })
+            };
+            let (ab, bc, ca) = (midpoint(a, b), midpoint(b, c), midpoint(c, a));
+            triangles.extend([[a, ab, ca], [ab, b, bc], [ca, bc, c], [ab, bc, ca]]);
+        }
+        self.triangles = triangles;
+        self.build_hierarchy();
+        self.update_hash();
+    }
+
+    /// Displaces the surface of the mesh along its normals by a height map
+    /// (of which only the red channel is used), scaled by `scale`, after
+    /// subdividing it `subdivisions` times (see `subdivide`). Unlike a bump
+    /// map (see `BumpMap`), this changes the actual geometry, so it also shows
+    /// in the silhouette and the shadows of the mesh, but the details are only
+    /// as fine as the triangles: each subdivision multiplies their number by 4.
+    ///
+    /// The mesh is displaced along its smooth normals (see `smooth_normals`),
+    /// and vertices at the same position (split along hard edges or the seams
+    /// of the texture coordinates) move by the average of their heights, so
+    /// that the mesh doesn't crack. Its normals are recomputed afterwards.
+    /// Meshes without texture coordinates look the height up at (0, 0) for
+    /// every vertex, so only height maps that vary with the position (the
+    /// last argument of `Texture::value`) displace them unevenly.
+    pub fn displace(&mut self, height: &dyn Texture, scale: f64, subdivisions: u32) {
+        for _ in 0..subdivisions {
+            self.subdivide();
+        }
+        self.smooth_normals();
+        let mut heights: HashMap<[u64; 3], (f64, usize)> = HashMap::new();
+        for (i, &p) in self.positions.iter().enumerate() {
+            let (u, v) = self.uvs.get(i).copied().unwrap_or((0.0, 0.0));
+            let (sum, count) = heights.entry(position_key(p)).or_insert((0.0, 0));
+            *sum += height.value(u, v, p).r();
+            *count += 1;
+        }
+        for (p, &normal) in self.positions.iter_mut().zip(&self.normals) {
+            let (sum, count) = heights[&position_key(*p)];
+            *p = *p + normal * (sum / count as f64 * scale);
+        }
+        self.smooth_normals();
+        self.build_hierarchy();
+        self.update_hash();
+    }
+
+    /// The number of triangles of the mesh.
+    pub fn len(&self) -> usize {
+        self.triangles.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.triangles.is_empty()
+    }
+
+    fn corners(&self, triangle: [usize; 3]) -> [Vec3; 3] {
+        triangle.map(|i| self.positions[i])
+    }
+
+    /// Hashes the vertices and triangles, which is too slow to do every time
+    /// the mesh is formatted (see the `Debug` implementation).
+    fn update_hash(&mut self) {
+        let sizes = [self.positions.len(), self.normals.len(), self.uvs.len()];
+        let indices = sizes
+            .into_iter()
+            .chain(self.triangles.iter().flatten().copied());
+        let floats = (self.positions.iter().chain(&self.normals))
+            .flat_map(|p| [p.x, p.y, p.z])
+            .chain(self.uvs.iter().flat_map(|&(u, v)| [u, v]));
+        let bytes: Vec<u8> = indices
+            .flat_map(|i| (i as u64).to_le_bytes())
+            .chain(floats.flat_map(f64::to_le_bytes))
+            .collect();
+        self.hash = hash_bytes(&bytes);
+    }
+
+    /// Sorts the triangles into a new bounding volume hierarchy, splitting
+    /// each node in the middle of its longest axis (by the centers of its
+    /// triangles) until the leaves are small.
+    fn build_hierarchy(&mut self) {
+        self.nodes.clear();
+        if !self.triangles.is_empty() {
+            self.build_node(0, self.triangles.len());
+        }
+    }
+
+    fn build_node(&mut self, start: usize, end: usize) {
+        let (mut min, mut max) = (Vec3::ONE * f64::INFINITY, Vec3::ONE * f64::NEG_INFINITY);
+        for &triangle in &self.triangles[start..end] {
+            for p in self.corners(triangle) {
+                min = Vec3::new(min.x.min(p.x), min.y.min(p.y), min.z.min(p.z));
+                max = Vec3::new(max.x.max(p.x), max.y.max(p.y), max.z.max(p.z));
+            }
+        }
+        let index = self.nodes.len();
+        self.nodes.push(Node {
+            min,
+            max,
+            start,
+            count: end - start,
+        });
+        if end - start <= LEAF_SIZE {
+            return;
+        }
+
+        let size = max - min;
+        let axis = match (size.x, size.y, size.z) {
+            (x, y, z) if x >= y && x >= z => 0,
+            (_, y, z) if y >= z => 1,
+            _ => 2,
+        };
+        let center = |corners: [Vec3; 3]| {
+            let sum = corners[0] + corners[1] + corners[2];
+            [sum.x, sum.y, sum.z][axis]
+        };
+        let positions = &self.positions;
+        let middle = (start + end) / 2;
+        self.triangles[start..end].select_nth_unstable_by(middle - start, |&a, &b| {
+            let a = center(a.map(|i| positions[i]));
+            let b = center(b.map(|i| positions[i]));
+            a.total_cmp(&b)
+        });
+        self.build_node(start, middle);
+        self.nodes[index].start = self.nodes.len();
+        self.nodes[index].count = 0;
+        self.build_node(middle, end);
+    }
+
+    /// Fills in the hit of a ray with a triangle, at the barycentric
+    /// coordinates (u, v).
+    fn fill_record(&self, triangle: [usize; 3], u: f64, v: f64, record: &mut HitRecord) {
+        let [a, b, c] = triangle;
+        let [pa, pb, pc] = self.corners(triangle);
+        let (e1, e2) = (pb - pa, pc - pa);
+        let w = 1.0 - u - v;
+        record.normal = e1.cross(e2).unit_vector();
+        record.shading_normal = match self.normals.is_empty() {
+            true => record.normal,
+            false => {
+                let normal = self.normals[a] * w + self.normals[b] * u + self.normals[c] * v;
+                match normal.length_squared() > 0.0 {
+                    // Interpolated normals should stay on the front of the
+                    // triangle, even if its vertices disagree.
+                    true if normal.dot(record.normal) >= 0.0 => normal.unit_vector(),
+                    true => -normal.unit_vector(),
+                    false => record.normal,
+                }
+            }
+        };
+
+        (record.u, record.v) = (u, v);
+        (record.tangent, record.bitangent) = (e1, e2);
+        if !self.uvs.is_empty() {
+            let [(ua, va), (ub, vb), (uc, vc)] = [self.uvs[a], self.uvs[b], self.uvs[c]];
+            record.u = ua * w + ub * u + uc * v;
+            record.v = va * w + vb * u + vc * v;
+
+            // The edges of the triangle in terms of the tangents:
+            //
+            //    e1 = (ub - ua) * dp/du + (vb - va) * dp/dv
+            //    e2 = (uc - ua) * dp/du + (vc - va) * dp/dv
+            //
+            // a 2x2 linear system for the tangents.
+            let (du1, dv1, du2, dv2) = (ub - ua, vb - va, uc - ua, vc - va);
+            let determinant = du1 * dv2 - dv1 * du2;
+            if determinant.abs() > 1e-12 {
+                record.tangent = (e1 * dv2 - e2 * dv1) / determinant;
+                record.bitangent = (e2 * du1 - e1 * du2) / determinant;
+            }
+        }
+    }
+}
+
+impl Shape for Mesh {
+    fn intersect(&self, ray: Ray, record: &mut HitRecord, opacity: Option<&dyn Texture>) -> bool {
+        if self.nodes.is_empty() {
+            return false;
+        }
+        let inverse = Vec3::new(
+            1.0 / ray.direction.x,
+            1.0 / ray.direction.y,
+            1.0 / ray.direction.z,
+        );
+        let mut closest = None;
+        let mut t_max = record.t;
+        let mut hit = *record;
+        let mut stack = vec![0];
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            if !hits_box(node, ray, inverse, t_max) {
+                continue;
+            }
+            if node.count == 0 {
+                stack.push(node.start);
+                stack.push(index + 1);
+                continue;
+            }
+            for &triangle in &self.triangles[node.start..node.start + node.count] {
+                let (t, u, v) = match intersect_triangle(self.corners(triangle), ray, t_max) {
+                    Some(hit) => hit,
+                    None => continue,
+                };
+                // The texture coordinates of the hit are only needed to
+                // check its opacity.
+                if opacity.is_some() {
+                    hit.p = ray.at(t);
+                    self.fill_record(triangle, u, v, &mut hit);
+                    if !is_opaque(opacity, &hit) {
+                        continue;
+                    }
+                }
+                t_max = t;
+                closest = Some((triangle, u, v));
+            }
+        }
+
+        let (triangle, u, v) = match closest {
+            Some(hit) => hit,
+            None => return false,
+        };
+        record.t = t_max;
+        record.p = ray.at(t_max);
+        self.fill_record(triangle, u, v, record);
+        true
+    }
+}
+
+/// Identifies the vertices at exactly the same position.
+fn position_key(p: Vec3) -> [u64; 3] {
+    [p.x.to_bits(), p.y.to_bits(), p.z.to_bits()]
+}
+
+/// Whether the ray goes through the box of a node before `t_max` (see
+/// `Cuboid::intersect` for the slab method).
+fn hits_box(node: &Node, ray: Ray, inverse: Vec3, t_max: f64) -> bool {
+    let t0 = (node.min - ray.origin) * inverse;
+    let t1 = (node.max - ray.origin) * inverse;
+    // The near and far planes of each slab first, since `clamp` (which clippy
+    // suggests for the chained form) panics when the bounds cross.
+    let near = [t0.x.min(t1.x), t0.y.min(t1.y), t0.z.min(t1.z)];
+    let far = [t0.x.max(t1.x), t0.y.max(t1.y), t0.z.max(t1.z)];
+    let entry = near[0].max(near[1]).max(near[2]);
+    let exit = far[0].min(far[1]).min(far[2]);
+    entry <= exit && exit >= 0.0 && entry <= t_max
+}
+
+/// Parses a vertex of a face, `v`, `v/vt`, `v//vn` or `v/vt/vn`, given the
+/// number of positions, texture coordinates and normals read so far. Indices
+/// start at 1, and negative ones count back from the last ones read.
+fn parse_face_vertex(word: &str, counts: [usize; 3]) -> io::Result<[Option<usize>; 3]> {
+    let mut indices = [None; 3];
+    for (i, index) in word.split('/').enumerate().take(3) {
+        if index.is_empty() {
+            continue;
+        }
+        let index: i64 = index
+            .parse()
+            .map_err(|_| invalid_data("invalid face index"))?;
+        let index = match index {
+            index if index > 0 => index - 1,
+            index => counts[i] as i64 + index,
+        };
+        if index < 0 || index >= counts[i] as i64 {
+            return Err(invalid_data("face index out of range"));
+        }
+        indices[i] = Some(index as usize);
+    }
+    if indices[0].is_none() {
+        return Err(invalid_data("face vertex without a position"));
+    }
+    Ok(indices)
+}
+
+/// Meshes are large, so like images they show a hash of their contents
+/// instead (see `Buffer::content_hash`).
+impl fmt::Debug for Mesh {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Mesh")
+            .field("triangles", &self.triangles.len())
+            .field("hash", &self.hash)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+    use std::collections::HashSet;
+
+    /// A unit square in the xz plane facing up, with texture coordinates.
+    const SQUARE: &str = "
+        v 0 0 0
+        v 1 0 0
+        v 1 0 -1
+        v 0 0 -1
+        vt 0 0
+        vt 1 0
+        vt 1 1
+        vt 0 1
+        vn 0 1 0
+        f 1/1/1 2/2/1 3/3/1 4/4/1
+    ";
+
+    fn hit(mesh: &Mesh, x: f64, z: f64) -> Option<HitRecord> {
+        let ray = Ray::new(Vec3::new(x, 2.0, z), -Vec3::Y);
+        let mut record = HitRecord::new();
+        match mesh.intersect(ray, &mut record, None) {
+            true => Some(record),
+            false => None,
+        }
+    }
+
+    #[test]
+    fn test_obj() {
+        let mesh = Mesh::from_obj(SQUARE).unwrap();
+        assert_eq!(mesh.len(), 2);
+        let record = hit(&mesh, 0.25, -0.75).unwrap();
+        assert!((record.t - 2.0).abs() < 1e-9);
+        assert!((record.normal.y - 1.0).abs() < 1e-9);
+        assert!((record.u - 0.25).abs() < 1e-9 && (record.v - 0.75).abs() < 1e-9);
+        assert!((record.tangent - Vec3::X).length() < 1e-9);
+        assert!((record.bitangent + Vec3::Z).length() < 1e-9);
+        assert!(hit(&mesh, 1.5, -0.5).is_none());
+
+        assert!(Mesh::from_obj("v 0 0 0\nf 1 2 3").is_err());
+        let mesh = Mesh::from_obj("v 0 0 0\nv 1 0 0\nv 0 1 0\nf -3 -2 -1").unwrap();
+        assert_eq!(mesh.len(), 1);
+    }
+
+    #[test]
+    fn test_hierarchy_finds_closest_hit() {
+        // A stack of squares, split into many small triangles.
+        let mut positions = Vec::new();
+        let mut triangles = Vec::new();
+        for layer in 0..10 {
+            let y = layer as f64 * 0.1;
+            let first = positions.len();
+            for i in 0..=10 {
+                for j in 0..=10 {
+                    positions.push(Vec3::new(i as f64 * 0.1, y, -j as f64 * 0.1));
+                }
+            }
+            for i in 0..10 {
+                for j in 0..10 {
+                    let corner = first + i * 11 + j;
+                    triangles.push([corner, corner + 11, corner + 12]);
+                    triangles.push([corner, corner + 12, corner + 1]);
+                }
+            }
+        }
+        let mesh = Mesh::new(positions, triangles);
+        for (x, z) in [(0.05, -0.05), (0.52, -0.31), (0.99, -0.77)] {
+            let record = hit(&mesh, x, z).unwrap();
+            assert!((record.p.y - 0.9).abs() < 1e-9, "{:?}", record.p);
+        }
+    }
+
+    #[test]
+    fn test_displace() {
+        let mut mesh = Mesh::from_obj(SQUARE).unwrap();
+        mesh.subdivide();
+        assert_eq!(mesh.len(), 8);
+
+        // A constant height lifts the whole square.
+        mesh.displace(&0.5, 0.2, 2);
+        assert_eq!(mesh.len(), 128);
+        let record = hit(&mesh, 0.3, -0.6).unwrap();
+        assert!((record.p.y - 0.1).abs() < 1e-9);
+        assert!((record.shading_normal.y - 1.0).abs() < 1e-9);
+    }
+
+    /// A height that increases along u.
+    #[derive(Debug)]
+    struct Ramp;
+
+    impl Texture for Ramp {
+        fn value(&self, u: f64, _v: f64, _p: Vec3) -> Color {
+            Color::WHITE * u
+        }
+    }
+
+    #[test]
+    fn test_displace_keeps_hard_edges_closed() {
+        // Two faces folded along a hard edge, whose vertices are split with
+        // different normals and texture coordinates.
+        let mut mesh = Mesh::from_obj(
+            "
+            v 0 0 0
+            v 1 0 0
+            v 0 0 -1
+            v 1 1 -1
+            vt 0 0
+            vt 1 0
+            vn 0 1 0
+            vn 1 -1 0
+            f 1/1/1 2/1/1 3/1/1
+            f 2/2/2 4/2/2 3/2/2
+        ",
+        )
+        .unwrap();
+        mesh.subdivide();
+        let distinct = |mesh: &Mesh| {
+            let keys: HashSet<_> = mesh.positions.iter().map(|&p| position_key(p)).collect();
+            keys.len()
+        };
+        let before = distinct(&mesh);
+        assert!(before < mesh.positions.len());
+
+        // The height jumps from 0 to 1 across the edge, and so does the
+        // normal, but the split vertices still move together.
+        mesh.displace(&Ramp, 0.1, 0);
+        assert_eq!(distinct(&mesh), before);
+        assert!((mesh.positions[4] - Vec3::new(1.0, 1.0, -1.0)).length() > 0.05);
+    }
+
+    #[test]
+    fn test_debug_hash_follows_contents() {
+        let debug = |mesh: &Mesh| format!("{mesh:?}");
+        let mut mesh = Mesh::from_obj(SQUARE).unwrap();
+        let original = debug(&mesh);
+        assert_eq!(original, debug(&Mesh::from_obj(SQUARE).unwrap()));
+
+        mesh.set_uvs(vec![(0.5, 0.5); 4]);
+        let with_uvs = debug(&mesh);
+        assert_ne!(with_uvs, original);
+        mesh.displace(&1.0, 0.1, 0);
+        assert_ne!(debug(&mesh), with_uvs);
+    }
+}
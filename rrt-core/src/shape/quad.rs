@@ -0,0 +1,70 @@
+use crate::{hitrecord::HitRecord, ray::Ray, texture::Texture, vec3::Vec3};
+
+use super::{is_opaque, Shape, T_MIN};
+
+/// A flat parallelogram, with a corner and two edges from it, e.g. for walls,
+/// or for leaves and decals cut out of a texture (see `Object::set_opacity`).
+/// The texture coordinates go from 0 to 1 along each of the edges, and its
+/// front face is the side from which the first edge turns counterclockwise
+/// towards the second one (like for `Triangle`).
+#[derive(Debug)]
+pub struct Quad {
+    corner: Vec3,
+    u: Vec3,
+    v: Vec3,
+}
+
+impl Quad {
+    pub fn new(corner: Vec3, u: Vec3, v: Vec3) -> Self {
+        Quad { corner, u, v }
+    }
+}
+
+impl Shape for Quad {
+    fn intersect(&self, ray: Ray, record: &mut HitRecord, opacity: Option<&dyn Texture>) -> bool {
+        // The ray hits the plane of the quad, n ⋅ (p - corner) = 0, where:
+        //
+        //    t = n ⋅ (corner - origin) / (n ⋅ d)
+        //
+        // The point p - corner = α * u + β * v is in the quad if α and β are
+        // between 0 and 1. Crossing both sides with v (and with u) isolates
+        // them:
+        //
+        //    α = n ⋅ ((p - corner) × v) / (n ⋅ n)
+        //    β = n ⋅ (u × (p - corner)) / (n ⋅ n)
+        //
+        // Reference: Shirley, "Ray Tracing: The Next Week"
+        let n = self.u.cross(self.v);
+        let denominator = n.dot(ray.direction);
+        // The ray is parallel to the quad.
+        if denominator.abs() < 1e-12 {
+            return false;
+        }
+        let t = n.dot(self.corner - ray.origin) / denominator;
+        if t < T_MIN || t > record.t {
+            return false;
+        }
+        let p = ray.at(t);
+        let w = n / n.dot(n);
+        let alpha = w.dot((p - self.corner).cross(self.v));
+        let beta = w.dot(self.u.cross(p - self.corner));
+        if !(0.0..=1.0).contains(&alpha) || !(0.0..=1.0).contains(&beta) {
+            return false;
+        }
+
+        let mut hit = *record;
+        hit.t = t;
+        hit.p = p;
+        hit.normal = n.unit_vector();
+        hit.shading_normal = hit.normal;
+        hit.u = alpha;
+        hit.v = beta;
+        hit.tangent = self.u;
+        hit.bitangent = self.v;
+        if !is_opaque(opacity, &hit) {
+            return false;
+        }
+        *record = hit;
+        true
+    }
+}
@@ -1,7 +1,10 @@
-use crate::{hitrecord::HitRecord, ray::Ray, vec3::Vec3};
+use std::f64::consts::PI;
 
-use super::{Shape, T_MIN};
+use crate::{hitrecord::HitRecord, ray::Ray, texture::Texture, vec3::Vec3};
 
+use super::{is_opaque, Shape, T_MIN};
+
+#[derive(Debug)]
 pub struct Sphere {
     radius: f64,
     center: Vec3,
@@ -11,10 +14,35 @@ impl Sphere {
     pub fn new(radius: f64, center: Vec3) -> Self {
         Sphere { radius, center }
     }
+
+    /// Fills in the hit of the ray with the sphere at t.
+    fn fill_record(&self, ray: Ray, t: f64, record: &mut HitRecord) {
+        record.t = t;
+        record.p = ray.at(t);
+        record.normal = (record.p - self.center) / self.radius;
+
+        // The texture coordinates are the longitude (u, starting from -x and
+        // going around the y axis) and the latitude (v, from the bottom pole
+        // to the top one) of the hit.
+        let n = record.normal;
+        record.shading_normal = n;
+        record.u = ((-n.z).atan2(n.x) + PI) / (2.0 * PI);
+        record.v = (-n.y).clamp(-1.0, 1.0).acos() / PI;
+
+        // With φ = 2πu - π and θ = πv, the point is at
+        //
+        //    p = c + r * (sin θ cos φ, -cos θ, -sin θ sin φ)
+        //
+        // whose derivatives are the tangents. dp/du vanishes at the poles.
+        let sin_theta = (1.0 - n.y * n.y).max(0.0).sqrt().max(1e-9);
+        record.tangent = Vec3::new(n.z, 0.0, -n.x) * (2.0 * PI * self.radius);
+        record.bitangent = Vec3::new(-n.y * n.x / sin_theta, sin_theta, -n.y * n.z / sin_theta)
+            * (PI * self.radius);
+    }
 }
 
 impl Shape for Sphere {
-    fn intersect(&self, ray: Ray, record: &mut HitRecord) -> bool {
+    fn intersect(&self, ray: Ray, record: &mut HitRecord, opacity: Option<&dyn Texture>) -> bool {
         // The equation for a sphere centered at the origin is:
         //
         //    x² + y² + z² = r²
@@ -97,19 +125,19 @@ impl Shape for Sphere {
         // itself), so this means that (-h - sqrt_d) / a will always be smaller
         // than (-h + sqrt_d) / a, so instead of computing them both and taking
         // the min, we can compute the first and only compute the second if the
-        // first is not positive.
+        // first is not positive (or is cut out, see `Object::set_opacity`).
         let sqrt_d = discriminant.sqrt();
-        let mut t = (-h - sqrt_d) / a;
-        if t < T_MIN || t > record.t {
-            t = (-h + sqrt_d) / a;
+        for t in [(-h - sqrt_d) / a, (-h + sqrt_d) / a] {
             if t < T_MIN || t > record.t {
-                return false;
+                continue;
+            }
+            let mut hit = *record;
+            self.fill_record(ray, t, &mut hit);
+            if is_opaque(opacity, &hit) {
+                *record = hit;
+                return true;
             }
         }
-
-        record.t = t;
-        record.p = ray.at(t);
-        record.normal = (record.p - self.center) / self.radius;
-        true
+        false
     }
 }
@@ -0,0 +1,85 @@
+use crate::{hitrecord::HitRecord, ray::Ray, texture::Texture, vec3::Vec3};
+
+use super::{is_opaque, Shape, T_MIN};
+
+/// A single triangle. Its front face is the side from which the vertices go
+/// counterclockwise, so closed objects can be built out of triangles (e.g. a
+/// prism) as long as all of them are wound the same way.
+#[derive(Debug)]
+pub struct Triangle {
+    a: Vec3,
+    b: Vec3,
+    c: Vec3,
+}
+
+impl Triangle {
+    pub fn new(a: Vec3, b: Vec3, c: Vec3) -> Self {
+        Triangle { a, b, c }
+    }
+}
+
+impl Shape for Triangle {
+    fn intersect(&self, ray: Ray, record: &mut HitRecord, opacity: Option<&dyn Texture>) -> bool {
+        let (t, u, v) = match intersect_triangle([self.a, self.b, self.c], ray, record.t) {
+            Some(hit) => hit,
+            None => return false,
+        };
+        let e1 = self.b - self.a;
+        let e2 = self.c - self.a;
+        let mut hit = *record;
+        hit.t = t;
+        hit.p = ray.at(t);
+        hit.normal = e1.cross(e2).unit_vector();
+        hit.shading_normal = hit.normal;
+        hit.u = u;
+        hit.v = v;
+        hit.tangent = e1;
+        hit.bitangent = e2;
+        if !is_opaque(opacity, &hit) {
+            return false;
+        }
+        *record = hit;
+        true
+    }
+}
+
+/// The distance t along the ray to the triangle and the barycentric
+/// coordinates (u, v) of the hit, if the ray hits it before `t_max`.
+pub(super) fn intersect_triangle(
+    [a, b, c]: [Vec3; 3],
+    ray: Ray,
+    t_max: f64,
+) -> Option<(f64, f64, f64)> {
+    // The points of the triangle are a + u * (b - a) + v * (c - a) with
+    // u, v >= 0 and u + v <= 1 (barycentric coordinates). Setting this
+    // equal to the ray p + t * d gives a 3x3 linear system:
+    //
+    //    -t * d + u * e1 + v * e2 = p - a
+    //
+    // with e1 = b - a and e2 = c - a, which we solve with Cramer's rule,
+    // writing the determinants as triple products.
+    // Reference: Möller and Trumbore, "Fast, Minimum Storage Ray/Triangle Intersection"
+    let e1 = b - a;
+    let e2 = c - a;
+    let h = ray.direction.cross(e2);
+    let determinant = e1.dot(h);
+    // The ray is parallel to the triangle.
+    if determinant.abs() < 1e-12 {
+        return None;
+    }
+    let s = ray.origin - a;
+    let u = s.dot(h) / determinant;
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+    let q = s.cross(e1);
+    let v = ray.direction.dot(q) / determinant;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+    let t = e2.dot(q) / determinant;
+    if t < T_MIN || t > t_max {
+        return None;
+    }
+    Some((t, u, v))
+}
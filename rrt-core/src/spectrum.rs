@@ -0,0 +1,224 @@
+//! Spectral rendering, where paths carry light at a few sampled wavelengths
+//! rather than red, green and blue values. This is what makes effects that
+//! depend on the wavelength of light (e.g. dispersion) possible.
+//!
+//! In spectral mode (see `RenderSettings::spectral`), each path picks a
+//! random "hero" wavelength and two more evenly spaced across the visible
+//! range (hero wavelength sampling), and the three channels of its `Color`s
+//! hold the values at these wavelengths instead of RGB values. The colors of
+//! the scene are still given in RGB, and are converted to spectra (upsampled)
+//! as the path picks them up. At the film, the light at the sampled
+//! wavelengths is converted back to RGB through the CIE XYZ color space.
+//! Reference: Wilkie et al., "Hero Wavelength Spectral Sampling"
+
+use crate::{color::Color, utils::random_double};
+
+/// The range of wavelengths (in nanometers) that are sampled. The eye is
+/// barely sensitive to the light outside of it.
+pub const LAMBDA_MIN: f64 = 380.0;
+pub const LAMBDA_MAX: f64 = 720.0;
+
+/// The wavelengths (in nanometers) carried by a path, one per channel of a
+/// `Color`. The first one is the hero wavelength.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Wavelengths(pub [f64; 3]);
+
+impl Wavelengths {
+    /// Wavelengths that stand for red, green and blue, for wavelength
+    /// dependent materials (see `Material::is_spectral`) in RGB mode.
+    pub const RGB: Wavelengths = Wavelengths([610.0, 550.0, 465.0]);
+
+    /// Picks a uniformly distributed hero wavelength, and two more spaced by
+    /// a third of the range from it (wrapping around). Each of them is
+    /// uniformly distributed, and together they cover the range evenly.
+    pub fn sample() -> Self {
+        let range = LAMBDA_MAX - LAMBDA_MIN;
+        let hero = random_double() * range;
+        let wavelength = |i: usize| LAMBDA_MIN + (hero + i as f64 * range / 3.0) % range;
+        Wavelengths([wavelength(0), wavelength(1), wavelength(2)])
+    }
+
+    pub fn hero(&self) -> f64 {
+        self.0[0]
+    }
+
+    /// Converts an RGB color (a reflectance or the color of a light) to a
+    /// spectrum, and returns its values at the wavelengths. This uses Smits'
+    /// method, which builds the spectrum out of the spectra of white, the
+    /// primaries (red, green and blue) and the secondaries (cyan, magenta and
+    /// yellow): e.g. for r < g < b, the spectrum is
+    ///
+    ///    r * white + (g - r) * cyan + (b - g) * blue
+    ///
+    /// These spectra are smooth, so the resulting spectra are too, and colors
+    /// from 0 to 1 give reflectances from 0 to 1.
+    /// Reference: Smits, "An RGB to Spectrum Conversion for Reflectances"
+    pub fn upsample(&self, color: Color) -> Color {
+        let (r, g, b) = (color.r(), color.g(), color.b());
+        let spectrum = |bin: usize| {
+            if r <= g && r <= b {
+                r * WHITE[bin]
+                    + match g <= b {
+                        true => (g - r) * CYAN[bin] + (b - g) * BLUE[bin],
+                        false => (b - r) * CYAN[bin] + (g - b) * GREEN[bin],
+                    }
+            } else if g <= r && g <= b {
+                g * WHITE[bin]
+                    + match r <= b {
+                        true => (r - g) * MAGENTA[bin] + (b - r) * BLUE[bin],
+                        false => (b - g) * MAGENTA[bin] + (r - b) * RED[bin],
+                    }
+            } else {
+                b * WHITE[bin]
+                    + match r <= g {
+                        true => (r - b) * YELLOW[bin] + (g - r) * GREEN[bin],
+                        false => (g - b) * YELLOW[bin] + (r - g) * RED[bin],
+                    }
+            }
+        };
+        let value = |lambda: f64| spectrum(bin(lambda));
+        Color::new(value(self.0[0]), value(self.0[1]), value(self.0[2]))
+    }
+
+    /// Converts the light carried by a path at the wavelengths to RGB. Each
+    /// wavelength gives an estimate of the XYZ color of the light, by
+    /// weighting its value by the color matching functions and dividing by
+    /// the pdf of the wavelength, which we then convert to linear sRGB. The
+    /// result is white balanced, so that a constant spectrum (like the
+    /// spectrum of white, see `upsample`) gives white.
+    pub fn to_rgb(&self, values: Color) -> Color {
+        let values = [values.r(), values.g(), values.b()];
+        let mut total = Color::BLACK;
+        for (lambda, value) in self.0.into_iter().zip(values) {
+            total = total + srgb_matching(lambda) * value;
+        }
+        let pdf = 1.0 / (LAMBDA_MAX - LAMBDA_MIN);
+        total / (3.0 * pdf) / white_balance()
+    }
+}
+
+/// The spectra Smits' method is built from (see `Wavelengths::upsample`),
+/// sampled in 10 bins that evenly cover the range of wavelengths.
+const WHITE: [f64; 10] = [
+    1.0000, 1.0000, 0.9999, 0.9993, 0.9992, 0.9998, 1.0000, 1.0000, 1.0000, 1.0000,
+];
+const CYAN: [f64; 10] = [
+    0.9710, 0.9426, 1.0007, 1.0007, 1.0007, 1.0007, 0.1564, 0.0000, 0.0000, 0.0000,
+];
+const MAGENTA: [f64; 10] = [
+    1.0000, 1.0000, 0.9685, 0.2229, 0.0000, 0.0458, 0.8369, 1.0000, 1.0000, 0.9959,
+];
+const YELLOW: [f64; 10] = [
+    0.0001, 0.0000, 0.1088, 0.6651, 1.0000, 1.0000, 0.9996, 0.9586, 0.9685, 0.9840,
+];
+const RED: [f64; 10] = [
+    0.1012, 0.0515, 0.0000, 0.0000, 0.0000, 0.0000, 0.8325, 1.0149, 1.0149, 1.0149,
+];
+const GREEN: [f64; 10] = [
+    0.0000, 0.0000, 0.0273, 0.7937, 1.0000, 0.9418, 0.1719, 0.0000, 0.0000, 0.0025,
+];
+const BLUE: [f64; 10] = [
+    1.0000, 1.0000, 0.8916, 0.3323, 0.0000, 0.0000, 0.0003, 0.0369, 0.0483, 0.0496,
+];
+
+/// The bin of the Smits spectra a wavelength falls in.
+fn bin(lambda: f64) -> usize {
+    let x = (lambda - LAMBDA_MIN) / (LAMBDA_MAX - LAMBDA_MIN) * 10.0;
+    (x.max(0.0) as usize).min(9)
+}
+
+/// The integrals of `srgb_matching` over the range of wavelengths, i.e. the
+/// color of a constant spectrum of 1 (computed numerically).
+const WHITE_BALANCE: [f64; 3] = [128.3607, 101.5381, 97.0509];
+
+fn white_balance() -> Color {
+    let [r, g, b] = WHITE_BALANCE;
+    Color::new(r, g, b)
+}
+
+/// The CIE 1931 color matching functions x̄, ȳ and z̄, which give the XYZ
+/// color of light of a single wavelength, approximated by sums of piecewise
+/// Gaussians.
+/// Reference: Wyman et al., "Simple Analytic Approximations to the CIE XYZ Color Matching Functions"
+fn color_matching(lambda: f64) -> [f64; 3] {
+    let g = |mu: f64, sigma_below: f64, sigma_above: f64| {
+        let sigma = if lambda < mu {
+            sigma_below
+        } else {
+            sigma_above
+        };
+        (-0.5 * ((lambda - mu) / sigma).powi(2)).exp()
+    };
+    [
+        1.056 * g(599.8, 37.9, 31.0) + 0.362 * g(442.0, 16.0, 26.7) - 0.065 * g(501.1, 20.4, 26.2),
+        0.821 * g(568.8, 46.9, 40.5) + 0.286 * g(530.9, 16.3, 31.1),
+        1.217 * g(437.0, 11.8, 36.0) + 0.681 * g(459.0, 26.0, 13.8),
+    ]
+}
+
+/// The color matching functions converted to linear sRGB (D65).
+fn srgb_matching(lambda: f64) -> Color {
+    let [x, y, z] = color_matching(lambda);
+    Color::new(
+        3.2406 * x - 1.5372 * y - 0.4986 * z,
+        -0.9689 * x + 1.8758 * y + 0.0415 * z,
+        0.0557 * x - 0.2040 * y + 1.0570 * z,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The RGB color of a spectrum, averaged over many sampled wavelengths.
+    fn round_trip(color: Color) -> Color {
+        let samples = 20_000;
+        let mut total = Color::BLACK;
+        for _ in 0..samples {
+            let wavelengths = Wavelengths::sample();
+            total = total + wavelengths.to_rgb(wavelengths.upsample(color));
+        }
+        total / samples as f64
+    }
+
+    fn assert_close(a: Color, b: Color, tolerance: f64) {
+        let difference = a - b;
+        let error = difference.r().abs() + difference.g().abs() + difference.b().abs();
+        assert!(error < tolerance, "{a:?} != {b:?}");
+    }
+
+    #[test]
+    fn test_white_balance() {
+        let steps = 10_000;
+        let mut total = Color::BLACK;
+        for i in 0..steps {
+            let lambda = LAMBDA_MIN + (i as f64 + 0.5) * (LAMBDA_MAX - LAMBDA_MIN) / steps as f64;
+            total = total + srgb_matching(lambda) * ((LAMBDA_MAX - LAMBDA_MIN) / steps as f64);
+        }
+        assert_close(total / white_balance(), Color::WHITE, 1e-4);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for color in [
+            Color::WHITE,
+            Color::new(0.8, 0.5, 0.4),
+            Color::new(0.2, 0.6, 0.9),
+            Color::new(0.0, 0.3, 0.1),
+        ] {
+            assert_close(round_trip(color), color, 0.05);
+        }
+    }
+
+    #[test]
+    fn test_sample() {
+        for _ in 0..1000 {
+            let Wavelengths(lambdas) = Wavelengths::sample();
+            for i in 0..3 {
+                assert!((LAMBDA_MIN..LAMBDA_MAX).contains(&lambdas[i]));
+                let gap = (lambdas[(i + 1) % 3] - lambdas[i]).rem_euclid(LAMBDA_MAX - LAMBDA_MIN);
+                assert!((gap - (LAMBDA_MAX - LAMBDA_MIN) / 3.0).abs() < 1e-9);
+            }
+        }
+    }
+}
@@ -0,0 +1,88 @@
+mod bump_map;
+mod checker;
+mod image;
+mod normal_map;
+
+pub use bump_map::BumpMap;
+pub use checker::Checker;
+pub use image::{ImageTexture, TextureFilter};
+pub use normal_map::NormalMap;
+
+use std::fmt::Debug;
+
+use crate::{color::Color, hitrecord::HitRecord, vec3::Vec3};
+
+/// A texture varies a parameter of a material (e.g. its color or roughness)
+/// over the surface of an object. Textures are looked up by the texture
+/// coordinates (u, v) of a hit (see `HitRecord`), and can also use its
+/// position `p` (e.g. for procedural textures).
+///
+/// Colors and numbers are textures that are the same everywhere, so any
+/// material parameter can be either a constant or a texture. Textures that
+/// drive a single number (e.g. a roughness) only use the red channel.
+///
+/// Textures implement `Debug` so that the contents of a scene can be hashed
+/// (see `Scene::content_hash`).
+pub trait Texture: Sync + Debug {
+    fn value(&self, u: f64, v: f64, p: Vec3) -> Color;
+
+    /// The value of the texture at a hit, averaged over the footprint of the
+    /// pixel (see `HitRecord::footprint`), which keeps detailed textures from
+    /// aliasing when they are far away. Only image textures are filtered (see
+    /// `ImageTexture`), other textures are looked up at the hit.
+    fn lookup(&self, record: &HitRecord) -> Color {
+        self.value(record.u, record.v, record.p)
+    }
+}
+
+/// The changes of the texture coordinates of a hit from a pixel to the next
+/// one on the image, to the right (x) and down (y). The footprint of the pixel
+/// on the texture is (roughly) the parallelogram spanned by (du/dx, dv/dx) and
+/// (du/dy, dv/dy). An empty footprint means that it is unknown (e.g. after
+/// diffuse bounces), and textures are then looked up at a single point.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Footprint {
+    pub dudx: f64,
+    pub dvdx: f64,
+    pub dudy: f64,
+    pub dvdy: f64,
+}
+
+impl Texture for Color {
+    fn value(&self, _u: f64, _v: f64, _p: Vec3) -> Color {
+        *self
+    }
+}
+
+impl Texture for f64 {
+    fn value(&self, _u: f64, _v: f64, _p: Vec3) -> Color {
+        Color::WHITE * *self
+    }
+}
+
+/// Adds detail to the shading of a surface without changing its geometry, by
+/// perturbing its shading normal with a texture (see `Object::set_normal_mapping`).
+/// The silhouette and the shadows of the object stay smooth, so this is best
+/// for small details (e.g. scratches, bricks or the pores of skin).
+pub trait NormalMapping: Sync + Debug {
+    /// Replaces the shading normal of the hit.
+    fn apply(&self, record: &mut HitRecord);
+}
+
+/// Sets the shading normal of a hit to the given unit vector, facing the same
+/// way as the surface normal. Perturbed normals can face away from the
+/// viewer, which would leave black spots where materials reflect nothing, so
+/// they are bent back towards the viewer just enough to be visible.
+fn set_shading_normal(record: &mut HitRecord, normal: Vec3) {
+    const MIN_COS: f64 = 0.01;
+    let mut normal = match normal.dot(record.normal) < 0.0 {
+        true => -normal,
+        false => normal,
+    };
+    let wo = -record.incoming.unit_vector();
+    let cos = normal.dot(wo);
+    if cos < MIN_COS {
+        normal = (normal + wo * (MIN_COS - cos)).unit_vector();
+    }
+    record.shading_normal = normal;
+}
@@ -0,0 +1,136 @@
+use crate::hitrecord::HitRecord;
+
+use super::{set_shading_normal, NormalMapping, Texture};
+
+/// The step in texture coordinates of the finite differences of the height,
+/// when the footprint of the pixel is unknown.
+const DELTA: f64 = 1e-3;
+
+/// A bump map, a texture of the height of the surface (e.g. a grayscale image
+/// or a procedural texture, of which only the red channel is used). The
+/// shading normal is the normal the surface would have if it were displaced
+/// along its normal by the height:
+///
+///    p'(u, v) = p(u, v) + h(u, v) * n
+///
+/// so its tangents are (ignoring the change of the normal itself):
+///
+///    dp'/du = dp/du + dh/du * n
+///    dp'/dv = dp/dv + dh/dv * n
+///
+/// and the new normal is their cross product. The derivatives of the height
+/// are estimated by finite differences over about half the footprint of the
+/// pixel (see `HitRecord::footprint`), so bump maps should be smooth: the
+/// edges of a checkerboard are either missed or turned into narrow grooves.
+///
+/// Reference: Blinn, "Simulation of Wrinkled Surfaces"
+#[derive(Debug)]
+pub struct BumpMap {
+    height: Box<dyn Texture>,
+
+    /// The height of the surface for a value of 1 of the texture, in world
+    /// units.
+    scale: f64,
+}
+
+impl BumpMap {
+    pub fn new(height: impl Texture + 'static, scale: f64) -> Self {
+        BumpMap {
+            height: Box::new(height),
+            scale,
+        }
+    }
+
+    /// The height at an offset of (du, dv) in texture space from the hit.
+    fn height(&self, record: &HitRecord, du: f64, dv: f64) -> f64 {
+        let mut record = *record;
+        record.u += du;
+        record.v += dv;
+        record.p = record.p + record.tangent * du + record.bitangent * dv;
+        self.height.lookup(&record).r() * self.scale
+    }
+}
+
+impl NormalMapping for BumpMap {
+    fn apply(&self, record: &mut HitRecord) {
+        let footprint = record.footprint;
+        let step = |dx: f64, dy: f64| match 0.5 * (dx.abs() + dy.abs()) {
+            step if step > 0.0 => step,
+            _ => DELTA,
+        };
+        let step_u = step(footprint.dudx, footprint.dudy);
+        let step_v = step(footprint.dvdx, footprint.dvdy);
+        let height = self.height(record, 0.0, 0.0);
+        let du = (self.height(record, step_u, 0.0) - height) / step_u;
+        let dv = (self.height(record, 0.0, step_v) - height) / step_v;
+
+        // The surface is displaced outwards, whichever side it is seen from.
+        let outwards = match record.front_face {
+            true => record.shading_normal,
+            false => -record.shading_normal,
+        };
+        let tangent = record.tangent + outwards * du;
+        let bitangent = record.bitangent + outwards * dv;
+        let normal = tangent.cross(bitangent);
+        if normal.length_squared() > 1e-18 {
+            set_shading_normal(record, normal.unit_vector());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        color::Color,
+        ray::Ray,
+        shape::{Shape, Sphere},
+        vec3::Vec3,
+    };
+
+    /// A height that increases along x.
+    #[derive(Debug)]
+    struct Ramp;
+
+    impl Texture for Ramp {
+        fn value(&self, _u: f64, _v: f64, p: Vec3) -> Color {
+            Color::WHITE * p.x
+        }
+    }
+
+    fn hit(ray: Ray) -> HitRecord {
+        let sphere = Sphere::new(1.0, Vec3::ZERO);
+        let mut record = HitRecord::new();
+        assert!(sphere.intersect(ray, &mut record, None));
+        record.correct_normal_direction(ray);
+        record
+    }
+
+    #[test]
+    fn test_constant_height_keeps_normal() {
+        let ray = Ray::new(Vec3::new(0.3, 0.2, 5.0), -Vec3::Z);
+        let mut record = hit(ray);
+        BumpMap::new(0.5, 1.0).apply(&mut record);
+        assert!((record.shading_normal.dot(record.normal) - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_slope_tilts_normal() {
+        // Seen from the front, the surface rises towards +x, so its normal
+        // leans towards -x, by 45° for a slope of 1.
+        let ray = Ray::new(Vec3::new(0.0, 0.0, 5.0), -Vec3::Z);
+        let mut record = hit(ray);
+        BumpMap::new(Ramp, 1.0).apply(&mut record);
+        let n = record.shading_normal;
+        assert!((n.x + 0.5f64.sqrt()).abs() < 1e-3, "{n:?}");
+        assert!((n.z - 0.5f64.sqrt()).abs() < 1e-3, "{n:?}");
+
+        // Seen from the inside, the bump is a dent, leaning the other way.
+        let ray = Ray::new(Vec3::ZERO, Vec3::Z);
+        let mut record = hit(ray);
+        BumpMap::new(Ramp, 1.0).apply(&mut record);
+        let n = record.shading_normal;
+        assert!((n.x - 0.5f64.sqrt()).abs() < 1e-3, "{n:?}");
+        assert!((n.z + 0.5f64.sqrt()).abs() < 1e-3, "{n:?}");
+    }
+}
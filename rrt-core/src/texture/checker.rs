@@ -0,0 +1,47 @@
+use crate::{color::Color, hitrecord::HitRecord, vec3::Vec3};
+
+use super::Texture;
+
+/// A checkerboard of two textures in texture space, mostly useful to check
+/// the texture coordinates of shapes.
+#[derive(Debug)]
+pub struct Checker {
+    even: Box<dyn Texture>,
+    odd: Box<dyn Texture>,
+
+    /// The number of squares along each of u and v.
+    squares: f64,
+}
+
+impl Checker {
+    pub fn new(even: impl Texture + 'static, odd: impl Texture + 'static, squares: f64) -> Self {
+        Checker {
+            even: Box::new(even),
+            odd: Box::new(odd),
+            squares,
+        }
+    }
+
+    fn is_even(&self, u: f64, v: f64) -> bool {
+        let parity = (u * self.squares).floor() + (v * self.squares).floor();
+        parity.rem_euclid(2.0) == 0.0
+    }
+}
+
+impl Texture for Checker {
+    fn value(&self, u: f64, v: f64, p: Vec3) -> Color {
+        if self.is_even(u, v) {
+            self.even.value(u, v, p)
+        } else {
+            self.odd.value(u, v, p)
+        }
+    }
+
+    fn lookup(&self, record: &HitRecord) -> Color {
+        if self.is_even(record.u, record.v) {
+            self.even.lookup(record)
+        } else {
+            self.odd.lookup(record)
+        }
+    }
+}
@@ -0,0 +1,428 @@
+use std::{fmt, io, path::Path};
+
+use crate::{color::Color, hitrecord::HitRecord, io::Buffer, vec3::Vec3};
+
+use super::{Footprint, Texture};
+
+/// The most the footprint of a pixel can be stretched by EWA filtering, in
+/// the ratio of its longest to its shortest axis. More stretched footprints
+/// are widened, so that a lookup only reads a few hundred pixels.
+const MAX_ANISOTROPY: f64 = 8.0;
+
+/// The most pixels an EWA lookup reads from a level of the mipmap. Lookups
+/// that would read more fall back to bilinear filtering.
+const MAX_PIXELS: f64 = 4096.0;
+
+/// A texture from an image. The image covers the unit square of texture
+/// space, with u going from left to right and v from the bottom to the top,
+/// and repeats outside of it. Pixels are interpolated bilinearly.
+///
+/// The pixels are used as is, so they should be linear (not sRGB).
+///
+/// When a texture is seen from afar, a pixel of the rendered image covers
+/// many pixels of the texture, and looking up a single point of it aliases
+/// (e.g. shimmering or moiré patterns). Lookups at hits (see `Texture::lookup`)
+/// instead average the texture over the footprint of the pixel, with the help
+/// of a mipmap: a pyramid of copies of the image, each half the size of the
+/// previous one, so that any area of the texture is covered by a few pixels of
+/// one of them (see `TextureFilter`).
+/// Reference: Williams, "Pyramidal Parametrics"
+pub struct ImageTexture {
+    /// The levels of the mipmap, from the image itself to a single pixel.
+    levels: Vec<Buffer>,
+
+    filter: TextureFilter,
+
+    /// Identifies the contents of the image (see `Scene::content_hash`).
+    hash: u64,
+}
+
+/// How image textures are filtered over the footprint of a pixel.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TextureFilter {
+    /// No filtering: the image is always looked up at the center of the
+    /// footprint, which is fast but aliases.
+    Bilinear,
+
+    /// Bilinear lookups in the two levels of the mipmap whose pixels are
+    /// closest to the size of the footprint, blended. The footprint is taken
+    /// to be a square as large as its longest side, so surfaces seen at
+    /// grazing angles are blurry.
+    Trilinear,
+
+    /// Elliptically weighted average: a weighted average of the pixels inside
+    /// of the ellipse that best fits the footprint, with gaussian weights,
+    /// in the level of the mipmap whose pixels match its short axis. This is
+    /// sharper than trilinear filtering at grazing angles, and slower.
+    /// Reference: Greene & Heckbert, "Creating Raster Omnimax Images from Multiple Perspective Views Using the Elliptical Weighted Average Filter"
+    Ewa,
+}
+
+impl ImageTexture {
+    pub fn new(image: Buffer) -> Self {
+        assert!(image.width > 0 && image.height > 0);
+        let hash = image.content_hash();
+        let mut levels = vec![image];
+        while let Some(level) = levels.last().and_then(downsample) {
+            levels.push(level);
+        }
+        ImageTexture {
+            levels,
+            filter: TextureFilter::Trilinear,
+            hash,
+        }
+    }
+
+    /// Loads a texture from a Radiance HDR (`.hdr`) or OpenEXR (`.exr`) file
+    /// (see `Buffer::load`).
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Ok(ImageTexture::new(Buffer::load(path)?))
+    }
+
+    /// Sets how the texture is filtered (trilinear by default).
+    pub fn set_filter(&mut self, filter: TextureFilter) {
+        self.filter = filter;
+    }
+
+    /// The pixel at column `j` and row `i` of a level, wrapping around the
+    /// edges.
+    fn pixel(&self, level: usize, j: isize, i: isize) -> Color {
+        let image = &self.levels[level];
+        let (width, height) = (image.width as isize, image.height as isize);
+        let (j, i) = (j.rem_euclid(width), i.rem_euclid(height));
+        image.pixels[(i * width + j) as usize]
+    }
+
+    /// The position of the texture coordinates (u, v) in pixels of a level,
+    /// relative to the centers of the pixels.
+    fn position(&self, level: usize, u: f64, v: f64) -> (f64, f64) {
+        let image = &self.levels[level];
+        let x = u * image.width as f64 - 0.5;
+        let y = (1.0 - v) * image.height as f64 - 0.5;
+        (x, y)
+    }
+
+    fn bilinear(&self, level: usize, u: f64, v: f64) -> Color {
+        let (x, y) = self.position(level, u, v);
+        let (j, i) = (x.floor(), y.floor());
+        let (tx, ty) = (x - j, y - i);
+        let (j, i) = (j as isize, i as isize);
+
+        let top = self.pixel(level, j, i) * (1.0 - tx) + self.pixel(level, j + 1, i) * tx;
+        let bottom =
+            self.pixel(level, j, i + 1) * (1.0 - tx) + self.pixel(level, j + 1, i + 1) * tx;
+        top * (1.0 - ty) + bottom * ty
+    }
+
+    /// Blends two lookups in the levels around the fractional `level`.
+    fn between_levels(&self, level: f64, lookup: impl Fn(usize) -> Color) -> Color {
+        let last = self.levels.len() - 1;
+        let level = level.clamp(0.0, last as f64);
+        let below = (level.floor() as usize).min(last);
+        let t = level - below as f64;
+        match t > 0.0 && below < last {
+            true => lookup(below) * (1.0 - t) + lookup(below + 1) * t,
+            false => lookup(below),
+        }
+    }
+
+    /// The axes of the footprint in pixels of the image (with y going down
+    /// like the rows of the image).
+    fn axes(&self, footprint: &Footprint) -> [(f64, f64); 2] {
+        let (width, height) = (self.levels[0].width as f64, self.levels[0].height as f64);
+        [
+            (footprint.dudx * width, -footprint.dvdx * height),
+            (footprint.dudy * width, -footprint.dvdy * height),
+        ]
+    }
+
+    fn trilinear(&self, u: f64, v: f64, footprint: &Footprint) -> Color {
+        let [(x0, y0), (x1, y1)] = self.axes(footprint);
+        let size = x0.abs().max(y0.abs()).max(x1.abs()).max(y1.abs());
+        if size <= 1.0 {
+            return self.bilinear(0, u, v);
+        }
+        self.between_levels(size.log2(), |level| self.bilinear(level, u, v))
+    }
+
+    fn ewa(&self, u: f64, v: f64, footprint: &Footprint) -> Color {
+        let [mut major, mut minor] = self.axes(footprint);
+        let length = |(x, y): (f64, f64)| (x * x + y * y).sqrt();
+        if length(major) < length(minor) {
+            std::mem::swap(&mut major, &mut minor);
+        }
+        let (major_length, minor_length) = (length(major), length(minor));
+        if major_length <= 1e-12 {
+            return self.bilinear(0, u, v);
+        }
+        // Stretched footprints are widened along their short axis (which is
+        // perpendicular to the long one if it is degenerate).
+        if minor_length * MAX_ANISOTROPY < major_length {
+            let direction = match minor_length > 1e-12 {
+                true => (minor.0 / minor_length, minor.1 / minor_length),
+                false => (-major.1 / major_length, major.0 / major_length),
+            };
+            let scale = major_length / MAX_ANISOTROPY;
+            minor = (direction.0 * scale, direction.1 * scale);
+        }
+        // An ellipse at least as wide as the image covers all of it (many times
+        // over, since the texture repeats), i.e. the single pixel of the last
+        // level of the mipmap.
+        let level = length(minor).max(1.0).log2();
+        let last = self.levels.len() - 1;
+        if level >= last as f64 {
+            return self.levels[last].pixels[0];
+        }
+        self.between_levels(level, |level| self.ewa_level(level, u, v, major, minor))
+    }
+
+    /// The elliptically weighted average of the pixels of a level around
+    /// (u, v), for the ellipse with the given axes (in pixels of the image).
+    fn ewa_level(
+        &self,
+        level: usize,
+        u: f64,
+        v: f64,
+        major: (f64, f64),
+        minor: (f64, f64),
+    ) -> Color {
+        let image = &self.levels[level];
+        let scale_x = image.width as f64 / self.levels[0].width as f64;
+        let scale_y = image.height as f64 / self.levels[0].height as f64;
+        // The texture repeats, so there is no point in an ellipse larger than
+        // the level itself.
+        let size = image.width.max(image.height) as f64;
+        let scale = |(x, y): (f64, f64)| {
+            let (x, y) = (x * scale_x, y * scale_y);
+            let length = (x * x + y * y).sqrt();
+            match length > size {
+                true => (x * size / length, y * size / length),
+                false => (x, y),
+            }
+        };
+        let ((x0, y0), (x1, y1)) = (scale(major), scale(minor));
+
+        // The ellipse is the set of offsets (dx, dy) from the center with:
+        //
+        //    a * dx² + b * dx * dy + c * dy² < 1
+        //
+        // where the coefficients come from the axes (plus one pixel, so that
+        // the ellipse always covers some pixels).
+        let mut a = y0 * y0 + y1 * y1 + 1.0;
+        let mut b = -2.0 * (x0 * y0 + x1 * y1);
+        let mut c = x0 * x0 + x1 * x1 + 1.0;
+        let f = a * c - b * b / 4.0;
+        (a, b, c) = (a / f, b / f, c / f);
+
+        // The bounding box of the ellipse.
+        let determinant = 4.0 * a * c - b * b;
+        let half_width = 2.0 * (determinant * c).sqrt() / determinant;
+        let half_height = 2.0 * (determinant * a).sqrt() / determinant;
+        let (x, y) = self.position(level, u, v);
+        let (j0, j1) = ((x - half_width).ceil(), (x + half_width).floor());
+        let (i0, i1) = ((y - half_height).ceil(), (y + half_height).floor());
+        if (j1 - j0 + 1.0) * (i1 - i0 + 1.0) > MAX_PIXELS {
+            return self.bilinear(level, u, v);
+        }
+
+        // Gaussian weights, shifted to reach 0 at the edge of the ellipse.
+        const ALPHA: f64 = 2.0;
+        let mut sum = Color::BLACK;
+        let mut weights = 0.0;
+        for i in i0 as isize..=i1 as isize {
+            let dy = i as f64 - y;
+            for j in j0 as isize..=j1 as isize {
+                let dx = j as f64 - x;
+                let r2 = a * dx * dx + b * dx * dy + c * dy * dy;
+                if r2 < 1.0 {
+                    let weight = (-ALPHA * r2).exp() - (-ALPHA).exp();
+                    sum = sum + self.pixel(level, j, i) * weight;
+                    weights += weight;
+                }
+            }
+        }
+        match weights > 0.0 {
+            true => sum / weights,
+            false => self.bilinear(level, u, v),
+        }
+    }
+}
+
+/// The next level of a mipmap: half the size of `image` (rounded up), each
+/// pixel the average of the (up to) 2x2 pixels it covers. Returns `None` once
+/// the image is a single pixel.
+fn downsample(image: &Buffer) -> Option<Buffer> {
+    if image.width == 1 && image.height == 1 {
+        return None;
+    }
+    let (width, height) = ((image.width + 1) / 2, (image.height + 1) / 2);
+    let mut pixels = Vec::with_capacity(width * height);
+    for i in 0..height {
+        for j in 0..width {
+            let mut sum = Color::BLACK;
+            let mut count = 0.0;
+            for y in 2 * i..(2 * i + 2).min(image.height) {
+                for x in 2 * j..(2 * j + 2).min(image.width) {
+                    sum = sum + image.pixels[y * image.width + x];
+                    count += 1.0;
+                }
+            }
+            pixels.push(sum / count);
+        }
+    }
+    Some(Buffer::new(pixels, width, height))
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, u: f64, v: f64, _p: Vec3) -> Color {
+        self.bilinear(0, u, v)
+    }
+
+    fn lookup(&self, record: &HitRecord) -> Color {
+        let (u, v, footprint) = (record.u, record.v, &record.footprint);
+        match self.filter {
+            TextureFilter::Bilinear => self.bilinear(0, u, v),
+            TextureFilter::Trilinear => self.trilinear(u, v, footprint),
+            TextureFilter::Ewa => self.ewa(u, v, footprint),
+        }
+    }
+}
+
+/// The image is large, so instead of its pixels we show a hash of them. This is
+/// also what goes into `Scene::content_hash`.
+impl fmt::Debug for ImageTexture {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ImageTexture")
+            .field("width", &self.levels[0].width)
+            .field("height", &self.levels[0].height)
+            .field("filter", &self.filter)
+            .field("hash", &self.hash)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bilinear_and_wrapping() {
+        // A 2x1 image, black on the left and white on the right.
+        let image = Buffer::new(vec![Color::BLACK, Color::WHITE], 2, 1);
+        let texture = ImageTexture::new(image);
+        // At the centers of the pixels we get the pixels themselves.
+        assert_eq!(texture.value(0.25, 0.5, Vec3::ZERO).r(), 0.0);
+        assert_eq!(texture.value(0.75, 0.5, Vec3::ZERO).r(), 1.0);
+        // Halfway between them we get the average, on both sides since the
+        // texture repeats.
+        assert!((texture.value(0.5, 0.5, Vec3::ZERO).r() - 0.5).abs() < 1e-9);
+        assert!((texture.value(1.0, 0.2, Vec3::ZERO).r() - 0.5).abs() < 1e-9);
+        assert!((texture.value(1.75, 0.5, Vec3::ZERO).r() - 1.0).abs() < 1e-9);
+    }
+
+    /// A 64x64 checkerboard of single black and white pixels.
+    fn checkerboard() -> ImageTexture {
+        let pixels = (0..64 * 64)
+            .map(|k| Color::WHITE * ((k / 64 + k % 64) % 2) as f64)
+            .collect();
+        ImageTexture::new(Buffer::new(pixels, 64, 64))
+    }
+
+    fn lookup(texture: &ImageTexture, footprint: Footprint) -> f64 {
+        let mut record = HitRecord::new();
+        // The center of a pixel.
+        (record.u, record.v) = (19.5 / 64.0, 1.0 - 25.5 / 64.0);
+        record.footprint = footprint;
+        texture.lookup(&record).r()
+    }
+
+    #[test]
+    fn test_mipmap() {
+        let texture = checkerboard();
+        assert_eq!(texture.levels.len(), 7);
+        let top = texture.levels.last().unwrap();
+        assert!((top.pixels[0].r() - 0.5).abs() < 1e-9);
+
+        // A 3x5 image goes down to 2x3, 1x2 and 1x1.
+        let image = Buffer::new(vec![Color::WHITE; 15], 3, 5);
+        let sizes: Vec<_> = ImageTexture::new(image)
+            .levels
+            .iter()
+            .map(|level| (level.width, level.height))
+            .collect();
+        assert_eq!(sizes, [(3, 5), (2, 3), (1, 2), (1, 1)]);
+    }
+
+    #[test]
+    fn test_filtering_averages_footprint() {
+        // Without a footprint, we get single pixels.
+        let mut texture = checkerboard();
+        let point = lookup(&texture, Footprint::default());
+        assert!(point == 0.0 || point == 1.0);
+
+        // A footprint of 8x8 pixels averages the checkerboard.
+        let square = Footprint {
+            dudx: 8.0 / 64.0,
+            dvdx: 0.0,
+            dudy: 0.0,
+            dvdy: -8.0 / 64.0,
+        };
+        assert!((lookup(&texture, square) - 0.5).abs() < 1e-9);
+
+        // A footprint stretched along u still averages it with EWA.
+        let stretched = Footprint {
+            dudx: 16.0 / 64.0,
+            dvdx: 0.0,
+            dudy: 0.0,
+            dvdy: -1.0 / 64.0,
+        };
+        texture.set_filter(TextureFilter::Ewa);
+        assert!((lookup(&texture, square) - 0.5).abs() < 0.05);
+        assert!((lookup(&texture, stretched) - 0.5).abs() < 0.05);
+
+        texture.set_filter(TextureFilter::Bilinear);
+        assert_eq!(lookup(&texture, square), point);
+    }
+
+    #[test]
+    fn test_ewa_huge_footprint() {
+        // Footprints far larger than the texture (e.g. at grazing angles) are
+        // as fast as any other and give the average of the texture.
+        let pixels = (0..64 * 64)
+            .map(|k| Color::WHITE * ((k * 37 % 101) as f64 / 100.0))
+            .collect::<Vec<_>>();
+        let average = pixels.iter().map(|c| c.r()).sum::<f64>() / pixels.len() as f64;
+        let mut texture = ImageTexture::new(Buffer::new(pixels, 64, 64));
+        texture.set_filter(TextureFilter::Ewa);
+
+        let start = std::time::Instant::now();
+        for size in [1e3, 1e5, 1e8] {
+            let square = Footprint {
+                dudx: size,
+                dvdx: 0.0,
+                dudy: 0.0,
+                dvdy: -size,
+            };
+            let stretched = Footprint {
+                dudx: size,
+                dvdx: size,
+                dudy: 0.0,
+                dvdy: 0.0,
+            };
+            for footprint in [square, stretched] {
+                assert!((lookup(&texture, footprint) - average).abs() < 1e-9);
+            }
+        }
+        assert!(start.elapsed() < std::time::Duration::from_millis(100));
+
+        // A footprint that nearly covers the texture reads a bounded number of
+        // its pixels, and averages them.
+        let large = Footprint {
+            dudx: 0.9,
+            dvdx: 0.0,
+            dudy: 0.0,
+            dvdy: -0.05,
+        };
+        assert!((lookup(&texture, large) - average).abs() < 0.05);
+    }
+}
@@ -0,0 +1,104 @@
+use crate::hitrecord::HitRecord;
+
+use super::{set_shading_normal, NormalMapping, Texture};
+
+/// A tangent space normal map, the usual blue-ish images that come with
+/// textured models. Each pixel stores a normal in the local frame of the
+/// surface, with x along the tangent (dp/du), y along the bitangent (dp/dv)
+/// and z along the normal, remapped from [-1, 1] to [0, 1]:
+///
+///    n = 2 * color - 1
+///
+/// so a flat surface is (0.5, 0.5, 1). The map must use the same convention as
+/// the texture coordinates (v going up, sometimes called "OpenGL" maps), and
+/// like other textures it should be linear.
+#[derive(Debug)]
+pub struct NormalMap {
+    map: Box<dyn Texture>,
+    strength: f64,
+}
+
+impl NormalMap {
+    pub fn new(map: impl Texture + 'static) -> Self {
+        NormalMap {
+            map: Box::new(map),
+            strength: 1.0,
+        }
+    }
+
+    /// Scales the slopes of the normals, e.g. 0 for a flat surface and 2 for
+    /// bumps twice as deep (1 by default).
+    pub fn set_strength(&mut self, strength: f64) {
+        self.strength = strength;
+    }
+}
+
+impl NormalMapping for NormalMap {
+    fn apply(&self, record: &mut HitRecord) {
+        let color = self.map.lookup(record);
+        let x = (2.0 * color.r() - 1.0) * self.strength;
+        let y = (2.0 * color.g() - 1.0) * self.strength;
+        let z = 2.0 * color.b() - 1.0;
+
+        // The tangent frame, made orthonormal around the shading normal. The
+        // bitangent follows dp/dv even if the texture is mirrored.
+        let n = record.shading_normal;
+        let tangent = record.tangent - n * n.dot(record.tangent);
+        let tangent = match tangent.length_squared() > 1e-18 {
+            true => tangent.unit_vector(),
+            false => record.frame().u,
+        };
+        let mut bitangent = n.cross(tangent);
+        if bitangent.dot(record.bitangent) < 0.0 {
+            bitangent = -bitangent;
+        }
+
+        let normal = tangent * x + bitangent * y + n * z;
+        if normal.length_squared() > 0.0 {
+            set_shading_normal(record, normal.unit_vector());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        color::Color,
+        ray::Ray,
+        shape::{Cuboid, Shape},
+        vec3::Vec3,
+    };
+
+    /// The hit of a ray going straight down onto the top of a box.
+    fn record() -> HitRecord {
+        let cuboid = Cuboid::new(Vec3::new(-1.0, -1.0, -1.0), Vec3::ZERO);
+        let ray = Ray::new(Vec3::new(-0.5, 1.0, -0.5), -Vec3::Y);
+        let mut record = HitRecord::new();
+        assert!(cuboid.intersect(ray, &mut record, None));
+        record.correct_normal_direction(ray);
+        record
+    }
+
+    #[test]
+    fn test_flat_map_keeps_normal() {
+        let mut record = record();
+        NormalMap::new(Color::new(0.5, 0.5, 1.0)).apply(&mut record);
+        let n = record.shading_normal;
+        assert!((n.y - 1.0).abs() < 1e-9, "{n:?}");
+    }
+
+    #[test]
+    fn test_normal_tilts_along_tangent() {
+        let mut record = record();
+        let tangent = record.tangent.unit_vector();
+        NormalMap::new(Color::new(1.0, 0.5, 1.0)).apply(&mut record);
+        let n = record.shading_normal;
+        assert!((n.length() - 1.0).abs() < 1e-9);
+        assert!((n.dot(tangent) - 0.5f64.sqrt()).abs() < 1e-9, "{n:?}");
+        assert!((n.y - 0.5f64.sqrt()).abs() < 1e-9, "{n:?}");
+
+        // The surface normal is left alone.
+        assert!((record.normal.y - 1.0).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,154 @@
+/// A rectangular region of the image that gets rendered as a single unit of
+/// work (also known as a bucket).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Tile {
+    /// The row of the top left pixel of the tile.
+    pub row: usize,
+
+    /// The column of the top left pixel of the tile.
+    pub col: usize,
+
+    /// Width of the tile in pixels.
+    pub width: usize,
+
+    /// Height of the tile in pixels.
+    pub height: usize,
+}
+
+impl Tile {
+    /// Iterates over the (row, column) of each pixel in the tile, row by row.
+    pub fn pixels(&self) -> impl Iterator<Item = (usize, usize)> {
+        let tile = *self;
+        (tile.row..tile.row + tile.height)
+            .flat_map(move |i| (tile.col..tile.col + tile.width).map(move |j| (i, j)))
+    }
+}
+
+/// The order in which tiles are rendered.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TileOrder {
+    /// Left-to-right, then top-to-bottom.
+    Scanline,
+
+    /// Starting from the center of the image and spiraling outwards, which is
+    /// nice for previews since the subject is usually in the center.
+    Spiral,
+
+    /// Along a Hilbert curve. Consecutive tiles are mostly neighbours (always
+    /// for a square grid of tiles whose size is a power of 2), which keeps the
+    /// part of the scene being rendered (and thus the memory being accessed)
+    /// coherent.
+    Hilbert,
+}
+
+/// Splits an image into tiles of (at most) `tile_size` x `tile_size` pixels,
+/// sorted according to `order`. Tiles on the right and bottom edges of the
+/// image are cropped if the image size is not a multiple of `tile_size`.
+pub fn generate_tiles(
+    width: usize,
+    height: usize,
+    tile_size: usize,
+    order: TileOrder,
+) -> Vec<Tile> {
+    assert!(tile_size > 0);
+    let num_cols = (width + tile_size - 1) / tile_size;
+    let num_rows = (height + tile_size - 1) / tile_size;
+
+    // Coordinates (in units of tiles) of each tile in scanline order.
+    let mut coords: Vec<(usize, usize)> = (0..num_rows)
+        .flat_map(|y| (0..num_cols).map(move |x| (x, y)))
+        .collect();
+
+    match order {
+        TileOrder::Scanline => {}
+        TileOrder::Spiral => {
+            // Sort the tiles by which "ring" around the center tile they are
+            // in, and then by their angle around the center within a ring.
+            let center_x = (num_cols as f64 - 1.0) / 2.0;
+            let center_y = (num_rows as f64 - 1.0) / 2.0;
+            let key = |&(x, y): &(usize, usize)| {
+                let (dx, dy) = (x as f64 - center_x, y as f64 - center_y);
+                (dx.abs().max(dy.abs()), dy.atan2(dx))
+            };
+            coords.sort_by(|a, b| key(a).partial_cmp(&key(b)).unwrap());
+        }
+        TileOrder::Hilbert => {
+            let n = num_cols.max(num_rows).next_power_of_two();
+            coords.sort_by_key(|&(x, y)| hilbert_index(n, x, y));
+        }
+    }
+
+    coords
+        .into_iter()
+        .map(|(x, y)| Tile {
+            row: y * tile_size,
+            col: x * tile_size,
+            width: tile_size.min(width - x * tile_size),
+            height: tile_size.min(height - y * tile_size),
+        })
+        .collect()
+}
+
+/// Computes the distance along a Hilbert curve that fills an `n` x `n` grid
+/// (where `n` is a power of 2) of the cell at (`x`, `y`).
+/// Reference: https://en.wikipedia.org/wiki/Hilbert_curve#Applications_and_mapping_algorithms
+fn hilbert_index(n: usize, mut x: usize, mut y: usize) -> usize {
+    let mut d = 0;
+    let mut s = n / 2;
+    while s > 0 {
+        let rx = (x & s > 0) as usize;
+        let ry = (y & s > 0) as usize;
+        d += s * s * ((3 * rx) ^ ry);
+
+        // Rotate the quadrant so that the curve inside of it has the right
+        // orientation.
+        if ry == 0 {
+            if rx == 1 {
+                x = n - 1 - x;
+                y = n - 1 - y;
+            }
+            std::mem::swap(&mut x, &mut y);
+        }
+        s /= 2;
+    }
+    d
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_covers_image(tiles: &[Tile], width: usize, height: usize) {
+        let mut hits = vec![0; width * height];
+        for tile in tiles {
+            for (i, j) in tile.pixels() {
+                hits[i * width + j] += 1;
+            }
+        }
+        assert!(hits.iter().all(|&n| n == 1));
+    }
+
+    #[test]
+    fn test_tiles_cover_image() {
+        for order in [TileOrder::Scanline, TileOrder::Spiral, TileOrder::Hilbert] {
+            assert_covers_image(&generate_tiles(100, 37, 16, order), 100, 37);
+            assert_covers_image(&generate_tiles(64, 64, 16, order), 64, 64);
+            assert_covers_image(&generate_tiles(5, 3, 16, order), 5, 3);
+        }
+    }
+
+    #[test]
+    fn test_hilbert_tiles_are_neighbours() {
+        let tiles = generate_tiles(128, 128, 16, TileOrder::Hilbert);
+        for pair in tiles.windows(2) {
+            let dist = pair[0].row.abs_diff(pair[1].row) + pair[0].col.abs_diff(pair[1].col);
+            assert_eq!(dist, 16);
+        }
+    }
+
+    #[test]
+    fn test_spiral_starts_at_center() {
+        let tiles = generate_tiles(90, 90, 30, TileOrder::Spiral);
+        assert_eq!((tiles[0].row, tiles[0].col), (30, 30));
+    }
+}
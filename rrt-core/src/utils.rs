@@ -1,5 +1,41 @@
-use crate::vec3::Vec3;
-use rand::Rng;
+use std::cell::RefCell;
+
+use crate::{onb::Onb, vec3::Vec3};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+thread_local! {
+    /// The random number generator used for rendering. Each thread has its own
+    /// generator, which can be reseeded (see `seed_rng`) to make a render
+    /// reproducible.
+    static RNG: RefCell<StdRng> = RefCell::new(StdRng::from_entropy());
+}
+
+/// Reseeds the random number generator of the current thread.
+pub fn seed_rng(seed: u64) {
+    RNG.with(|rng| *rng.borrow_mut() = StdRng::seed_from_u64(seed));
+}
+
+/// Combines two values into a new well distributed seed (using the finalizer
+/// of splitmix64), e.g. to derive the seed of a tile from the seed of a pass.
+pub fn mix_seed(seed: u64, value: u64) -> u64 {
+    let mut z = seed ^ value.wrapping_add(0x9e3779b97f4a7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+/// Returns a random seed that is not tied to the state of the render random
+/// number generator.
+pub fn random_seed() -> u64 {
+    rand::random()
+}
+
+/// A stable (across platforms and runs) 64-bit FNV-1a hash of some bytes.
+pub fn hash_bytes(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
 
 pub fn random_in_unit_sphere() -> Vec3 {
     loop {
@@ -11,5 +47,41 @@ pub fn random_in_unit_sphere() -> Vec3 {
 }
 
 pub fn random_double() -> f64 {
-    rand::thread_rng().gen_range(0.0..1.0)
+    random_range(0.0, 1.0)
+}
+
+pub fn random_range(min: f64, max: f64) -> f64 {
+    RNG.with(|rng| rng.borrow_mut().gen_range(min..max))
+}
+
+/// A uniformly random direction (a point on the unit sphere).
+pub fn random_unit_vector() -> Vec3 {
+    let z = random_range(-1.0, 1.0);
+    let phi = random_range(0.0, 2.0 * std::f64::consts::PI);
+    let r = (1.0 - z * z).sqrt();
+    Vec3::new(r * phi.cos(), r * phi.sin(), z)
+}
+
+/// A random direction on the hemisphere around +z, picked proportionally to
+/// the cosine of its angle with +z (i.e. its z coordinate), so the pdf is
+/// cos θ / π. We pick a uniform point on the unit disk and project it up onto
+/// the hemisphere (Malley's method).
+pub fn random_cosine_direction() -> Vec3 {
+    let r = random_double().sqrt();
+    let phi = random_range(0.0, 2.0 * std::f64::consts::PI);
+    let (x, y) = (r * phi.cos(), r * phi.sin());
+    Vec3::new(x, y, (1.0 - x * x - y * y).max(0.0).sqrt())
+}
+
+/// A uniformly random direction within the cone around `axis` (a unit vector)
+/// whose half-angle has the cosine `cos_max`.
+pub fn random_in_cone(axis: Vec3, cos_max: f64) -> Vec3 {
+    let cos_theta = 1.0 - random_double() * (1.0 - cos_max);
+    let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+    let phi = random_range(0.0, 2.0 * std::f64::consts::PI);
+    Onb::from_w(axis).to_world(Vec3::new(
+        sin_theta * phi.cos(),
+        sin_theta * phi.sin(),
+        cos_theta,
+    ))
 }
@@ -1,6 +1,6 @@
 use std::ops::{Add, Div, Mul, Neg, Sub};
 
-use rand::Rng;
+use crate::utils::random_range;
 
 /// A data structure to represent a 3D vector with a x, y, and z component.
 /// Vec3 supports common arithmetic such as scalar/vector multiplication,
@@ -46,9 +46,9 @@ impl Vec3 {
 
     pub fn random(min: f64, max: f64) -> Vec3 {
         Vec3::new(
-            rand::thread_rng().gen_range(min..max),
-            rand::thread_rng().gen_range(min..max),
-            rand::thread_rng().gen_range(min..max),
+            random_range(min, max),
+            random_range(min, max),
+            random_range(min, max),
         )
     }
 
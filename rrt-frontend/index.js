@@ -16,6 +16,7 @@ let redraw = true;
 let totalRaysDrawn = 0;
 
 const fovInput = document.getElementById("fovInput");
+const denoiseInput = document.getElementById("denoiseInput");
 const inputForm = document.getElementById("inputForm");
 const timeOutput = document.getElementById("timeOutput");
 
@@ -76,6 +77,7 @@ async function renderLoop(image) {
 
       await image.clear();
       await image.set_camera(xRot, yRot, fov);
+      await image.set_denoise(denoiseInput.checked);
     }
     else if (n <= maxSamples) {
       // render the image and compute the time it took
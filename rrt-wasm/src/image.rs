@@ -1,7 +1,7 @@
 use js_sys::{Uint8ClampedArray, WebAssembly};
 use rrt_core::{
-    camera::Camera, color::Color, engine, material::Lambertian, object::Object, scene::Scene,
-    shape::Sphere, vec3::Vec3,
+    camera::Camera, color::Color, denoise::DenoiseSettings, material::Lambertian, object::Object,
+    progressive::ProgressiveRenderer, scene::Scene, shape::Sphere, vec3::Vec3,
 };
 use wasm_bindgen::prelude::*;
 
@@ -35,17 +35,8 @@ pub struct Image {
     /// RGBA array containing the actual pixel values we'd like to show.
     arr: Vec<u8>,
 
-    /// Array containing the raw color calculations per pixel (flattened row-wise).
-    buf: Vec<Color>,
-
-    /// Width of the rendered image.
-    width: usize,
-
-    /// Height of the rendered image.
-    height: usize,
-
-    /// Keeps track of the total number of samples we have thus far drawn.
-    total_samples: usize,
+    /// Accumulates the samples we have thus far drawn.
+    renderer: ProgressiveRenderer,
 
     /// The scene. The "center" of the scene should be the origin (which is
     /// what the camera will rotate around).
@@ -63,10 +54,7 @@ impl Image {
     pub fn new(width: usize, height: usize) -> Self {
         Image {
             arr: vec![255; width * height * 4],
-            buf: vec![Color::BLACK; width * height],
-            width: width,
-            height: height,
-            total_samples: 0,
+            renderer: ProgressiveRenderer::new(width, height, 0),
             scene: default_scene(),
             camera: Camera::default(),
             camera_distance: 1.0,
@@ -86,35 +74,26 @@ impl Image {
     }
 
     pub fn render(&mut self, num_samples: usize, max_bounces: usize) {
-        self.total_samples += num_samples;
+        self.renderer.set_max_bounces(max_bounces);
+        self.renderer
+            .add_samples(&self.scene, &self.camera, num_samples);
 
-        let pixels = engine::render(
-            &self.scene,
-            &self.camera,
-            self.width,
-            self.height,
-            num_samples,
-            max_bounces,
-        )
-        .pixels;
-
-        for (i, color) in pixels.into_iter().enumerate() {
-            // TODO: we need to multiply color by num_samples since render
-            // takes the average for us, we should probably have a separate
-            // rendering function to return an iterator that returns the raw sum
-            // as well as being an iterator means we don't need to collect to
-            // a vector first
-            self.buf[i] = self.buf[i] + color * num_samples as f64;
-            let (r, g, b) = (self.buf[i] / self.total_samples as f64).to_u8();
+        for (i, color) in self.renderer.image().pixels.iter().enumerate() {
+            let (r, g, b) = color.to_u8();
             self.arr[i * 4 + 0] = r;
             self.arr[i * 4 + 1] = g;
             self.arr[i * 4 + 2] = b;
         }
     }
 
+    /// Sets whether the displayed image is denoised.
+    pub fn set_denoise(&mut self, enabled: bool) {
+        self.renderer
+            .set_denoise(enabled.then(DenoiseSettings::default));
+    }
+
     pub fn clear(&mut self) {
-        self.buf.fill(Color::BLACK);
-        self.total_samples = 0;
+        self.renderer.reset();
     }
 
     pub fn get_image_so_far(&self) -> Uint8ClampedArray {